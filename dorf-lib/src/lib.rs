@@ -9,7 +9,15 @@ pub mod prelude {
     pub use glam::*;
 }
 
+use std::path::{Path, PathBuf};
+
 use crate::prelude::*;
+use crate::script::content::{Content, ContentError, DEFAULT_DATA_DIR};
+use crate::script::local_map::MapSettings;
+use crate::script::map_image::{import_surface, ImportedSurface, MapImageError};
+use crate::script::save::{PendingLoad, SaveError, SaveFile};
+use crate::script::terrain::TerrainSettings;
+use crate::script::text_map::{TextMap, TextMapError};
 use bevy::{app::ScheduleRunnerSettings, utils::Duration};
 
 fn configure_logging() {
@@ -34,18 +42,132 @@ fn configure_logging() {
     log::info!("Log initialized.")
 }
 
-pub fn app_main() {
+/// Settings picked at launch, e.g. from the command line.
+#[derive(Debug, Default, Clone)]
+pub struct AppSettings {
+    /// Seed for the simulation's [`SimRng`], picked at random if not given.
+    pub seed: Option<u64>,
+    pub map: MapSettings,
+    /// Directory content is loaded from. Defaults to [`DEFAULT_DATA_DIR`],
+    /// or the built-in content if there isn't one.
+    pub data_dir: Option<PathBuf>,
+    /// Image to read the map's surface from instead of generating it, see
+    /// [`import_surface`]. The map is resized to fit it.
+    pub map_image: Option<PathBuf>,
+    /// Text map to use instead of generating the map, see [`TextMap`]. The
+    /// map is resized to fit it.
+    pub map_file: Option<PathBuf>,
+    /// Save to load once started, see [`SaveFile`]. Its seed and map are used
+    /// instead of `seed` and `map`, so it can't be given with `map_image` or
+    /// `map_file`.
+    pub load: Option<PathBuf>,
+}
+
+/// Reasons the app can fail to start.
+#[derive(Debug, thiserror::Error)]
+pub enum AppError {
+    #[error(transparent)]
+    Content(#[from] ContentError),
+    #[error(transparent)]
+    MapImage(#[from] MapImageError),
+    #[error(transparent)]
+    Save(#[from] SaveError),
+    #[error(transparent)]
+    TextMap(#[from] TextMapError),
+}
+
+/// Load the content picked by `settings`, see [`AppSettings::data_dir`].
+fn load_content(settings: &AppSettings) -> Result<Content, ContentError> {
+    match &settings.data_dir {
+        Some(dir) => Content::load(dir),
+        None if Path::new(DEFAULT_DATA_DIR).is_dir() => Content::load(Path::new(DEFAULT_DATA_DIR)),
+        None => {
+            log::warn!("No {} directory, using built-in content", DEFAULT_DATA_DIR);
+            Ok(Content::default())
+        }
+    }
+}
+
+/// Run the app until it exits. Fails before starting if the content, map
+/// image, text map or save can't be loaded.
+pub fn app_main(mut settings: AppSettings) -> Result<(), AppError> {
     configure_logging();
 
-    log::info!("Initializing App");
-    App::new()
-        .insert_resource(ScheduleRunnerSettings::run_loop(Duration::from_secs_f64(
-            1.0 / 60.0,
-        )))
-        .add_plugins(MinimalPlugins)
-        .add_plugin(terminal::TerminalPlugin::default())
-        .add_plugin(script::ScriptPlugin::default())
-        .add_plugin(OnExitPlugin {}) // Note: Must be last in order to handle AppExit
-        .run();
+    let content = load_content(&settings).map_err(|e| {
+        log::error!("Failed to load content: {}", e);
+        e
+    })?;
+    let imported = match &settings.map_image {
+        Some(path) => {
+            let surface =
+                import_surface(path, settings.map.origin, &content.palette).map_err(|e| {
+                    log::error!("Failed to import map: {}", e);
+                    e
+                })?;
+            settings.map.size = surface.rect().size().as_uvec2();
+            Some(ImportedSurface(surface.into()))
+        }
+        None => None,
+    };
+    let text_map = match &settings.map_file {
+        Some(path) => {
+            let text_map = TextMap::read(path, settings.map.origin).map_err(|e| {
+                log::error!("Failed to read text map: {}", e);
+                e
+            })?;
+            settings.map.size = text_map.size();
+            settings.map.depth = text_map.depth();
+            Some(text_map)
+        }
+        None => None,
+    };
+    let save = match &settings.load {
+        Some(path) => {
+            let save = SaveFile::read(path).map_err(|e| {
+                log::error!("Failed to load save: {}", e);
+                e
+            })?;
+            log::info!("Loading {}", path.display());
+            settings.map = save.map.clone();
+            Some(save)
+        }
+        None => None,
+    };
+    let rng = match &save {
+        Some(save) => SimRng::from_state(save.rng.clone()),
+        None => settings.seed.map_or_else(SimRng::from_entropy, SimRng::new),
+    };
+    // Always log the seed so any run can be reproduced with `--seed`.
+    log::info!("Initializing App with seed {}", rng.seed());
+    let mut app = App::new();
+    if let Some(imported) = imported {
+        app.insert_resource(imported);
+    }
+    if let Some(text_map) = text_map {
+        app.insert_resource(text_map);
+    }
+    if let Some(save) = save {
+        app.insert_resource(PendingLoad(save));
+    }
+    app.insert_resource(ScheduleRunnerSettings::run_loop(Duration::from_secs_f64(
+        1.0 / 60.0,
+    )))
+    .insert_resource(rng)
+    .insert_resource(settings.map)
+    .insert_resource(TerrainSettings {
+        biomes: content.biomes,
+        ..default()
+    })
+    .insert_resource(content.tiles)
+    .insert_resource(content.creatures)
+    .insert_resource(content.items)
+    .insert_resource(content.palette)
+    .insert_resource(content.prefabs)
+    .add_plugins(MinimalPlugins)
+    .add_plugin(terminal::TerminalPlugin::default())
+    .add_plugin(script::ScriptPlugin::default())
+    .add_plugin(OnExitPlugin {}) // Note: Must be last in order to handle AppExit
+    .run();
     log::info!("Exited app");
+    Ok(())
 }
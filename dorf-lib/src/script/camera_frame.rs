@@ -83,7 +83,7 @@ fn handle_camera_resized(
     mut event: EventReader<CameraResized>,
 ) {
     if let Some(event) = event.iter().last() {
-        resize_walls(&camera.dim(), &mut walls)
+        resize_walls(camera.dim(), &mut walls)
     }
 }
 fn resize_walls(cam_size: &UVec2, walls: &mut Query<(&mut Transform2D, &CameraSide)>) {
@@ -105,8 +105,13 @@ fn move_camera(direction: Vec2, camera: &mut ResMut<TerminalCamera2D>) {
 
 fn handle_camera_movement_keys(
     mut input: EventReader<KeyboardInput>,
+    focus: Res<InputFocus>,
     mut camera: ResMut<TerminalCamera2D>,
 ) {
+    if *focus != InputFocus::World {
+        input.clear();
+        return;
+    }
     for e in input.iter() {
         if e.state != ButtonState::Pressed {
             continue;
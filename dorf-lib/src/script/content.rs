@@ -0,0 +1,287 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use ron::extensions::Extensions;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::prelude::*;
+
+use super::local_map::Biome;
+use super::map_image::MapPalette;
+use super::prefabs::{PrefabDef, Prefabs};
+use super::terrain::BiomeTable;
+use super::tiles::{Material, TileDef, TileTable};
+
+/// Where content is loaded from when no other directory is picked.
+pub const DEFAULT_DATA_DIR: &str = "data";
+
+const TILES_FILE: &str = "tiles.ron";
+const BIOMES_FILE: &str = "biomes.ron";
+const CREATURES_FILE: &str = "creatures.ron";
+const ITEMS_FILE: &str = "items.ron";
+const PALETTE_FILE: &str = "palette.ron";
+const PREFABS_FILE: &str = "prefabs.ron";
+
+#[derive(Debug, Error)]
+pub enum ContentError {
+    #[error("data directory {} doesn't exist", .0.display())]
+    MissingDir(PathBuf),
+
+    #[error("couldn't read {}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("{}:{source}", path.display())]
+    Parse {
+        path: PathBuf,
+        source: ron::error::SpannedError,
+    },
+
+    #[error("{}: {message}", path.display())]
+    Invalid { path: PathBuf, message: String },
+}
+
+/// A kind of creature which may be spawned.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CreatureDef {
+    pub name: String,
+    pub glyph: char,
+    pub color: RGB,
+    /// Tiles moved per second.
+    pub speed: f32,
+}
+
+/// A kind of item.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ItemDef {
+    pub name: String,
+    pub glyph: char,
+    pub color: RGB,
+    pub material: Material,
+    /// In kilograms.
+    pub weight: f32,
+}
+
+/// Every [`CreatureDef`], in the order they were defined.
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct Creatures(pub Vec<CreatureDef>);
+
+impl Default for Creatures {
+    fn default() -> Self {
+        Self(vec![CreatureDef {
+            name: "dwarf".to_string(),
+            glyph: '▢',
+            color: Color::RED,
+            speed: 5.0,
+        }])
+    }
+}
+
+/// Every [`ItemDef`], in the order they were defined.
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct Items(pub Vec<ItemDef>);
+
+impl Default for Items {
+    fn default() -> Self {
+        let n = |name: &str, glyph, color, material, weight| ItemDef {
+            name: name.to_string(),
+            glyph,
+            color,
+            material,
+            weight,
+        };
+        Self(vec![
+            n("log", '=', RGB::new(140, 90, 40), Material::Wood, 20.0),
+            n("stone", '•', RGB::new(127, 127, 127), Material::Stone, 10.0),
+            n("ore", '•', RGB::new(242, 204, 51), Material::Ore, 12.0),
+        ])
+    }
+}
+
+/// Name of the [`CreatureDef`] an entity was spawned from.
+#[derive(Component, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Species(pub String);
+
+/// Everything loaded from the data directory.
+#[derive(Debug, Clone, Default)]
+pub struct Content {
+    pub tiles: TileTable,
+    pub biomes: BiomeTable,
+    pub creatures: Creatures,
+    pub items: Items,
+    pub palette: MapPalette,
+    pub prefabs: Prefabs,
+}
+
+impl Content {
+    /// Load every data file in `dir`, checking each makes sense. A file which
+    /// doesn't exist keeps the built-in content.
+    pub fn load(dir: &Path) -> Result<Self, ContentError> {
+        if !dir.is_dir() {
+            return Err(ContentError::MissingDir(dir.to_path_buf()));
+        }
+        let mut content = Self::default();
+
+        let path = dir.join(TILES_FILE);
+        if let Some(tiles) = read::<HashMap<Biome, TileDef>>(&path)? {
+            content.tiles = TileTable::from_entries(tiles).map_err(invalid(&path))?;
+        }
+        let path = dir.join(BIOMES_FILE);
+        if let Some(biomes) = read::<BiomeTable>(&path)? {
+            biomes.validate().map_err(invalid(&path))?;
+            content.biomes = biomes;
+        }
+        let path = dir.join(CREATURES_FILE);
+        if let Some(creatures) = read::<Vec<CreatureDef>>(&path)? {
+            validate_creatures(&creatures).map_err(invalid(&path))?;
+            content.creatures = Creatures(creatures);
+        }
+        let path = dir.join(ITEMS_FILE);
+        if let Some(items) = read::<Vec<ItemDef>>(&path)? {
+            validate_items(&items).map_err(invalid(&path))?;
+            content.items = Items(items);
+        }
+        let path = dir.join(PALETTE_FILE);
+        if let Some(palette) = read::<HashMap<Biome, RGB>>(&path)? {
+            content.palette = MapPalette::from_entries(palette).map_err(invalid(&path))?;
+        }
+        let path = dir.join(PREFABS_FILE);
+        if let Some(prefabs) = read::<Vec<PrefabDef>>(&path)? {
+            check_names("prefab", prefabs.iter().map(|p| p.name.as_str()))
+                .map_err(invalid(&path))?;
+            content.prefabs = Prefabs::from_defs(prefabs).map_err(invalid(&path))?;
+        }
+        Ok(content)
+    }
+}
+
+/// Parse the file at `path`, `None` if there isn't one.
+fn read<T: DeserializeOwned>(path: &Path) -> Result<Option<T>, ContentError> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            log::info!("No {}, using built-in content", path.display());
+            return Ok(None);
+        }
+        Err(source) => {
+            return Err(ContentError::Io {
+                path: path.to_path_buf(),
+                source,
+            })
+        }
+    };
+    log::info!("Loading {}", path.display());
+    // Optional fields can be written without wrapping them in `Some(..)`.
+    ron::Options::default()
+        .with_default_extension(Extensions::IMPLICIT_SOME)
+        .from_str(&text)
+        .map(Some)
+        .map_err(|source| ContentError::Parse {
+            path: path.to_path_buf(),
+            source,
+        })
+}
+
+fn invalid(path: &Path) -> impl FnOnce(String) -> ContentError + '_ {
+    move |message| ContentError::Invalid {
+        path: path.to_path_buf(),
+        message,
+    }
+}
+
+/// Names must be given and can't be repeated.
+fn check_names<'a>(kind: &str, names: impl Iterator<Item = &'a str>) -> Result<(), String> {
+    let mut seen = HashSet::new();
+    for name in names {
+        if name.is_empty() {
+            return Err(format!("{} without a name", kind));
+        }
+        if !seen.insert(name) {
+            return Err(format!("{} {:?} is defined twice", kind, name));
+        }
+    }
+    Ok(())
+}
+
+fn validate_creatures(creatures: &[CreatureDef]) -> Result<(), String> {
+    check_names("creature", creatures.iter().map(|c| c.name.as_str()))?;
+    for creature in creatures {
+        if !(creature.speed.is_finite() && creature.speed > 0.0) {
+            return Err(format!(
+                "creature {:?} has speed {}, expected a positive number",
+                creature.name, creature.speed
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn validate_items(items: &[ItemDef]) -> Result<(), String> {
+    check_names("item", items.iter().map(|i| i.name.as_str()))?;
+    for item in items {
+        if !(item.weight.is_finite() && item.weight >= 0.0) {
+            return Err(format!(
+                "item {:?} has weight {}, expected zero or more",
+                item.name, item.weight
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data_dir() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("..")
+            .join(DEFAULT_DATA_DIR)
+    }
+
+    #[test]
+    fn shipped_content_loads() {
+        let dir = data_dir();
+        // Each must be there, or the built-in content is checked instead.
+        for file in [
+            TILES_FILE,
+            BIOMES_FILE,
+            CREATURES_FILE,
+            ITEMS_FILE,
+            PALETTE_FILE,
+            PREFABS_FILE,
+        ] {
+            assert!(dir.join(file).is_file(), "{} isn't shipped", file);
+        }
+        let content = Content::load(&dir).unwrap();
+        assert!(!content.creatures.0.is_empty());
+        assert!(!content.items.0.is_empty());
+        assert!(!content.prefabs.0.is_empty());
+    }
+
+    #[test]
+    fn invalid_content_is_rejected() {
+        let creatures: Vec<CreatureDef> = read(&data_dir().join(CREATURES_FILE)).unwrap().unwrap();
+        assert!(validate_creatures(&creatures).is_ok());
+
+        let twice: Vec<_> = creatures.iter().chain(&creatures).cloned().collect();
+        assert!(validate_creatures(&twice).is_err());
+
+        let mut stuck = creatures;
+        stuck[0].speed = 0.0;
+        assert!(validate_creatures(&stuck).is_err());
+
+        assert!(matches!(
+            Content::load(Path::new("no such dir")),
+            Err(ContentError::MissingDir(_))
+        ));
+    }
+}
@@ -0,0 +1,223 @@
+use crate::prelude::*;
+use bevy::input::ButtonState;
+
+use super::content::Species;
+use super::levels::{Level, ViewLevel};
+use super::local_map::{biome_at, BiomeGrid};
+use super::pathing::{CollisionGridCache, GoalLoc, LayerableCollider, LevelPoint, MovePath, Speed};
+
+/// How close (in tiles) the cursor may get to the edge of the camera before the
+/// camera is pushed along with it.
+const CURSOR_EDGE_MARGIN: i32 = 2;
+const LOOK_PANEL_WIDTH: u32 = 40;
+
+pub fn add_cursor_systems(app: &mut App, enabled: bool) {
+    app.insert_resource(LookMode::default())
+        .add_startup_system(spawn_tile_cursor)
+        .add_system(handle_cursor_keys)
+        .add_system(sys_update_look_panel.after(handle_cursor_keys));
+}
+
+/// The keyboard driven cursor used to inspect world tiles.
+#[derive(Component, Debug)]
+pub struct TileCursor {
+    pub tile: IVec2,
+}
+
+/// While enabled, a panel lists everything found under the [`TileCursor`].
+#[derive(Resource, Debug, Default)]
+pub struct LookMode {
+    pub enabled: bool,
+}
+
+/// Tag for the panel spawned while [`LookMode`] is enabled.
+#[derive(Component, Debug, Default)]
+struct LookPanel;
+
+#[derive(Bundle)]
+struct LookPanelBundle {
+    tag: LookPanel,
+    mesh: CharMeshTransform,
+    ui_component: UIComponent,
+}
+
+fn spawn_tile_cursor(mut cmd: Commands) {
+    // Start just inside the camera frame, on the first tile of the map.
+    let tile = IVec2::ONE;
+    cmd.spawn((
+        TileCursor { tile },
+        CharTexture::new('X', Color::YELLOW),
+        Transform2D {
+            scale: UVec2::splat(1),
+            loc: tile.as_vec2().extend(384.0),
+        },
+    ));
+}
+
+/// Shift the camera so `tile` stays at least [`CURSOR_EDGE_MARGIN`] tiles
+/// inside of it.
+fn push_camera_towards(tile: IVec2, camera: &mut ResMut<TerminalCamera2D>) {
+    let rect = camera.transform().as_rect2d();
+    let low = rect.min + CURSOR_EDGE_MARGIN;
+    let high = rect.max - CURSOR_EDGE_MARGIN - 1;
+    let shift = (tile - high).max(IVec2::ZERO) + (tile - low).min(IVec2::ZERO);
+    if shift != IVec2::ZERO {
+        *camera.loc_mut() += shift.as_vec2().extend(0.0);
+    }
+}
+
+fn handle_cursor_keys(
+    mut input: EventReader<KeyboardInput>,
+    focus: Res<InputFocus>,
+    mut camera: ResMut<TerminalCamera2D>,
+    mut look_mode: ResMut<LookMode>,
+    mut cursor: Query<(&mut TileCursor, &mut Transform2D)>,
+) {
+    if *focus != InputFocus::World {
+        input.clear();
+        return;
+    }
+    let Ok((mut cursor, mut transform)) = cursor.get_single_mut() else {
+        return;
+    };
+    for e in input.iter() {
+        if e.state != ButtonState::Pressed {
+            continue;
+        }
+        let step = match e.key_code {
+            Some(KeyCode::Left) => IVec2::new(-1, 0),
+            Some(KeyCode::Right) => IVec2::new(1, 0),
+            Some(KeyCode::Up) => IVec2::new(0, -1),
+            Some(KeyCode::Down) => IVec2::new(0, 1),
+            Some(KeyCode::L) => {
+                look_mode.enabled = !look_mode.enabled;
+                continue;
+            }
+            _ => continue,
+        };
+        cursor.tile += step;
+        push_camera_towards(cursor.tile, &mut camera);
+    }
+    if cursor.is_changed() {
+        transform.loc.x = cursor.tile.x as f32;
+        transform.loc.y = cursor.tile.y as f32;
+    }
+}
+
+fn fmt_point(point: LevelPoint) -> String {
+    format!(
+        "({:.1},{:.1}) lvl {}",
+        point.loc.x, point.loc.y, point.level
+    )
+}
+
+/// Describe everything found at `tile` on `level`, one line per entry.
+#[allow(clippy::type_complexity)]
+fn describe_tile(
+    tile: IVec2,
+    level: u32,
+    grids: &Query<&BiomeGrid>,
+    col_cache: &CollisionGridCache,
+    movers: &Query<
+        (
+            Entity,
+            &Transform2D,
+            &Level,
+            &GoalLoc,
+            &Speed,
+            Option<&MovePath>,
+            Option<&Species>,
+        ),
+        With<LayerableCollider>,
+    >,
+) -> Vec<String> {
+    let mut lines = vec![format!("Look ({},{}) lvl {}", tile.x, tile.y, level)];
+    match biome_at(grids.iter(), tile, level) {
+        Some(biome) => lines.push(format!("Biome: {:?}", biome)),
+        None => lines.push("Biome: unexplored".to_string()),
+    }
+    if let Some(obstacle) = col_cache.obstacle_at(tile.extend(level as i32)) {
+        lines.push(format!("Obstacle: {:?}", obstacle));
+    }
+    for (entity, transform, mover_level, goal, speed, path, species) in movers.iter() {
+        if mover_level.0 != level || !transform.as_rect2d().contains_exclusive_max(tile.as_vec2()) {
+            continue;
+        }
+        let name = species.map_or("mover", |s| s.0.as_str());
+        lines.push(format!("{:?} {} speed {:.1}", entity, name, speed.0));
+        match goal.0 {
+            Some(goal) => lines.push(format!("  goal: {}", fmt_point(goal))),
+            None => lines.push("  goal: none".to_string()),
+        }
+        match path.and_then(|p| Some((p.steps.last()?, p.steps.first()?, p.steps.len()))) {
+            Some((next, end, len)) => lines.push(format!(
+                "  path: {} steps, next {} end {}",
+                len,
+                fmt_point(*next),
+                fmt_point(*end)
+            )),
+            None => lines.push("  path: none".to_string()),
+        }
+    }
+    lines
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn sys_update_look_panel(
+    mut cmd: Commands,
+    look_mode: Res<LookMode>,
+    view: Res<ViewLevel>,
+    cursor: Query<&TileCursor>,
+    grids: Query<&BiomeGrid>,
+    col_cache: Res<CollisionGridCache>,
+    movers: Query<
+        (
+            Entity,
+            &Transform2D,
+            &Level,
+            &GoalLoc,
+            &Speed,
+            Option<&MovePath>,
+            Option<&Species>,
+        ),
+        With<LayerableCollider>,
+    >,
+    mut panel: Query<
+        (Entity, &mut CharMesh, &mut Transform2D),
+        (With<LookPanel>, Without<LayerableCollider>),
+    >,
+) {
+    let panel = panel.get_single_mut().ok();
+    let cursor = cursor.get_single().ok();
+    let (Some(cursor), true) = (cursor, look_mode.enabled) else {
+        if let Some((entity, _, _)) = panel {
+            cmd.entity(entity).despawn();
+        }
+        return;
+    };
+
+    let lines = describe_tile(cursor.tile, view.level, &grids, &col_cache, &movers);
+    let scale = UVec2::new(LOOK_PANEL_WIDTH, lines.len() as u32);
+    match panel {
+        Some((_, mut mesh, mut transform)) => {
+            if transform.scale != scale {
+                transform.scale = scale;
+                mesh.resize(scale);
+            }
+            for (row, line) in lines.iter().enumerate() {
+                mesh.write_row(scale.x, row as u32, line, None);
+            }
+        }
+        None => {
+            // Filled in on the next update.
+            cmd.spawn(LookPanelBundle {
+                tag: LookPanel,
+                mesh: CharMeshTransform::new(Transform2D {
+                    scale,
+                    loc: Vec3::ZERO,
+                }),
+                ui_component: UIComponent::new(Vec3::new(1.0, 1.0, 512.0)),
+            });
+        }
+    }
+}
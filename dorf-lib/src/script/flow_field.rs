@@ -0,0 +1,458 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+};
+
+use ordered_float::OrderedFloat;
+use serde::{Deserialize, Serialize};
+
+use crate::prelude::*;
+
+use super::levels::Level;
+use super::pathing::{
+    random_point_on_local_map, step_origin, sys_update_collision_cache,
+    sys_update_terrain_obstacles, system_assign_optimal_path, CollisionChanged, CollisionGridCache,
+    FunctionalTuple, GoalLoc, LevelPoint, Speed,
+};
+use super::sim_time::SimTime;
+
+pub fn add_flow_field_systems(app: &mut App, enabled: bool) {
+    app.init_resource::<FlowFields>()
+        .add_system(
+            sys_update_flow_fields
+                .after(sys_update_collision_cache)
+                .after(sys_update_terrain_obstacles)
+                .before(system_assign_optimal_path),
+        )
+        .add_system(sys_move_on_flow_field.after(system_assign_optimal_path));
+}
+
+/// A component for entities heading to `goal` down its [`FlowField`], in
+/// place of a [`MovePath`](super::pathing::MovePath).
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
+pub struct FollowFlowField {
+    pub goal: LevelPoint,
+    /// The tile being stepped to, the next is picked from the field once it's
+    /// reached.
+    pub next: Option<LevelPoint>,
+}
+
+impl FollowFlowField {
+    pub fn new(goal: LevelPoint) -> Self {
+        Self { goal, next: None }
+    }
+}
+
+/// Cost of the cheapest path from every tile of the [`CollisionGridCache`] to
+/// a goal, a Dijkstra map. Movers anywhere find their way by stepping to
+/// whichever neighbor is cheapest, so one field serves all those headed to
+/// the same place.
+///
+/// Movers are taken to be a single tile.
+#[derive(Debug)]
+pub struct FlowField {
+    goal: IVec3,
+    /// `None` for tiles with no way to the goal.
+    costs: Grid3D<Option<f32>>,
+}
+
+impl FlowField {
+    /// Work out the field for `goal` by searching outwards from it. Steps
+    /// cost the same both ways, so the cost from the goal is the cost to it.
+    pub fn new(col_cache: &CollisionGridCache, goal: IVec3) -> Self {
+        let rect = col_cache.rect();
+        let mut costs = Grid3D::new(rect.min, rect.size().as_uvec2(), col_cache.levels(), None);
+        let mut to_explore = BinaryHeap::new();
+        if passable(col_cache, goal) {
+            costs.set(goal, Some(0.0));
+            to_explore.push(Reverse(FunctionalTuple(OrderedFloat(0.0), goal)));
+        }
+        while let Some(Reverse(FunctionalTuple(cost, point))) = to_explore.pop() {
+            // Reached more cheaply since being pushed, and explored then.
+            if costs.get(point) != Ok(&Some(cost.0)) {
+                continue;
+            }
+            for (next, step) in neighbors(col_cache, point) {
+                let cost = cost.0 + step;
+                if costs
+                    .get(next)
+                    .is_ok_and(|known| known.is_some_and(|known| known <= cost))
+                {
+                    continue;
+                }
+                costs.set(next, Some(cost));
+                to_explore.push(Reverse(FunctionalTuple(OrderedFloat(cost), next)));
+            }
+        }
+        Self { goal, costs }
+    }
+
+    #[inline]
+    pub fn goal(&self) -> IVec3 {
+        self.goal
+    }
+
+    /// Cost of the cheapest path from `point` to the goal, `None` if there's
+    /// no way there.
+    #[inline]
+    pub fn cost(&self, point: IVec3) -> Option<f32> {
+        self.costs.get(point).ok().copied().flatten()
+    }
+
+    /// The neighbor of `point` to step to on the cheapest path to the goal.
+    /// `None` at the goal, or where there's no way there. Movers stood on
+    /// something which has since blocked `point` still find their way off it.
+    pub fn next_step(&self, col_cache: &CollisionGridCache, point: IVec3) -> Option<IVec3> {
+        if point == self.goal {
+            return None;
+        }
+        neighbors(col_cache, point)
+            .filter_map(|(next, step)| self.cost(next).map(|cost| (next, cost + step)))
+            .min_by_key(|(_, cost)| OrderedFloat(*cost))
+            .map(|(next, _)| next)
+    }
+
+    /// Whether a mover on `point` can find its way to the goal.
+    pub fn reaches(&self, col_cache: &CollisionGridCache, point: IVec3) -> bool {
+        point == self.goal || self.next_step(col_cache, point).is_some()
+    }
+
+    /// Whether what collides changing within `rect` on `level` may change
+    /// the field. Only steps onto, off of or around the corners of the area
+    /// change, so if none of those touch a tile with a way to the goal,
+    /// nothing that reaches it does.
+    pub fn affected_by(&self, rect: Rect2D, level: u32) -> bool {
+        let around = rect.inset(1);
+        // Stairs lead straight up and down.
+        let levels = level.saturating_sub(1) as i32..=level as i32 + 1;
+        levels.into_iter().any(|z| {
+            (around.min.y..around.max.y).any(|y| {
+                (around.min.x..around.max.x).any(|x| self.cost(IVec3::new(x, y, z)).is_some())
+            })
+        })
+    }
+}
+
+/// The [`FlowField`] to each goal being followed, worked out by a
+/// [`PathTask`](super::pathing::PathTask) when first needed. Fields are
+/// dropped when anything that collides changes where they reach, as the cost
+/// from any tile may have.
+#[derive(Resource, Debug, Default)]
+pub struct FlowFields {
+    fields: HashMap<IVec3, FlowField>,
+}
+
+impl FlowFields {
+    /// The cached field leading to `goal`, if any.
+    pub fn get(&self, goal: IVec3) -> Option<&FlowField> {
+        self.fields.get(&goal)
+    }
+
+    /// Cache `field`, unless there's already one leading to its goal.
+    pub fn insert(&mut self, field: FlowField) -> &FlowField {
+        self.fields.entry(field.goal).or_insert(field)
+    }
+}
+
+fn passable(col_cache: &CollisionGridCache, point: IVec3) -> bool {
+    col_cache.collides(point) == Ok(false)
+}
+
+/// Tiles a mover on `point` could step to, and the cost of each step. Moves
+/// as A* on the [`CollisionGridCache`] does.
+fn neighbors(
+    col_cache: &CollisionGridCache,
+    point: IVec3,
+) -> impl Iterator<Item = (IVec3, f32)> + '_ {
+    let straight = [IVec3::X, -IVec3::X, IVec3::Y, -IVec3::Y]
+        .into_iter()
+        .map(move |dir| point + dir);
+    // Diagonals can't cut the corner of anything.
+    let diagonal = [(1, 1), (-1, 1), (1, -1), (-1, -1)]
+        .into_iter()
+        .filter(move |(x, y)| {
+            passable(col_cache, point + IVec3::new(*x, 0, 0))
+                && passable(col_cache, point + IVec3::new(0, *y, 0))
+        })
+        .map(move |(x, y)| point + IVec3::new(x, y, 0));
+    let stairs = [point - IVec3::Z, point + IVec3::Z]
+        .into_iter()
+        .filter(move |stairs| col_cache.stairs_connect(point, *stairs));
+    straight
+        .chain(diagonal)
+        .chain(stairs)
+        .filter(|next| passable(col_cache, *next))
+        .map(move |next| (next, col_cache.step_cost(point, next)))
+}
+
+/// Drop each [`FlowField`] that anything colliding has changed, and those
+/// nobody follows any more.
+fn sys_update_flow_fields(
+    mut fields: ResMut<FlowFields>,
+    mut changes: EventReader<CollisionChanged>,
+    followers: Query<&FollowFlowField>,
+) {
+    for change in changes.iter() {
+        match change {
+            CollisionChanged::All => fields.fields.clear(),
+            CollisionChanged::Area { rect, level } => fields
+                .fields
+                .retain(|_, field| !field.affected_by(*rect, *level)),
+        }
+    }
+    let followed: HashSet<IVec3> = followers
+        .iter()
+        .map(|follower| follower.goal.tile())
+        .collect();
+    fields.fields.retain(|goal, _| followed.contains(goal));
+}
+
+/// Move entities a step at a time down the [`FlowField`] to their goal, then
+/// assign them a new goal once they're there. Those left with no way there,
+/// e.g. walled in since, give up on it. Terrain slows or speeds them up as it
+/// does those on a [`MovePath`](super::pathing::MovePath).
+///
+/// Those whose field was dropped head for the goal again, to wait where they
+/// are until it's worked out anew.
+fn sys_move_on_flow_field(
+    mut cmd: Commands,
+    time: Res<SimTime>,
+    col_cache: Res<CollisionGridCache>,
+    fields: Res<FlowFields>,
+    mut rng: ResMut<SimRng>,
+    mut q: Query<(
+        Entity,
+        &mut FollowFlowField,
+        &mut Transform2D,
+        &mut Level,
+        &Speed,
+        &mut GoalLoc,
+    )>,
+) {
+    let rng = rng.stream("flow_field::move_on_flow_field");
+    for (entity, mut follow, mut rect, mut level, speed, mut goal) in q.iter_mut() {
+        let Some(field) = fields.get(follow.goal.tile()) else {
+            cmd.entity(entity).remove::<FollowFlowField>();
+            goal.0 = Some(follow.goal);
+            continue;
+        };
+        let mut travel = speed.0 * time.delta_seconds();
+        loop {
+            let next = match follow.next {
+                Some(next) => next,
+                None => {
+                    let at = rect.as_tile().extend(level.0 as i32);
+                    // Part way to a tile that was blocked, get back onto one.
+                    let step = if rect.loc.xy() != at.xy().as_vec2() && passable(&col_cache, at) {
+                        Some(at)
+                    } else {
+                        field.next_step(&col_cache, at)
+                    };
+                    let Some(step) = step else {
+                        if at != field.goal() {
+                            log::debug!("No way left to {:?} for {:?}", follow.goal, entity);
+                        }
+                        cmd.entity(entity).remove::<FollowFlowField>();
+                        goal.0 = Some(random_point_on_local_map(rng, &col_cache));
+                        break;
+                    };
+                    let next = LevelPoint::new(step.xy().as_vec2(), step.z as u32);
+                    follow.next = Some(next);
+                    next
+                }
+            };
+            if col_cache
+                .would_collide_if_moved(&rect, &next.tile())
+                .unwrap_or(true)
+            {
+                // Blocked since it was picked, head for the goal again.
+                cmd.entity(entity).remove::<FollowFlowField>();
+                goal.0 = Some(follow.goal);
+                break;
+            }
+            let from = step_origin(rect.loc.xy(), next.loc).extend(level.0 as i32);
+            let move_cost = col_cache.step_move_cost(from, next.tile());
+            let dist = rect.loc.xy().distance(next.loc) * move_cost;
+            if dist <= travel {
+                travel -= dist;
+                follow.next = None;
+                (rect.loc.x, rect.loc.y) = (next.loc.x, next.loc.y);
+                // Stairs are climbed in place.
+                if level.0 != next.level {
+                    level.0 = next.level;
+                }
+                continue;
+            }
+            let direction = (next.loc - rect.loc.xy()).normalize();
+            rect.loc.x += direction.x * travel / move_cost;
+            rect.loc.y += direction.y * travel / move_cost;
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::script::local_map::{Biome, BiomeGrid};
+    use crate::script::pathing::tests::{fixtures, shipped_tiles};
+    use crate::script::tiles::TileTable;
+
+    fn tiles(col_cache: &CollisionGridCache) -> impl Iterator<Item = IVec3> + '_ {
+        let rect = *col_cache.rect();
+        (0..col_cache.levels() as i32).flat_map(move |z| {
+            (rect.min.y..rect.max.y)
+                .flat_map(move |y| (rect.min.x..rect.max.x).map(move |x| IVec3::new(x, y, z)))
+        })
+    }
+
+    #[test]
+    fn next_steps_reach_goal_at_cost() {
+        for fixture in fixtures() {
+            let cache = &fixture.cache;
+            for goal in fixture.map.goals.iter() {
+                let field = FlowField::new(cache, goal.tile());
+                for spawn in fixture.map.spawns.iter() {
+                    let reaches = field.cost(spawn.tile()).is_some();
+                    assert_eq!(reaches, fixture.reachable(), "{}", fixture.name);
+                }
+                for start in tiles(cache).filter(|tile| passable(cache, *tile)) {
+                    let Some(expected) = field.cost(start) else {
+                        assert_eq!(field.next_step(cache, start), None, "{}", fixture.name);
+                        continue;
+                    };
+                    let (mut at, mut cost) = (start, 0.0);
+                    while let Some(next) = field.next_step(cache, at) {
+                        assert!(
+                            neighbors(cache, at).any(|(neighbor, _)| neighbor == next),
+                            "{}: {} to {} isn't a step",
+                            fixture.name,
+                            at,
+                            next
+                        );
+                        cost += cache.step_cost(at, next);
+                        at = next;
+                        assert!(cost <= expected + 1e-3, "{}: loops", fixture.name);
+                    }
+                    assert_eq!(at, goal.tile(), "{}: stuck at {}", fixture.name, at);
+                    assert!(
+                        (cost - expected).abs() < 1e-3,
+                        "{}: following from {} costs {}, field says {}",
+                        fixture.name,
+                        start,
+                        cost,
+                        expected
+                    );
+                }
+            }
+        }
+    }
+
+    /// Fields kept after a change must be the same as if worked out again.
+    #[test]
+    fn unaffected_fields_are_unchanged() {
+        for fixture in fixtures() {
+            let goal = fixture.map.goals[0].tile();
+            let field = FlowField::new(&fixture.cache, goal);
+            // Walls put on every tile, and each of the map's taken away.
+            let placed = tiles(&fixture.cache)
+                .filter(|tile| *tile != goal)
+                .map(|tile| {
+                    let mut cache = fixture.cache.clone();
+                    let change = cache.place_obstacle(tile, Entity::from_raw(1_000_000));
+                    (cache, change)
+                });
+            let removed = (0..fixture.map.walls.len()).map(|id| {
+                let mut cache = fixture.cache.clone();
+                let change = cache.remove_obstacle(Entity::from_raw(id as u32)).unwrap();
+                (cache, change)
+            });
+            let (mut kept, mut dropped) = (0, 0);
+            for (cache, change) in placed.chain(removed) {
+                let CollisionChanged::Area { rect, level } = change else {
+                    unreachable!();
+                };
+                if field.affected_by(rect, level) {
+                    dropped += 1;
+                    continue;
+                }
+                kept += 1;
+                let after = FlowField::new(&cache, goal);
+                for point in tiles(&cache) {
+                    assert_eq!(
+                        field.cost(point),
+                        after.cost(point),
+                        "{}: change at {:?} altered {}",
+                        fixture.name,
+                        rect,
+                        point
+                    );
+                }
+            }
+            assert!(dropped > 0, "{}", fixture.name);
+            if fixture.name == "unreachable.ron" {
+                assert!(kept > 0);
+            }
+        }
+    }
+
+    /// Loading a chunk only drops the fields reaching up to it.
+    #[test]
+    fn loading_chunks_keeps_fields_out_of_reach() {
+        let tiles = shipped_tiles();
+        let mut world = World::new();
+        world.insert_resource(CollisionGridCache::new(
+            IVec2::ZERO,
+            UVec2::new(12, 4),
+            1,
+            tiles.clone(),
+        ));
+        world.insert_resource(tiles);
+        world.init_resource::<FlowFields>();
+        world.init_resource::<Events<CollisionChanged>>();
+        let mut schedule = Schedule::new();
+        schedule.add_systems((
+            sys_update_terrain_obstacles,
+            sys_update_flow_fields.after(sys_update_terrain_obstacles),
+        ));
+        let chunk = |x: i32, biome: Biome| {
+            BiomeGrid::new(Grid3D::new(IVec2::new(x, 0), UVec2::new(4, 4), 1, biome))
+        };
+        let goal = LevelPoint::new(Vec2::new(1.0, 1.0), 0);
+        let insert_field = |world: &mut World| {
+            world.resource_scope(|world, mut fields: Mut<FlowFields>| {
+                fields.insert(FlowField::new(
+                    world.resource::<CollisionGridCache>(),
+                    goal.tile(),
+                ));
+            })
+        };
+        let kept = |world: &World| {
+            world
+                .resource::<FlowFields>()
+                .fields
+                .contains_key(&goal.tile())
+        };
+
+        // A river cuts the goal's chunk off from the rest.
+        world.spawn(chunk(0, Biome::Grassland));
+        let river = world.spawn(chunk(4, Biome::River)).id();
+        world.spawn(FollowFlowField::new(goal));
+        schedule.run(&mut world);
+        insert_field(&mut world);
+
+        world.spawn(chunk(8, Biome::Grassland));
+        schedule.run(&mut world);
+        assert!(kept(&world));
+
+        *world.get_mut::<BiomeGrid>(river).unwrap() = chunk(4, Biome::Grassland);
+        schedule.run(&mut world);
+        assert!(!kept(&world));
+
+        // Changing the tiles changes every field.
+        insert_field(&mut world);
+        world.resource_mut::<TileTable>().set_changed();
+        schedule.run(&mut world);
+        assert!(!kept(&world));
+    }
+}
@@ -0,0 +1,838 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+};
+
+use ordered_float::OrderedFloat;
+use serde::{Deserialize, Serialize};
+
+use crate::prelude::*;
+
+use super::levels::Level;
+use super::pathing::{
+    needs_search, octile_distance, sys_update_collision_cache, sys_update_terrain_obstacles,
+    system_assign_optimal_path, system_move_on_optimal_path, CollisionChanged, CollisionGridCache,
+    FunctionalTuple, GoalLoc, LevelPoint, MovePath,
+};
+
+/// Width and height of each cluster, in tiles.
+pub const CLUSTER_SIZE: i32 = 10;
+/// Openings between clusters at least this wide get an entrance at each end
+/// rather than one in the middle.
+const WIDE_ENTRANCE: usize = 6;
+/// Refine the path into tiles this many steps ahead of the mover.
+const REFINE_AHEAD: usize = CLUSTER_SIZE as usize;
+
+pub fn add_hierarchy_systems(app: &mut App, enabled: bool) {
+    app.init_resource::<PathHierarchy>()
+        .add_system(
+            sys_update_path_hierarchy
+                .after(sys_update_collision_cache)
+                .after(sys_update_terrain_obstacles)
+                .before(system_assign_optimal_path),
+        )
+        .add_system(
+            sys_refine_abstract_paths
+                .after(system_assign_optimal_path)
+                .before(system_move_on_optimal_path),
+        );
+}
+
+/// A component holding the rest of a path found over the [`PathHierarchy`].
+/// It's refined into the entity's [`MovePath`](super::pathing::MovePath) a
+/// stretch at a time, as the entity gets near.
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
+pub struct AbstractPath {
+    /// Where the path ends, searched for again if the path is blocked.
+    pub goal: LevelPoint,
+    /// Points to pass through on the way, stored in reverse like
+    /// [`MovePath`](super::pathing::MovePath) steps.
+    pub waypoints: Vec<LevelPoint>,
+}
+
+impl AbstractPath {
+    /// Refine waypoints onto the front of `steps`, the furthest step, until
+    /// there are [`REFINE_AHEAD`] of them or no waypoints are left. `at` is
+    /// where the entity stands, used once it has walked every step. Returns
+    /// `false` if the way to a waypoint has been blocked since the path was
+    /// found.
+    pub fn refine(
+        &mut self,
+        col_cache: &CollisionGridCache,
+        at: IVec3,
+        steps: &mut Vec<LevelPoint>,
+    ) -> bool {
+        while steps.len() < REFINE_AHEAD {
+            let Some(to) = self.waypoints.last() else {
+                return true;
+            };
+            let from = steps.first().map_or(at, |step| step.tile());
+            let Some(segment) = refine_segment(col_cache, from, to.tile()) else {
+                return false;
+            };
+            steps.splice(0..0, segment);
+            self.waypoints.pop();
+        }
+        true
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Side {
+    East,
+    South,
+}
+
+/// An abstraction of the [`CollisionGridCache`] for finding long paths. Each
+/// level is split into clusters of [`CLUSTER_SIZE`] tiles square, and the
+/// paths between the entrances of each cluster, and the stairs within it,
+/// are found ahead of time. Long paths are then found between entrances, then
+/// refined into tiles near the mover.
+///
+/// Kept up to date with [`CollisionChanged`], only clusters where something
+/// changed are worked out again.
+#[derive(Resource, Debug, Default, Clone)]
+pub struct PathHierarchy {
+    rect: Rect2D,
+    levels: u32,
+    /// Pairs of tiles either side of the openings between each cluster,
+    /// given as `(x, y, level)`, and the next cluster along the side.
+    entrances: HashMap<(IVec3, Side), Vec<(IVec3, IVec3)>>,
+    /// Tiles of each cluster with stairs to another level.
+    stairs: HashMap<IVec3, Vec<IVec3>>,
+    /// Cost between each pair of entrances and stairs within a cluster, by
+    /// cluster.
+    inner: HashMap<IVec3, HashMap<IVec3, Vec<(IVec3, f32)>>>,
+    /// Steps between clusters, through entrances or up and down stairs.
+    outer: HashMap<IVec3, Vec<(IVec3, f32)>>,
+}
+
+impl PathHierarchy {
+    /// The cluster containing `point`, as `(x, y, level)`.
+    fn cluster_of(&self, point: IVec3) -> IVec3 {
+        cluster_coord(self.rect.min, point.xy()).extend(point.z)
+    }
+
+    fn cluster_rect(&self, cluster: IVec3) -> Rect2D {
+        let min = self.rect.min + cluster.xy() * CLUSTER_SIZE;
+        Rect2D::from_corners(min, min + IVec2::splat(CLUSTER_SIZE)).intersect(self.rect)
+    }
+
+    fn clusters(&self) -> impl Iterator<Item = IVec3> {
+        let count = (self.rect.size() + CLUSTER_SIZE - 1) / CLUSTER_SIZE;
+        (0..self.levels as i32).flat_map(move |z| {
+            (0..count.y).flat_map(move |y| (0..count.x).map(move |x| IVec3::new(x, y, z)))
+        })
+    }
+
+    /// Entrances and stairs within `cluster`.
+    fn nodes(&self, cluster: IVec3) -> HashSet<IVec3> {
+        let west = cluster - IVec3::X;
+        let north = cluster - IVec3::Y;
+        let sides = [
+            (cluster, Side::East, true),
+            (cluster, Side::South, true),
+            (west, Side::East, false),
+            (north, Side::South, false),
+        ];
+        let mut nodes: HashSet<IVec3> = sides
+            .iter()
+            .flat_map(|(at, side, first)| {
+                self.entrances
+                    .get(&(*at, *side))
+                    .into_iter()
+                    .flatten()
+                    .map(move |(from, to)| if *first { *from } else { *to })
+            })
+            .collect();
+        nodes.extend(self.stairs.get(&cluster).into_iter().flatten());
+        nodes
+    }
+
+    /// Work out every cluster from scratch, e.g. once the map has moved.
+    pub fn rebuild(&mut self, col_cache: &CollisionGridCache) {
+        self.rect = *col_cache.rect();
+        self.levels = col_cache.levels();
+        self.entrances.clear();
+        self.stairs.clear();
+        self.inner.clear();
+        self.outer.clear();
+        let clusters: Vec<IVec3> = self.clusters().collect();
+        self.update(col_cache, clusters.into_iter().collect());
+    }
+
+    /// Work out `dirty` clusters again, and the entrances around them.
+    fn update(&mut self, col_cache: &CollisionGridCache, dirty: HashSet<IVec3>) {
+        let mut touched = HashSet::new();
+        let mut stairs_touched = HashSet::new();
+        for cluster in dirty.iter() {
+            let stairs = self.find_stairs(col_cache, *cluster);
+            for point in self.stairs.insert(*cluster, stairs).into_iter().flatten() {
+                self.unlink_stairs(point);
+            }
+            // Stairs from the levels above and below may lead into it.
+            stairs_touched.extend([*cluster - IVec3::Z, *cluster, *cluster + IVec3::Z]);
+            for (at, side) in [
+                (*cluster, Side::East),
+                (*cluster, Side::South),
+                (*cluster - IVec3::X, Side::East),
+                (*cluster - IVec3::Y, Side::South),
+            ] {
+                let entrances = self.find_entrances(col_cache, at, side);
+                self.set_entrances(col_cache, (at, side), entrances);
+                touched.insert(at);
+                touched.insert(at + next_along(side));
+            }
+        }
+        touched.retain(|cluster| self.clusters_contain(*cluster));
+        for cluster in touched {
+            let inner = self.connect_nodes(col_cache, cluster);
+            self.inner.insert(cluster, inner);
+        }
+        for cluster in stairs_touched {
+            self.link_stairs(col_cache, cluster);
+        }
+    }
+
+    /// Replace the entrances along a side, and the steps across them.
+    fn set_entrances(
+        &mut self,
+        col_cache: &CollisionGridCache,
+        key: (IVec3, Side),
+        entrances: Vec<(IVec3, IVec3)>,
+    ) {
+        for (from, to) in self.entrances.remove(&key).into_iter().flatten() {
+            self.unlink(from, to);
+            self.unlink(to, from);
+        }
+        for (from, to) in entrances.iter() {
+            self.link(col_cache, *from, *to);
+            self.link(col_cache, *to, *from);
+        }
+        self.entrances.insert(key, entrances);
+    }
+
+    /// Steps up and down the stairs of `cluster`, to those that can be stood
+    /// on.
+    fn link_stairs(&mut self, col_cache: &CollisionGridCache, cluster: IVec3) {
+        let stairs = self.stairs.get(&cluster).cloned().unwrap_or_default();
+        for point in stairs {
+            self.unlink_stairs(point);
+            for other in [point - IVec3::Z, point + IVec3::Z] {
+                if col_cache.stairs_connect(point, other) && passable(col_cache, other) {
+                    self.link(col_cache, point, other);
+                }
+            }
+        }
+    }
+
+    fn link(&mut self, col_cache: &CollisionGridCache, from: IVec3, to: IVec3) {
+        let step = col_cache.step_cost(from, to);
+        self.outer.entry(from).or_default().push((to, step));
+    }
+
+    fn unlink(&mut self, from: IVec3, to: IVec3) {
+        if let Some(steps) = self.outer.get_mut(&from) {
+            steps.retain(|(next, _)| *next != to);
+            if steps.is_empty() {
+                self.outer.remove(&from);
+            }
+        }
+    }
+
+    /// Drop the steps up and down from `point`.
+    fn unlink_stairs(&mut self, point: IVec3) {
+        for other in [point - IVec3::Z, point + IVec3::Z] {
+            self.unlink(point, other);
+        }
+    }
+
+    /// Work out the clusters `changes` touched again, or every cluster if
+    /// the map itself changed.
+    fn apply_changes<'c>(
+        &mut self,
+        col_cache: &CollisionGridCache,
+        changes: impl IntoIterator<Item = &'c CollisionChanged>,
+    ) {
+        let mut dirty = HashSet::new();
+        let mut rebuild = self.rect != *col_cache.rect() || self.levels != col_cache.levels();
+        for change in changes {
+            match change {
+                CollisionChanged::All => rebuild = true,
+                CollisionChanged::Area { rect, level } => {
+                    let min = self.cluster_of(rect.min.extend(*level as i32));
+                    let max = self.cluster_of((rect.max - IVec2::ONE).extend(*level as i32));
+                    for y in min.y..=max.y {
+                        for x in min.x..=max.x {
+                            dirty.insert(IVec3::new(x, y, *level as i32));
+                        }
+                    }
+                }
+            }
+        }
+        if rebuild {
+            self.rebuild(col_cache);
+            return;
+        }
+        dirty.retain(|cluster| self.clusters_contain(*cluster));
+        if !dirty.is_empty() {
+            self.update(col_cache, dirty);
+        }
+    }
+
+    fn clusters_contain(&self, cluster: IVec3) -> bool {
+        cluster.cmpge(IVec3::ZERO).all()
+            && cluster.z < self.levels as i32
+            && !self.cluster_rect(cluster).is_empty()
+    }
+
+    fn find_stairs(&self, col_cache: &CollisionGridCache, cluster: IVec3) -> Vec<IVec3> {
+        let rect = self.cluster_rect(cluster);
+        (rect.min.y..rect.max.y)
+            .flat_map(|y| (rect.min.x..rect.max.x).map(move |x| IVec3::new(x, y, cluster.z)))
+            .filter(|point| {
+                passable(col_cache, *point)
+                    && [*point - IVec3::Z, *point + IVec3::Z]
+                        .into_iter()
+                        .any(|other| col_cache.stairs_connect(*point, other))
+            })
+            .collect()
+    }
+
+    /// Openings along the `side` of `cluster`, as pairs of tiles on either
+    /// side of it.
+    fn find_entrances(
+        &self,
+        col_cache: &CollisionGridCache,
+        cluster: IVec3,
+        side: Side,
+    ) -> Vec<(IVec3, IVec3)> {
+        let next = cluster + next_along(side);
+        if !self.clusters_contain(cluster) || !self.clusters_contain(next) {
+            return Vec::new();
+        }
+        let rect = self.cluster_rect(cluster);
+        // Tiles along the side, inside the cluster, and the step across.
+        let (along, across): (Vec<IVec2>, IVec2) = match side {
+            Side::East => (
+                (rect.min.y..rect.max.y)
+                    .map(|y| IVec2::new(rect.max.x - 1, y))
+                    .collect(),
+                IVec2::X,
+            ),
+            Side::South => (
+                (rect.min.x..rect.max.x)
+                    .map(|x| IVec2::new(x, rect.max.y - 1))
+                    .collect(),
+                IVec2::Y,
+            ),
+        };
+        let open = |tile: &IVec2| {
+            passable(col_cache, tile.extend(cluster.z))
+                && passable(col_cache, (*tile + across).extend(cluster.z))
+        };
+        let mut entrances = Vec::new();
+        let crossing = |tile: &IVec2| {
+            col_cache.step_cost(tile.extend(cluster.z), (*tile + across).extend(cluster.z))
+        };
+        let mut add = |run: &[IVec2]| {
+            let mut picks = match run.len() {
+                0 => vec![],
+                len if len < WIDE_ENTRANCE => vec![run[len / 2]],
+                len => vec![run[0], run[len - 1]],
+            };
+            // Paths keep to cheap ground, e.g. a road, so cross where they do.
+            let cheapest = run.iter().min_by_key(|tile| OrderedFloat(crossing(tile)));
+            if let Some(cheapest) = cheapest
+                .filter(|cheapest| picks.iter().all(|pick| crossing(cheapest) < crossing(pick)))
+            {
+                picks.push(*cheapest);
+            }
+            for tile in picks {
+                entrances.push((tile.extend(cluster.z), (tile + across).extend(cluster.z)));
+            }
+        };
+        let mut start = 0;
+        for (idx, tile) in along.iter().enumerate() {
+            if !open(tile) {
+                add(&along[start..idx]);
+                start = idx + 1;
+            }
+        }
+        add(&along[start..]);
+        entrances
+    }
+
+    /// Costs between every pair of nodes of `cluster`, keeping inside it.
+    fn connect_nodes(
+        &self,
+        col_cache: &CollisionGridCache,
+        cluster: IVec3,
+    ) -> HashMap<IVec3, Vec<(IVec3, f32)>> {
+        let rect = self.cluster_rect(cluster);
+        let nodes = self.nodes(cluster);
+        nodes
+            .iter()
+            .map(|from| {
+                let reached = search_within(col_cache, rect, *from, None);
+                let edges = nodes
+                    .iter()
+                    .filter(|to| *to != from)
+                    .filter_map(|to| reached.get(to).map(|(cost, _)| (*to, *cost)))
+                    .collect();
+                (*from, edges)
+            })
+            .collect()
+    }
+
+    /// Find a path from `start` to `goal` over the clusters, returning the
+    /// waypoints to pass through in reverse, ending with `goal`.
+    pub fn find_path(
+        &self,
+        col_cache: &CollisionGridCache,
+        start: IVec3,
+        goal: IVec3,
+    ) -> Option<Vec<LevelPoint>> {
+        let start_cluster = self.cluster_of(start);
+        let goal_cluster = self.cluster_of(goal);
+        if !self.clusters_contain(start_cluster) || !self.clusters_contain(goal_cluster) {
+            return None;
+        }
+        // Join the start and goal to the nodes of their clusters.
+        let start_reached = search_within(col_cache, self.cluster_rect(start_cluster), start, None);
+        let goal_reached = search_within(col_cache, self.cluster_rect(goal_cluster), goal, None);
+        let start_nodes = self.nodes(start_cluster);
+        let goal_nodes = self.nodes(goal_cluster);
+        // Close by, going straight there may beat going through entrances.
+        let offset = start_cluster - goal_cluster;
+        let direct = (offset.z == 0 && offset.abs().max_element() <= 1)
+            .then(|| {
+                let rect = self
+                    .cluster_rect(start_cluster)
+                    .union(self.cluster_rect(goal_cluster));
+                search_within(col_cache, rect, start, Some(goal))
+            })
+            .and_then(|reached| reached.get(&goal).map(|(cost, _)| *cost));
+        let neighbors =
+            |point: IVec3| -> Vec<(IVec3, f32)> {
+                let inner = self
+                    .inner
+                    .get(&self.cluster_of(point))
+                    .and_then(|inner| inner.get(&point));
+                let outer = self.outer.get(&point);
+                let mut edges: Vec<(IVec3, f32)> =
+                    inner.into_iter().chain(outer).flatten().copied().collect();
+                if point == start {
+                    edges.extend(start_nodes.iter().filter_map(|node| {
+                        start_reached.get(node).map(|(cost, _)| (*node, *cost))
+                    }));
+                    edges.extend(direct.map(|cost| (goal, cost)));
+                }
+                if goal_nodes.contains(&point) {
+                    if let Some((cost, _)) = goal_reached.get(&point) {
+                        edges.push((goal, *cost));
+                    }
+                }
+                edges
+            };
+
+        let estimate = |point| octile_distance(point, goal) * col_cache.cheapest_move_cost();
+        let mut reached: HashMap<IVec3, (f32, IVec3)> = HashMap::from([(start, (0.0, start))]);
+        let mut to_explore = BinaryHeap::from([Reverse(FunctionalTuple(
+            OrderedFloat(estimate(start)),
+            start,
+        ))]);
+        while let Some(Reverse(FunctionalTuple(functional, point))) = to_explore.pop() {
+            if point == goal {
+                break;
+            }
+            let cost = reached[&point].0;
+            if functional.0 > cost + estimate(point) {
+                continue;
+            }
+            for (next, step) in neighbors(point) {
+                let cost = cost + step;
+                if reached.get(&next).is_some_and(|(known, _)| *known <= cost) {
+                    continue;
+                }
+                reached.insert(next, (cost, point));
+                to_explore.push(Reverse(FunctionalTuple(
+                    OrderedFloat(cost + estimate(next)),
+                    next,
+                )));
+            }
+        }
+
+        reached.get(&goal)?;
+        let mut waypoints = Vec::new();
+        let mut point = goal;
+        while point != start {
+            waypoints.push(LevelPoint::new(point.xy().as_vec2(), point.z as u32));
+            point = reached[&point].1;
+        }
+        Some(waypoints)
+    }
+}
+
+/// The cluster containing `tile`, counting from the one at `origin`.
+fn cluster_coord(origin: IVec2, tile: IVec2) -> IVec2 {
+    IVec2::new(
+        (tile.x - origin.x).div_euclid(CLUSTER_SIZE),
+        (tile.y - origin.y).div_euclid(CLUSTER_SIZE),
+    )
+}
+
+fn next_along(side: Side) -> IVec3 {
+    match side {
+        Side::East => IVec3::X,
+        Side::South => IVec3::Y,
+    }
+}
+
+fn passable(col_cache: &CollisionGridCache, point: IVec3) -> bool {
+    col_cache.collides(point) == Ok(false)
+}
+
+/// Cost of reaching each tile of `rect` on the level of `from`, and the tile
+/// it was reached from, stopping early once `to` is reached. Moves as A* on
+/// the [`CollisionGridCache`] does, minus the stairs.
+fn search_within(
+    col_cache: &CollisionGridCache,
+    rect: Rect2D,
+    from: IVec3,
+    to: Option<IVec3>,
+) -> HashMap<IVec3, (f32, IVec3)> {
+    let inside = |point: IVec3| {
+        rect.contains_exclusive_max(point.xy().as_vec2()) && passable(col_cache, point)
+    };
+    let mut reached = HashMap::from([(from, (0.0, from))]);
+    let mut to_explore = BinaryHeap::from([Reverse(FunctionalTuple(OrderedFloat(0.0), from))]);
+    while let Some(Reverse(FunctionalTuple(cost, point))) = to_explore.pop() {
+        if Some(point) == to {
+            break;
+        }
+        if cost.0 > reached[&point].0 {
+            continue;
+        }
+        for y in -1..=1 {
+            for x in -1..=1 {
+                let next = point + IVec3::new(x, y, 0);
+                if next == point || !inside(next) {
+                    continue;
+                }
+                // Diagonals can't cut corners.
+                if x != 0
+                    && y != 0
+                    && (!inside(point + IVec3::new(x, 0, 0))
+                        || !inside(point + IVec3::new(0, y, 0)))
+                {
+                    continue;
+                }
+                let cost = cost.0 + col_cache.step_cost(point, next);
+                if reached.get(&next).is_some_and(|(known, _)| *known <= cost) {
+                    continue;
+                }
+                reached.insert(next, (cost, point));
+                to_explore.push(Reverse(FunctionalTuple(OrderedFloat(cost), next)));
+            }
+        }
+    }
+    reached
+}
+
+/// Tiles from `from` to `to`, excluding `from`, in reverse. Keeps to the
+/// clusters the two are in, as the waypoints of an [`AbstractPath`] are
+/// either in the same cluster or neighboring ones.
+fn refine_segment(
+    col_cache: &CollisionGridCache,
+    from: IVec3,
+    to: IVec3,
+) -> Option<Vec<LevelPoint>> {
+    let point = |tile: IVec3| LevelPoint::new(tile.xy().as_vec2(), tile.z as u32);
+    if from.z != to.z {
+        return (col_cache.stairs_connect(from, to) && passable(col_cache, to))
+            .then(|| vec![point(to)]);
+    }
+    let cluster = |tile: IVec3| {
+        let origin = col_cache.rect().min;
+        let min = origin + cluster_coord(origin, tile.xy()) * CLUSTER_SIZE;
+        Rect2D::from_corners(min, min + IVec2::splat(CLUSTER_SIZE))
+    };
+    let rect = cluster(from).union(cluster(to));
+    let reached = search_within(col_cache, rect, from, Some(to));
+    reached.get(&to)?;
+    let mut steps = Vec::new();
+    let mut tile = to;
+    while tile != from {
+        steps.push(point(tile));
+        tile = reached[&tile].1;
+    }
+    Some(steps)
+}
+
+/// Keep the [`PathHierarchy`] in step with the [`CollisionGridCache`].
+fn sys_update_path_hierarchy(
+    mut hierarchy: ResMut<PathHierarchy>,
+    col_cache: Res<CollisionGridCache>,
+    mut changes: EventReader<CollisionChanged>,
+) {
+    hierarchy.apply_changes(&col_cache, changes.iter());
+}
+
+/// Refine [`AbstractPath`]s as their entities get near the end of the tiles
+/// refined so far, searching again for those which have been blocked.
+fn sys_refine_abstract_paths(
+    mut cmd: Commands,
+    col_cache: Res<CollisionGridCache>,
+    mut q: Query<(
+        Entity,
+        &Transform2D,
+        &Level,
+        &mut AbstractPath,
+        &mut MovePath,
+        &mut GoalLoc,
+    )>,
+) {
+    for (entity, transform, level, mut abstract_path, mut path, mut goal) in q.iter_mut() {
+        if abstract_path.waypoints.is_empty() {
+            cmd.entity(entity).remove::<AbstractPath>();
+            continue;
+        }
+        if path.steps.len() >= REFINE_AHEAD {
+            continue;
+        }
+        let at = transform.as_tile().extend(level.0 as i32);
+        if !abstract_path.refine(&col_cache, at, &mut path.steps) {
+            path.steps.clear();
+            goal.0 = Some(abstract_path.goal);
+            cmd.entity(entity).remove::<AbstractPath>();
+        }
+    }
+}
+
+/// Calculate a path from `start` on `level` to `goal` over the
+/// [`PathHierarchy`]. Returns the first steps, as
+/// [`calc_path`](super::pathing::calc_path) does, and the rest of the path to
+/// refine as the mover walks them.
+///
+/// Movers are taken to be a single tile.
+pub fn calc_hierarchical_path(
+    col_cache: &CollisionGridCache,
+    hierarchy: &PathHierarchy,
+    start: &Transform2D,
+    level: u32,
+    goal: LevelPoint,
+) -> Option<(Vec<LevelPoint>, AbstractPath)> {
+    let start_tile = start.as_tile().extend(level as i32);
+    let mut abstract_path = AbstractPath {
+        goal,
+        waypoints: Vec::new(),
+    };
+    if !needs_search(col_cache, start_tile, goal.tile())? {
+        return Some((Vec::new(), abstract_path));
+    }
+    abstract_path.waypoints = hierarchy.find_path(col_cache, start_tile, goal.tile())?;
+    let mut steps = vec![LevelPoint::new(start_tile.xy().as_vec2(), level)];
+    abstract_path
+        .refine(col_cache, start_tile, &mut steps)
+        .then_some((steps, abstract_path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::script::flow_field::FlowField;
+    use crate::script::pathing::tests::{cheapest, fixtures, walk};
+    use crate::script::pathing::{calc_path, PathAlgorithm};
+
+    /// Every step of a path found over `hierarchy`, refined all the way to
+    /// the goal.
+    fn refine_all(
+        col_cache: &CollisionGridCache,
+        hierarchy: &PathHierarchy,
+        mover: &Transform2D,
+        level: u32,
+        goal: LevelPoint,
+    ) -> Option<Vec<LevelPoint>> {
+        let start = mover.as_tile().extend(level as i32);
+        let (mut steps, mut abstract_path) =
+            calc_hierarchical_path(col_cache, hierarchy, mover, level, goal)?;
+        while let Some(to) = abstract_path.waypoints.pop() {
+            let from = steps.first().map_or(start, |step| step.tile());
+            steps.splice(0..0, refine_segment(col_cache, from, to.tile()).unwrap());
+        }
+        Some(steps)
+    }
+
+    /// Every step between nodes of `hierarchy`, in order, to compare
+    /// hierarchies by.
+    fn steps(hierarchy: &PathHierarchy) -> Vec<String> {
+        let inner = hierarchy.inner.values().flatten();
+        let mut steps: Vec<String> = inner
+            .chain(hierarchy.outer.iter())
+            .flat_map(|(from, edges)| {
+                edges
+                    .iter()
+                    .map(move |(to, cost)| format!("{} -> {}: {:.3}", from, to, cost))
+            })
+            .collect();
+        steps.sort();
+        steps
+    }
+
+    /// Put a wall on every tile of each fixture in turn, then take it away,
+    /// checking updating the hierarchy each time matches working it out
+    /// afresh.
+    #[test]
+    fn updates_match_rebuild() {
+        for mut fixture in fixtures() {
+            let mut hierarchy = PathHierarchy::default();
+            hierarchy.rebuild(&fixture.cache);
+            let original = steps(&hierarchy);
+            let rect = *fixture.cache.rect();
+            let tiles: Vec<IVec3> = (0..fixture.cache.levels() as i32)
+                .flat_map(|z| {
+                    (rect.min.y..rect.max.y).flat_map(move |y| {
+                        (rect.min.x..rect.max.x).map(move |x| IVec3::new(x, y, z))
+                    })
+                })
+                .filter(|tile| passable(&fixture.cache, *tile))
+                .collect();
+            let wall = Entity::from_raw(1_000_000);
+            for tile in tiles {
+                let change = fixture.cache.place_obstacle(tile, wall);
+                hierarchy.apply_changes(&fixture.cache, [&change]);
+                let mut rebuilt = PathHierarchy::default();
+                rebuilt.rebuild(&fixture.cache);
+                assert_eq!(
+                    steps(&hierarchy),
+                    steps(&rebuilt),
+                    "{}: wall on {}",
+                    fixture.name,
+                    tile
+                );
+
+                let change = fixture.cache.remove_obstacle(wall).unwrap();
+                hierarchy.apply_changes(&fixture.cache, [&change]);
+                assert_eq!(
+                    steps(&hierarchy),
+                    original,
+                    "{}: wall off {}",
+                    fixture.name,
+                    tile
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn hierarchical_near_a_star() {
+        for fixture in fixtures() {
+            let mut hierarchy = PathHierarchy::default();
+            hierarchy.rebuild(&fixture.cache);
+            for (mover, level, goal) in fixture.trips() {
+                let start = mover.as_tile().extend(level as i32);
+                let path = refine_all(&fixture.cache, &hierarchy, &mover, level, goal);
+                let expected = cheapest(&fixture.cache, start, goal);
+                assert_eq!(path.is_some(), expected.is_some(), "{}", fixture.name);
+                // Both end on the goal and start where the mover stands.
+                let a_star = calc_path(&fixture.cache, &mover, level, goal, PathAlgorithm::AStar);
+                let ends = |path: &Vec<LevelPoint>| {
+                    (path.first().unwrap().tile(), path.last().unwrap().tile())
+                };
+                assert_eq!(
+                    path.as_ref().map(ends),
+                    a_star.as_ref().map(ends),
+                    "{}",
+                    fixture.name
+                );
+                if let (Some(path), Some(expected)) = (path, expected) {
+                    let cost = walk(&fixture.cache, start, &path, goal);
+                    assert!(
+                        cost <= expected * 1.1 + 1e-3,
+                        "{}: hierarchical path costs {}, cheapest is {}",
+                        fixture.name,
+                        cost,
+                        expected
+                    );
+                }
+            }
+        }
+    }
+
+    /// Movers following flow fields are only given one if the hierarchy finds
+    /// a way there, so it must find one from wherever the field reaches.
+    #[test]
+    fn finds_paths_where_flow_fields_reach() {
+        for fixture in fixtures() {
+            let mut hierarchy = PathHierarchy::default();
+            hierarchy.rebuild(&fixture.cache);
+            let rect = *fixture.cache.rect();
+            for goal in fixture.map.goals.iter().map(|goal| goal.tile()) {
+                let field = FlowField::new(&fixture.cache, goal);
+                for z in 0..fixture.cache.levels() as i32 {
+                    for (x, y) in (rect.min.y..rect.max.y)
+                        .flat_map(|y| (rect.min.x..rect.max.x).map(move |x| (x, y)))
+                    {
+                        let start = IVec3::new(x, y, z);
+                        if !passable(&fixture.cache, start) {
+                            continue;
+                        }
+                        assert_eq!(
+                            hierarchy.find_path(&fixture.cache, start, goal).is_some(),
+                            field.reaches(&fixture.cache, start),
+                            "{}: from {} to {}",
+                            fixture.name,
+                            start,
+                            goal
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// Wall off the middle of the cheapest path of each fixture, then check
+    /// updating the hierarchy finds the same paths as working it out afresh.
+    #[test]
+    fn hierarchy_updates_after_wall() {
+        for mut fixture in fixtures() {
+            let mut hierarchy = PathHierarchy::default();
+            hierarchy.rebuild(&fixture.cache);
+            let Some((mover, level, goal)) = fixture.trips().next() else {
+                continue;
+            };
+            let Some(path) = calc_path(&fixture.cache, &mover, level, goal, PathAlgorithm::AStar)
+            else {
+                continue;
+            };
+            let wall = path[path.len() / 2].tile();
+            if wall == mover.as_tile().extend(level as i32) {
+                continue;
+            }
+            let change = fixture
+                .cache
+                .place_obstacle(wall, Entity::from_raw(1_000_000));
+            hierarchy.apply_changes(&fixture.cache, [&change]);
+            let mut rebuilt = PathHierarchy::default();
+            rebuilt.rebuild(&fixture.cache);
+
+            let start = mover.as_tile().extend(level as i32);
+            let updated = refine_all(&fixture.cache, &hierarchy, &mover, level, goal);
+            let expected = refine_all(&fixture.cache, &rebuilt, &mover, level, goal);
+            assert_eq!(updated.is_some(), expected.is_some(), "{}", fixture.name);
+            if let (Some(updated), Some(expected)) = (updated, expected) {
+                // Walking it checks it doesn't run into the new wall.
+                let cost = walk(&fixture.cache, start, &updated, goal);
+                let expected = walk(&fixture.cache, start, &expected, goal);
+                assert!(
+                    (cost - expected).abs() < 1e-3,
+                    "{}: updated path costs {}, rebuilt {}",
+                    fixture.name,
+                    cost,
+                    expected
+                );
+            }
+        }
+    }
+}
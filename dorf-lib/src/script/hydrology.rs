@@ -0,0 +1,216 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashSet},
+};
+
+use ordered_float::OrderedFloat;
+
+use crate::prelude::*;
+
+use super::local_map::Biome;
+use super::terrain::{BiomeTable, TerrainFields};
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct HydrologySettings {
+    /// Chance for each tile high and wet enough to hold a spring.
+    pub spring_chance: f64,
+    /// Springs only form on land at least this high...
+    pub spring_elevation: f32,
+    /// ...and at least this wet.
+    pub spring_moisture: f32,
+    /// How far rivers erode the terrain they flow through...
+    pub carve_depth: f32,
+    /// ...and how many tiles out their valleys reach, growing shallower
+    /// further from the river. Lowered banks can turn mountain to land or
+    /// land to sand when classified.
+    pub valley_width: u32,
+    /// Lakes stop filling once they cover this many tiles, ending their river.
+    pub max_lake_size: usize,
+    /// Terrain is generated this many tiles past each side of a chunk, so
+    /// rivers flowing in from neighboring chunks are traced too. Rivers from
+    /// springs further away than this won't be seen by the chunk.
+    pub margin: u32,
+}
+
+impl Default for HydrologySettings {
+    fn default() -> Self {
+        Self {
+            spring_chance: 0.002,
+            spring_elevation: 0.55,
+            spring_moisture: 0.45,
+            carve_depth: 0.04,
+            valley_width: 2,
+            max_lake_size: 400,
+            margin: 48,
+        }
+    }
+}
+
+/// Run water over `fields`, returning the [`Biome::River`] and [`Biome::Lake`]
+/// tiles. River valleys are carved into the elevation field.
+///
+/// Rivers stop at the edge of `fields`, so only tiles at least
+/// [`HydrologySettings::margin`] from it are sure to see every river reaching
+/// them. Callers pad the area they need by the margin, then overlapping areas
+/// agree on the water they share as long as no river comes from further away.
+pub fn run_hydrology(
+    fields: &mut TerrainFields,
+    settings: &HydrologySettings,
+    table: &BiomeTable,
+    seed: u64,
+) -> Grid2D<Option<Biome>> {
+    let rect = *fields.rect();
+    let mut water = Grid2D::new(rect.min, rect.size().as_uvec2(), None);
+    let springs: Vec<IVec2> = fields
+        .elevation
+        .iter()
+        .filter(|(tile, elevation)| {
+            **elevation >= settings.spring_elevation
+                && *fields.moisture.get(*tile).unwrap() >= settings.spring_moisture
+                && tile_roll(seed, *tile) < settings.spring_chance
+        })
+        .map(|(tile, _)| tile)
+        .collect();
+
+    // Trace every river before carving, so the rivers don't depend on the
+    // order they were traced in.
+    for spring in springs {
+        trace_river(&fields.elevation, &mut water, settings, table, spring);
+    }
+    carve_valleys(&mut fields.elevation, &water, settings);
+    water
+}
+
+/// Lower the terrain around each river by up to [`HydrologySettings::carve_depth`],
+/// keeping the deepest cut where valleys overlap.
+fn carve_valleys(
+    elevation: &mut Grid2D<f32>,
+    water: &Grid2D<Option<Biome>>,
+    settings: &HydrologySettings,
+) {
+    let rect = *elevation.rect();
+    let width = settings.valley_width as i32;
+    let mut carved = Grid2D::new(rect.min, rect.size().as_uvec2(), 0.0f32);
+    for (river, _) in water.iter().filter(|(_, w)| **w == Some(Biome::River)) {
+        for y in -width..=width {
+            for x in -width..=width {
+                let offset = IVec2::new(x, y);
+                let Ok(depth) = carved.get_mut(river + offset) else {
+                    continue;
+                };
+                let falloff = 1.0 - offset.abs().max_element() as f32 / (width + 1) as f32;
+                *depth = depth.max(settings.carve_depth * falloff);
+            }
+        }
+    }
+    for (tile, depth) in carved.iter() {
+        *elevation.get_mut(tile).unwrap() -= depth;
+    }
+}
+
+fn neighbors(point: IVec2) -> impl Iterator<Item = IVec2> {
+    (-1..=1)
+        .flat_map(move |y| (-1..=1).map(move |x| point + IVec2::new(x, y)))
+        .filter(move |p| *p != point)
+}
+
+/// Follow the steepest descent from `spring` until reaching the ocean, other
+/// water, or the edge of the area. Basins along the way fill into lakes.
+fn trace_river(
+    elevation: &Grid2D<f32>,
+    water: &mut Grid2D<Option<Biome>>,
+    settings: &HydrologySettings,
+    table: &BiomeTable,
+    spring: IVec2,
+) {
+    let mut cur = spring;
+    // Rivers only run downhill, this can't be hit but guards against loops.
+    for _ in 0..elevation.rect().size().x * elevation.rect().size().y {
+        let Ok(height) = elevation.get(cur).copied() else {
+            return;
+        };
+        if height < table.sea_level || water.get(cur).unwrap().is_some() {
+            return;
+        }
+        water.set(cur, Some(Biome::River));
+
+        let lowest = neighbors(cur)
+            .filter_map(|p| Some((p, *elevation.get(p).ok()?)))
+            .min_by(|l, r| l.1.total_cmp(&r.1));
+        cur = match lowest {
+            Some((next, h)) if h < height => next,
+            _ => match fill_lake(elevation, water, settings.max_lake_size, cur) {
+                Some(spill) => spill,
+                None => return,
+            },
+        };
+    }
+}
+
+/// Flood the basin at `basin` until the water finds a way out, returning
+/// where it spills over. Returns `None` if the lake grew too large, or filled
+/// the area, without spilling.
+fn fill_lake(
+    elevation: &Grid2D<f32>,
+    water: &mut Grid2D<Option<Biome>>,
+    max_size: usize,
+    basin: IVec2,
+) -> Option<IVec2> {
+    let mut level = *elevation.get(basin).unwrap();
+    let mut lake = vec![basin];
+    let mut visited = HashSet::from([basin]);
+    let mut frontier = BinaryHeap::new();
+    let mut spill = None;
+    let mut push_neighbors = |tile: IVec2, frontier: &mut BinaryHeap<_>| {
+        for n in neighbors(tile) {
+            if let Ok(h) = elevation.get(n) {
+                if visited.insert(n) {
+                    frontier.push(Reverse((OrderedFloat(*h), n.x, n.y)));
+                }
+            }
+        }
+    };
+    push_neighbors(basin, &mut frontier);
+    while let Some(Reverse((OrderedFloat(h), x, y))) = frontier.pop() {
+        let tile = IVec2::new(x, y);
+        if h < level {
+            spill = Some(tile);
+            break;
+        }
+        if lake.len() >= max_size {
+            break;
+        }
+        level = h;
+        lake.push(tile);
+        push_neighbors(tile, &mut frontier);
+    }
+    for tile in lake {
+        water.set(tile, Some(Biome::Lake));
+    }
+    spill
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valleys_deepen_toward_the_river() {
+        let settings = HydrologySettings::default();
+        let mut elevation = Grid2D::new(IVec2::ZERO, UVec2::splat(9), 0.5);
+        let mut water = Grid2D::new(IVec2::ZERO, UVec2::splat(9), None);
+        for y in 0..9 {
+            water.set(IVec2::new(4, y), Some(Biome::River));
+        }
+        carve_valleys(&mut elevation, &water, &settings);
+
+        let row: Vec<f32> = (0..9)
+            .map(|x| *elevation.get(IVec2::new(x, 4)).unwrap())
+            .collect();
+        assert_eq!(row[4], 0.5 - settings.carve_depth);
+        assert!(row[3] < row[2] && row[2] < 0.5);
+        assert_eq!(row[3], row[5]);
+        assert_eq!(row[1], 0.5);
+        assert_eq!(row[0], 0.5);
+    }
+}
@@ -0,0 +1,260 @@
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use ordered_float::OrderedFloat;
+
+use crate::prelude::*;
+
+use super::pathing::{octile_distance, CollisionGridCache, FunctionalTuple, LevelPoint};
+
+/// Every direction a mover can step in on a level.
+const DIRECTIONS: [IVec2; 8] = [
+    IVec2::new(1, 0),
+    IVec2::new(-1, 0),
+    IVec2::new(0, 1),
+    IVec2::new(0, -1),
+    IVec2::new(1, 1),
+    IVec2::new(-1, 1),
+    IVec2::new(1, -1),
+    IVec2::new(-1, -1),
+];
+
+/// Jump Point Search over the [`CollisionGridCache`], moving by the same rules
+/// as its A* search: diagonals never cut corners and levels are joined by
+/// stairs.
+///
+/// Rather than pushing every neighbor of a point, it jumps along rows,
+/// columns and diagonals until reaching a point the cheapest path might turn
+/// at, i.e. one beside an obstacle, on stairs or the goal. Only those points
+/// are pushed, so crossing open ground takes a handful of them.
+///
+/// Where tiles cost more or less to cross the cheapest path may turn too, so
+/// every point beside a tile of another `move_cost` is a jump point, searched
+/// onward in every direction. Only the edges of each stretch of terrain are
+/// searched like A*.
+struct JumpPointSearch<'i> {
+    col_cache: &'i CollisionGridCache,
+    /// The shape of the mover, only the tiles it could stand on are searched.
+    start: &'i Transform2D,
+    goal: IVec3,
+    /// Cost to reach each jump point, and the jump point it was reached from.
+    reached: SparseGrid3D<(OrderedFloat<f32>, IVec3)>,
+    to_explore: BinaryHeap<Reverse<FunctionalTuple>>,
+}
+
+impl<'i> JumpPointSearch<'i> {
+    fn new(col_cache: &'i CollisionGridCache, start: &'i Transform2D, goal: IVec3) -> Self {
+        Self {
+            col_cache,
+            start,
+            goal,
+            reached: SparseGrid3D::new(*col_cache.rect(), col_cache.levels()),
+            to_explore: default(),
+        }
+    }
+
+    /// Whether the mover could stand on `point`, which is out of bounds
+    /// otherwise.
+    #[inline]
+    fn passable(&self, point: IVec3) -> bool {
+        !self
+            .col_cache
+            .would_collide_if_moved(self.start, &point)
+            .unwrap_or(true)
+    }
+
+    fn has_stairs(&self, point: IVec3) -> bool {
+        [point - IVec3::Z, point + IVec3::Z]
+            .into_iter()
+            .any(|other| self.col_cache.stairs_connect(point, other))
+    }
+
+    /// Whether moving along `dir`, a row or a column, to `point` passed an
+    /// obstacle beside it which the path may need to turn around.
+    fn has_forced_neighbor(&self, point: IVec3, dir: IVec2) -> bool {
+        self.forced_sides(point, dir).next().is_some()
+    }
+
+    /// Sides of `point` which can only be reached cheapest through it, having
+    /// moved to it along `dir`, a row or a column.
+    fn forced_sides(&self, point: IVec3, dir: IVec2) -> impl Iterator<Item = IVec2> + '_ {
+        let side = IVec2::new(dir.y, dir.x);
+        let behind = point - dir.extend(0);
+        [side, -side].into_iter().filter(move |side| {
+            self.passable(point + side.extend(0)) && !self.passable(behind + side.extend(0))
+        })
+    }
+
+    /// Whether a diagonal step along `dir` from `point` is allowed, which needs
+    /// both of the tiles beside it clear.
+    fn can_step_diagonally(&self, point: IVec3, dir: IVec2) -> bool {
+        self.passable(point + IVec3::new(dir.x, 0, 0))
+            && self.passable(point + IVec3::new(0, dir.y, 0))
+            && self.passable(point + dir.extend(0))
+    }
+
+    /// Whether any tile around `point` costs more or less to cross than it.
+    fn on_cost_edge(&self, point: IVec3) -> bool {
+        let cost = self.col_cache.move_cost(point);
+        DIRECTIONS
+            .iter()
+            .any(|dir| self.col_cache.move_cost(point + dir.extend(0)) != cost)
+    }
+
+    fn is_jump_point(&self, point: IVec3) -> bool {
+        point == self.goal || self.has_stairs(point) || self.on_cost_edge(point)
+    }
+
+    /// Cost of the steps along a row, column or diagonal from `from` to `to`.
+    fn jump_cost(&self, mut from: IVec3, to: IVec3) -> f32 {
+        let step = (to - from).signum();
+        let mut cost = 0.0;
+        while from != to {
+            cost += self.col_cache.step_cost(from, from + step);
+            from += step;
+        }
+        cost
+    }
+
+    /// Never more than the cost of the cheapest path from `point` to the goal.
+    fn estimate(&self, point: IVec3) -> f32 {
+        octile_distance(point, self.goal) * self.col_cache.cheapest_move_cost()
+    }
+
+    /// Move from `point` along `dir` until reaching a jump point, or `None`
+    /// if something is in the way first.
+    fn jump(&self, point: IVec3, dir: IVec2) -> Option<IVec3> {
+        if dir.x != 0 && dir.y != 0 {
+            self.jump_diagonally(point, dir)
+        } else {
+            self.jump_straight(point, dir)
+        }
+    }
+
+    fn jump_straight(&self, mut point: IVec3, dir: IVec2) -> Option<IVec3> {
+        loop {
+            point += dir.extend(0);
+            if !self.passable(point) {
+                return None;
+            }
+            if self.is_jump_point(point) || self.has_forced_neighbor(point, dir) {
+                return Some(point);
+            }
+        }
+    }
+
+    fn jump_diagonally(&self, mut point: IVec3, dir: IVec2) -> Option<IVec3> {
+        loop {
+            if !self.can_step_diagonally(point, dir) {
+                return None;
+            }
+            point += dir.extend(0);
+            // Stop wherever turning onto the row or column would find a jump
+            // point further along.
+            if self.is_jump_point(point)
+                || self.jump_straight(point, IVec2::new(dir.x, 0)).is_some()
+                || self.jump_straight(point, IVec2::new(0, dir.y)).is_some()
+            {
+                return Some(point);
+            }
+        }
+    }
+
+    /// Directions worth jumping in from `point`, having arrived along `dir`.
+    /// Every direction is tried from the start, after climbing stairs and
+    /// beside a change in cost.
+    fn directions(&self, point: IVec3, dir: Option<IVec2>) -> Vec<IVec2> {
+        match dir {
+            None => DIRECTIONS.to_vec(),
+            Some(_) if self.on_cost_edge(point) => DIRECTIONS.to_vec(),
+            Some(dir) if dir.x != 0 && dir.y != 0 => {
+                vec![IVec2::new(dir.x, 0), IVec2::new(0, dir.y), dir]
+            }
+            Some(dir) => {
+                let mut dirs = vec![dir];
+                for side in self.forced_sides(point, dir) {
+                    dirs.push(side);
+                    dirs.push(dir + side);
+                }
+                dirs
+            }
+        }
+    }
+
+    fn push(&mut self, from: IVec3, point: IVec3, cost: f32) {
+        let Ok(known) = self.reached.get(point) else {
+            return;
+        };
+        if known.is_some_and(|(known, _)| known.0 <= cost) {
+            return;
+        }
+        self.reached.set(point, (OrderedFloat(cost), from));
+        let functional = cost + self.estimate(point);
+        self.to_explore
+            .push(Reverse(FunctionalTuple(OrderedFloat(functional), point)));
+    }
+
+    fn search(&mut self, start: IVec3) -> Option<()> {
+        self.reached.set(start, (OrderedFloat(0.0), start));
+        self.to_explore.push(Reverse(FunctionalTuple(
+            OrderedFloat(self.estimate(start)),
+            start,
+        )));
+        while let Some(Reverse(FunctionalTuple(functional, point))) = self.to_explore.pop() {
+            if point == self.goal {
+                return Some(());
+            }
+            let (cost, from) = *self.reached.get(point).unwrap().unwrap();
+            // Reached more cheaply since being pushed, and explored then.
+            if functional.0 > cost.0 + self.estimate(point) {
+                continue;
+            }
+            let dir = (from != point && from.z == point.z).then(|| (point - from).xy().signum());
+            for dir in self.directions(point, dir) {
+                if let Some(next) = self.jump(point, dir) {
+                    self.push(point, next, cost.0 + self.jump_cost(point, next));
+                }
+            }
+            for stairs in [point - IVec3::Z, point + IVec3::Z] {
+                if self.col_cache.stairs_connect(point, stairs) && self.passable(stairs) {
+                    self.push(
+                        point,
+                        stairs,
+                        cost.0 + self.col_cache.step_cost(point, stairs),
+                    );
+                }
+            }
+        }
+        None
+    }
+
+    /// Every tile from the goal to `start`, filling in those jumped over.
+    fn collect_path(&self, start: IVec3) -> Vec<LevelPoint> {
+        let mut point = self.goal;
+        let mut path = vec![LevelPoint::new(point.xy().as_vec2(), point.z as u32)];
+        while point != start {
+            let (_, from) = *self.reached.get(point).unwrap().unwrap();
+            let step = (from - point).signum();
+            while point != from {
+                point += step;
+                path.push(LevelPoint::new(point.xy().as_vec2(), point.z as u32));
+            }
+        }
+        path
+    }
+}
+
+/// Calculate the optimal path from `start` on `level` to `goal` using Jump
+/// Point Search, returned like [`calc_path`](super::pathing::calc_path).
+///
+/// Paths cost the same as those found by A*.
+pub fn calc_jump_point_path(
+    col_cache: &CollisionGridCache,
+    start: &Transform2D,
+    level: u32,
+    goal: LevelPoint,
+) -> Option<Vec<LevelPoint>> {
+    let start_tile = start.as_tile().extend(level as i32);
+    let mut state = JumpPointSearch::new(col_cache, start, goal.tile());
+    state.search(start_tile)?;
+    Some(state.collect_path(start_tile))
+}
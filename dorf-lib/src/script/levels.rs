@@ -0,0 +1,127 @@
+use bevy::input::ButtonState;
+
+use crate::prelude::*;
+
+use super::local_map::{Biome, BiomeGrid, MapSettings};
+use super::tiles::TileTable;
+
+/// How much the level below is darkened when seen through [`Biome::Open`] tiles.
+const BELOW_DIM: f32 = 0.5;
+
+pub fn add_level_systems(app: &mut App, enabled: bool) {
+    app.init_resource::<ViewLevel>()
+        .add_system(handle_level_keys)
+        .add_system(sys_draw_view_level.after(handle_level_keys))
+        .add_system(sys_hide_other_levels.after(handle_level_keys));
+}
+
+/// The level an entity is on. Levels count down from the surface at 0.
+#[derive(
+    Component, Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize,
+)]
+pub struct Level(pub u32);
+
+/// The level shown by the camera. Entities with a [`Level`] are only drawn
+/// while it's in view.
+#[derive(Resource, Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ViewLevel {
+    pub level: u32,
+    /// Draw the level below, dimmed, through [`Biome::Open`] tiles.
+    pub show_below: bool,
+}
+
+impl Default for ViewLevel {
+    fn default() -> Self {
+        Self {
+            level: 0,
+            show_below: true,
+        }
+    }
+}
+
+fn handle_level_keys(
+    mut input: EventReader<KeyboardInput>,
+    focus: Res<InputFocus>,
+    map: Res<MapSettings>,
+    mut view: ResMut<ViewLevel>,
+) {
+    if *focus != InputFocus::World {
+        input.clear();
+        return;
+    }
+    for e in input.iter() {
+        if e.state != ButtonState::Pressed {
+            continue;
+        }
+        match e.key_code {
+            Some(KeyCode::Comma | KeyCode::PageUp) => {
+                view.level = view.level.saturating_sub(1);
+            }
+            Some(KeyCode::Period | KeyCode::PageDown) => {
+                view.level = (view.level + 1).min(map.depth);
+            }
+            Some(KeyCode::B) => view.show_below = !view.show_below,
+            _ => (),
+        }
+    }
+}
+
+/// Texture for `tile` on `level`, looking through open tiles if enabled.
+fn level_texture(
+    grid: &BiomeGrid,
+    tiles: &TileTable,
+    tile: IVec2,
+    biome: Biome,
+    view: &ViewLevel,
+) -> CharTexture {
+    if biome == Biome::Open && view.show_below {
+        if let Some(below) = grid.biome_at_level(tile, view.level + 1) {
+            return tiles.texture(below).dimmed(BELOW_DIM);
+        }
+    }
+    tiles.texture(biome)
+}
+
+/// Redraw each chunk with the level in view, when the view or tiles change or
+/// the chunk is first spawned.
+fn sys_draw_view_level(
+    view: Res<ViewLevel>,
+    tiles: Res<TileTable>,
+    mut chunks: Query<(Ref<BiomeGrid>, &mut CharMesh)>,
+) {
+    for (grid, mut mesh) in chunks.iter_mut() {
+        if !view.is_changed() && !tiles.is_changed() && !grid.is_added() {
+            continue;
+        }
+        let textures = grid
+            .iter_level(view.level)
+            .map(|(tile, biome)| level_texture(&grid, &tiles, tile, biome, &view));
+        // Chunks without the level are left blank.
+        mesh.texture_vec.fill(default());
+        for (texture, new) in mesh.texture_vec.iter_mut().zip(textures) {
+            *texture = new;
+        }
+    }
+}
+
+fn sys_hide_other_levels(
+    mut cmd: Commands,
+    view: Res<ViewLevel>,
+    q: Query<(Entity, &Level, Option<&Hidden>)>,
+    changed: Query<(), Changed<Level>>,
+) {
+    if !view.is_changed() && changed.is_empty() {
+        return;
+    }
+    for (entity, level, hidden) in q.iter() {
+        match (level.0 == view.level, hidden.is_some()) {
+            (true, true) => {
+                cmd.entity(entity).remove::<Hidden>();
+            }
+            (false, false) => {
+                cmd.entity(entity).insert(Hidden);
+            }
+            _ => (),
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use num_traits::FromPrimitive;
+
+use crate::prelude::*;
+
+use super::local_map::{GenStage, MapGenStatus};
+
+/// Over the map and the world map, under dialogs.
+const LOADING_SCREEN_Z: f32 = 850.0;
+const BAR_WIDTH: usize = 24;
+
+pub fn add_loading_screen_systems(app: &mut App, enabled: bool) {
+    app.add_system(sys_update_loading_screen.in_base_set(CoreSet::PostUpdate));
+}
+
+/// Tag for the box shown while chunks in view are being generated.
+#[derive(Component, Debug, Default)]
+struct LoadingScreen;
+
+/// Lay out the loading screen, returning its size and textures.
+fn layout_loading_screen(status: &MapGenStatus) -> (UVec2, Vec<CharTexture>) {
+    let fraction = status.fraction().clamp(0.0, 1.0);
+    let filled = (fraction * BAR_WIDTH as f32).round() as usize;
+    let mut lines = vec![
+        String::new(),
+        format!(
+            "[{}{}] {:3.0}%",
+            "█".repeat(filled),
+            "░".repeat(BAR_WIDTH - filled),
+            fraction * 100.0
+        ),
+        format!(
+            "{} of {} chunks",
+            status.finished,
+            status.finished + status.pending()
+        ),
+        String::new(),
+    ];
+    for (stage, count) in status.stages.iter().enumerate() {
+        if *count > 0 {
+            let stage = GenStage::from_usize(stage).unwrap();
+            lines.push(format!("{:<12}{:>4}", format!("{:?}", stage), count));
+        }
+    }
+    lines.push(String::new());
+
+    let title = " Generating map ";
+    let inner = lines
+        .iter()
+        .map(|line| line.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max(title.chars().count() + 1);
+    // Border and a column of padding on each side.
+    let width = inner + 4;
+
+    let plain = |c| CharTexture::from_char(c);
+    let mut textures = Vec::with_capacity(width * (lines.len() + 2));
+    textures.push(plain('┌'));
+    textures.push(plain('─'));
+    textures.extend(title.chars().map(plain));
+    textures.extend((0..width - title.chars().count() - 3).map(|_| plain('─')));
+    textures.push(plain('┐'));
+    for line in lines.iter() {
+        textures.push(plain('│'));
+        textures.push(plain(' '));
+        textures.extend(line.chars().map(plain));
+        textures.extend((0..inner - line.chars().count()).map(|_| plain(' ')));
+        textures.push(plain(' '));
+        textures.push(plain('│'));
+    }
+    textures.push(plain('└'));
+    textures.extend((0..width - 2).map(|_| plain('─')));
+    textures.push(plain('┘'));
+
+    (UVec2::new(width as u32, lines.len() as u32 + 2), textures)
+}
+
+/// Show the loading screen in the middle of the camera while any chunk in
+/// view is being generated.
+fn sys_update_loading_screen(
+    mut cmd: Commands,
+    status: Res<MapGenStatus>,
+    camera: Res<TerminalCamera2D>,
+    mut screen: Query<
+        (Entity, &mut CharMesh, &mut Transform2D, &mut UIComponent),
+        With<LoadingScreen>,
+    >,
+) {
+    let screen = screen.get_single_mut();
+    if status.in_view == 0 {
+        if let Ok((entity, ..)) = screen {
+            cmd.entity(entity).despawn();
+        }
+        return;
+    }
+    let (scale, textures) = layout_loading_screen(&status);
+    let offset = (camera.dim().as_ivec2() - scale.as_ivec2()).max(IVec2::ZERO) / 2;
+    let local_pos = offset.as_vec2().extend(LOADING_SCREEN_Z);
+    let Ok((_, mut mesh, mut transform, mut ui_component)) = screen else {
+        // Placed straight away, as generating may only take a few frames.
+        cmd.spawn((
+            LoadingScreen,
+            CharMesh {
+                texture_vec: textures,
+            },
+            Transform2D {
+                scale,
+                loc: local_pos + *camera.loc(),
+            },
+            UIComponent::new(local_pos),
+        ));
+        return;
+    };
+    if mesh.texture_vec != textures {
+        mesh.texture_vec = textures;
+    }
+    if transform.scale != scale {
+        transform.scale = scale;
+    }
+    if ui_component.local_pos != local_pos {
+        ui_component.local_pos = local_pos;
+        // Touch the transform so the UI layer repositions us.
+        transform.set_changed();
+    }
+}
@@ -1,61 +1,154 @@
-use std::cmp::max;
+use std::{
+    cmp::max,
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicBool, AtomicU8, Ordering},
+        Arc,
+    },
+};
 
 use bevy::tasks::{AsyncComputeTaskPool, Task, TaskPool};
-use noise::{
-    utils::{ImageRenderer, NoiseMapBuilder, PlaneMapBuilder},
-    NoiseFn,
-};
+use futures_lite::future;
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
 use strum::EnumCount;
 
 use crate::prelude::*;
 
-pub const LOCAL_MAP_DIMMENSIONS: UVec2 = UVec2 { x: 10, y: 50 };
-//
-///// Number of points to seed with
-//pub const INIT_RATIO: f32 = 0.05;
-//
-pub fn add_local_map_systems(app: &mut App, enabled: bool) {
-    app.add_system(sys_spawn_map_on_finish)
-        .add_system(sys_prepare_gen_map_task)
-        .add_startup_system(sys_init_spawn_gen_req);
-
-    //.add_startup_system(gen_map)
+use super::hydrology::run_hydrology;
+use super::levels::Level;
+use super::map_image::ImportedSurface;
+use super::pathing::{ImmobileObstacle, LayerableCollider};
+use super::prefabs::{place_prefabs, Prefabs, SPAWN_CLEARANCE};
+use super::terrain::{TerrainFields, TerrainSettings, TERRAIN_SEED};
+use super::text_map::{TextMap, WallRun};
+use super::tiles::TileTable;
+use super::underground::generate_underground;
+
+/// The area covered by the local map. Picked before the app starts, e.g. from
+/// the command line, and read by everything that needs the map's bounds.
+#[derive(Resource, Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct MapSettings {
+    /// Tile at the topleft corner of the map.
+    pub origin: IVec2,
+    /// Size of the map in tiles.
+    pub size: UVec2,
+    /// Number of levels beneath the surface. Levels count down from the
+    /// surface at 0, so the deepest level is `depth`.
+    pub depth: u32,
+    /// The map is generated in chunks of this many tiles.
+    pub chunk_size: UVec2,
+    /// Chunks within this many tiles of the camera's view are generated.
+    pub load_distance: u32,
+    /// Chunks further than this many tiles from the camera's view are
+    /// unloaded. Kept larger than `load_distance` so chunks on the border
+    /// aren't constantly regenerated.
+    pub unload_distance: u32,
 }
 
-pub fn spawn_random_global_map() {
-    //let grid: Grid2D<Option<Biome>> = Grid2D::new(IVec2::ZERO, LOCAL_MAP_DIMMENSIONS, None);
+impl Default for MapSettings {
+    fn default() -> Self {
+        Self {
+            origin: IVec2::ZERO,
+            size: UVec2::new(10, 50),
+            depth: 8,
+            chunk_size: UVec2::splat(32),
+            load_distance: 16,
+            unload_distance: 48,
+        }
+    }
+}
 
-    //let num_init_nodes = max(
-    //    1,
-    //    (INIT_RATIO * (LOCAL_MAP_DIMMENSIONS.x * LOCAL_MAP_DIMMENSIONS.y) as f32) as usize,
-    //);
+impl MapSettings {
+    pub fn rect(&self) -> Rect2D {
+        Rect2D::from_corners(self.origin, self.origin + self.size.as_ivec2())
+    }
 
-    //for i in 0..num_init_nodes {
-    //    let x = fastrand::i32(0..LOCAL_MAP_DIMMENSIONS.x as i32);
-    //    let y = fastrand::i32(0..LOCAL_MAP_DIMMENSIONS.y as i32);
+    /// The chunk containing `tile`.
+    pub fn chunk_for_tile(&self, tile: IVec2) -> IVec2 {
+        let offset = tile - self.origin;
+        let size = self.chunk_size.max(UVec2::ONE).as_ivec2();
+        IVec2::new(offset.x.div_euclid(size.x), offset.y.div_euclid(size.y))
+    }
 
-    //    let biome: Option<Biome> = fastrand::u8(0..Biome::_Max as u8).try_into().ok();
+    /// The tiles covered by `chunk`, clipped to the map's bounds.
+    pub fn chunk_rect(&self, chunk: IVec2) -> Rect2D {
+        let size = self.chunk_size.max(UVec2::ONE).as_ivec2();
+        let min = self.origin + chunk * size;
+        Rect2D::from_corners(min, min + size).intersect(self.rect())
+    }
 
-    //    grid.set(IVec2 { x, y }, biome);
-    //}
-    // TODO: constraint based generation:
+    /// Every chunk of the map overlapping `rect`.
+    pub fn chunks_in(&self, rect: Rect2D) -> impl Iterator<Item = IVec2> {
+        let rect = rect.intersect(self.rect());
+        let empty = rect.is_empty();
+        let min = self.chunk_for_tile(rect.min);
+        let max = self.chunk_for_tile(rect.max - IVec2::ONE);
+        (min.y..=max.y)
+            .flat_map(move |y| (min.x..=max.x).map(move |x| IVec2::new(x, y)))
+            .filter(move |_| !empty)
+    }
+}
+pub fn add_local_map_systems(app: &mut App, enabled: bool) {
+    app.init_resource::<MapSettings>()
+        .init_resource::<TerrainSettings>()
+        .init_resource::<TileTable>()
+        .init_resource::<Prefabs>()
+        .init_resource::<LoadedChunks>()
+        .init_resource::<MapGenStatus>()
+        .add_event::<RegenerateMap>()
+        .add_system(sys_spawn_map_on_finish)
+        .add_system(sys_prepare_gen_map_task)
+        .add_system(sys_update_map_gen_status.after(sys_spawn_map_on_finish))
+        .add_system(sys_regenerate_map)
+        // Unload after the other systems, so they never touch a despawned chunk.
+        .add_system(
+            sys_load_chunks_near_camera
+                .after(sys_spawn_map_on_finish)
+                .after(sys_prepare_gen_map_task)
+                .after(sys_regenerate_map),
+        );
 
-    // - Start out by picking a couple of points to begin constraint solving for
-    // - Iteratively begin placing nodes that meet constraints
+    //.add_startup_system(gen_map)
 }
 
-#[derive(Component, Debug, Copy, Clone, FromPrimitive, strum_macros::EnumCount)]
-enum Biome {
+/// The kind of each tile. How each looks and behaves is defined in the
+/// [`TileTable`].
+#[derive(
+    Component,
+    Debug,
+    Copy,
+    Clone,
+    PartialEq,
+    Eq,
+    Hash,
+    FromPrimitive,
+    strum_macros::EnumCount,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub enum Biome {
     //Hill,
     Forest,
     Grassland,
-    //Desert,
+    Desert,
+    Tundra,
     //Beach,
     Ocean,
     Sand,
     Mountain,
+    River,
+    Lake,
+    Soil,
+    Rock,
+    Ore,
+    Cave,
+    /// Nothing to stand on, looks down onto the level below.
+    Open,
+    /// Connects to stairs on the levels directly above and below.
+    Stairs,
+    /// Paved ground, quicker to cross than any other.
+    Road,
     Null,
 }
 
@@ -67,53 +160,208 @@ struct BiomeBundle {
 }
 
 impl BiomeBundle {
-    fn new(biome: Biome, location: Vec2) -> BiomeBundle {
+    fn new(biome: Biome, location: Vec2, tiles: &TileTable) -> BiomeBundle {
         let mut transform = Transform2D {
             scale: UVec2::splat(1),
             loc: location.xyy(),
         };
         transform.loc.z = 0.0;
         BiomeBundle {
-            texture: biome.texture(),
+            texture: tiles.texture(biome),
             biome,
             transform,
         }
     }
 }
 
-impl Biome {
-    fn texture(&self) -> CharTexture {
-        let n = |c, r, g, b| CharTexture::new(c, RGB::new_f32(r, g, b));
-        match self {
-            Biome::Forest => n('|', 0.0, 0.3, 0.3),
-            Biome::Ocean => n('~', 0.0, 0.0, 0.7),
-            Biome::Sand => n(':', 1.00, 0.85, 0.10),
-            Biome::Mountain => n('^', 0.85, 0.85, 0.85),
-            Biome::Grassland => n('^', 0.53, 1.00, 0.30),
-            //Biome::Beach => n('.', 1.00, 0.85, 0.10),
-            Biome::Null => n(' ', 0.00, 0.00, 0.00),
-            //Biome::Desert => todo!(),
-            //Biome::Hill => todo!(),
+struct MapGenResult {
+    map: Map,
+    /// Walls of the prefabs placed in the chunk.
+    walls: Vec<WallRun>,
+}
+
+/// The stages a chunk goes through while being generated, in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive, strum_macros::EnumCount)]
+pub enum GenStage {
+    /// Waiting for a thread to pick it up.
+    Queued,
+    /// Sampling elevation, moisture and temperature.
+    Terrain,
+    Hydrology,
+    /// Picking the biome of each tile.
+    Biomes,
+    Underground,
+    Prefabs,
+    Done,
+}
+
+/// Shared between a chunk's generation task and the main thread, so one can
+/// follow and the other cancel.
+#[derive(Debug, Default)]
+pub struct GenProgress {
+    stage: AtomicU8,
+    cancelled: AtomicBool,
+}
+
+impl GenProgress {
+    pub fn stage(&self) -> GenStage {
+        GenStage::from_u8(self.stage.load(Ordering::Relaxed)).unwrap()
+    }
+
+    /// Stop generating at the start of the next stage.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Move on to `stage`, `None` if generation was cancelled.
+    fn enter(&self, stage: GenStage) -> Option<()> {
+        if self.is_cancelled() {
+            return None;
+        }
+        self.stage.store(stage as u8, Ordering::Relaxed);
+        Some(())
+    }
+}
+
+/// How far generating the loaded chunks has got, updated every frame.
+#[derive(Resource, Debug, Default, Clone, PartialEq)]
+pub struct MapGenStatus {
+    /// Chunks generated since generation last went idle.
+    pub finished: usize,
+    /// Chunks still being generated, counted by the [`GenStage`] they're in.
+    pub stages: [usize; GenStage::COUNT],
+    /// How many of those are in the camera's view.
+    pub in_view: usize,
+}
+
+impl MapGenStatus {
+    pub fn pending(&self) -> usize {
+        self.stages.iter().sum()
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.pending() == 0
+    }
+
+    /// Share of the work done since generation last went idle, in `[0, 1]`.
+    pub fn fraction(&self) -> f32 {
+        let total = self.finished + self.pending();
+        if total == 0 {
+            return 1.0;
         }
+        let last = (GenStage::COUNT - 1) as f32;
+        let partial: f32 = self
+            .stages
+            .iter()
+            .enumerate()
+            .map(|(stage, count)| *count as f32 * stage as f32 / last)
+            .sum();
+        (self.finished as f32 + partial) / total as f32
     }
 }
-struct MapGenResult {}
 
+/// Generation is cancelled when the task is dropped, e.g. by its chunk being
+/// unloaded.
 #[derive(Component)]
 struct MapGenTask {
-    task: Option<Task<Map>>,
+    task: Option<Task<Option<MapGenResult>>>,
+    progress: Arc<GenProgress>,
+}
+
+impl Drop for MapGenTask {
+    fn drop(&mut self) {
+        // The task is taken once it has finished.
+        if self.task.is_some() {
+            log::info!("Cancelling map generation at {:?}", self.progress.stage());
+            self.progress.cancel();
+        }
+    }
 }
 
 #[derive(Component)]
 struct MapGenTaskRequest {}
 
-#[derive(Component, Debug, Clone)]
-struct BiomeGrid(Grid2D<Biome>);
+/// Marks the entity holding a chunk of the map, both while it is being
+/// generated and once its [`BiomeGrid`] is spawned.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MapChunk {
+    pub coord: IVec2,
+}
+
+/// Marks an entity spawned along with a generated chunk, such as a prefab's
+/// wall. It is despawned when the chunk is unloaded.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ChunkMember {
+    pub coord: IVec2,
+}
+
+/// The entity of every chunk which is loaded or being generated.
+#[derive(Resource, Debug, Default)]
+pub struct LoadedChunks {
+    chunks: HashMap<IVec2, Entity>,
+    /// The settings the chunks were loaded with, everything is unloaded when
+    /// they change.
+    settings: Option<MapSettings>,
+}
+
+/// Event to generate every loaded chunk again, e.g. once the seed or the
+/// [`TerrainSettings`] have changed.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RegenerateMap;
+
+/// Every level of a chunk, the surface being level 0.
+#[derive(Component, Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct BiomeGrid(Grid3D<Biome>);
 
 impl BiomeGrid {
-    fn new(dim: UVec2) -> Self {
-        Self(Grid2D::<Biome>::new(IVec2::ZERO, dim, Biome::Null))
+    pub fn new(levels: Grid3D<Biome>) -> Self {
+        Self(levels)
+    }
+
+    pub fn rect(&self) -> &Rect2D {
+        self.0.rect()
+    }
+
+    /// Number of levels, including the surface.
+    pub fn levels(&self) -> u32 {
+        self.0.levels()
+    }
+
+    /// Iterate over the surface.
+    pub fn iter(&self) -> impl Iterator<Item = (IVec2, Biome)> + '_ {
+        self.iter_level(0)
+    }
+
+    pub fn iter_level(&self, level: u32) -> impl Iterator<Item = (IVec2, Biome)> + '_ {
+        self.0
+            .iter_level(level)
+            .map(|(point, biome)| (point, *biome))
     }
+
+    /// Returns the [`Biome`] at `tile` on the surface, or `None` if the grid
+    /// doesn't cover it.
+    pub fn biome_at(&self, tile: IVec2) -> Option<Biome> {
+        self.biome_at_level(tile, 0)
+    }
+
+    pub fn biome_at_level(&self, tile: IVec2, level: u32) -> Option<Biome> {
+        self.0.get(tile.extend(level as i32)).ok().copied()
+    }
+}
+
+/// Look up the [`Biome`] at `tile` on `level` across every spawned [`BiomeGrid`].
+pub fn biome_at<'a>(
+    grids: impl IntoIterator<Item = &'a BiomeGrid>,
+    tile: IVec2,
+    level: u32,
+) -> Option<Biome> {
+    grids
+        .into_iter()
+        .find_map(|grid| grid.biome_at_level(tile, level))
 }
 
 #[derive(Bundle)]
@@ -122,139 +370,356 @@ struct Map {
     biome_grid: BiomeGrid,
 }
 
-// Testing start code to kick off generating the map.
-pub fn sys_init_spawn_gen_req(cnt: Local<usize>, mut cmds: Commands) {
-    cmds.spawn(MapGenTaskRequest {});
+/// Request chunks as the camera approaches them and unload those it has left
+/// far behind. Everything is unloaded if the [`MapSettings`] change.
+fn sys_load_chunks_near_camera(
+    mut cmds: Commands,
+    mut loaded: ResMut<LoadedChunks>,
+    settings: Res<MapSettings>,
+    camera: Res<TerminalCamera2D>,
+    members: Query<(Entity, &ChunkMember)>,
+) {
+    let mut unloaded = Vec::new();
+    if loaded.settings.as_ref() != Some(&*settings) {
+        for (chunk, entity) in loaded.chunks.drain() {
+            cmds.entity(entity).despawn();
+            unloaded.push(chunk);
+        }
+        loaded.settings = Some(settings.clone());
+    }
+
+    let view = camera.transform().as_rect2d();
+    let keep = view.inset(settings.unload_distance as i32);
+    loaded.chunks.retain(|chunk, entity| {
+        let rect = settings.chunk_rect(*chunk);
+        if keep.intersect(rect).is_empty() {
+            cmds.entity(*entity).despawn();
+            unloaded.push(*chunk);
+            return false;
+        }
+        true
+    });
+    for (entity, member) in members.iter() {
+        if unloaded.contains(&member.coord) {
+            cmds.entity(entity).despawn();
+        }
+    }
+
+    for chunk in settings.chunks_in(view.inset(settings.load_distance as i32)) {
+        loaded.chunks.entry(chunk).or_insert_with(|| {
+            cmds.spawn((MapChunk { coord: chunk }, MapGenTaskRequest {}))
+                .id()
+        });
+    }
+}
+
+/// Movers are part of the local map, so they go along with the old one
+/// rather than being left standing in the new terrain.
+fn sys_regenerate_map(
+    mut cmds: Commands,
+    mut events: EventReader<RegenerateMap>,
+    mut loaded: ResMut<LoadedChunks>,
+    movers: Query<Entity, With<LayerableCollider>>,
+) {
+    if events.iter().count() == 0 {
+        return;
+    }
+    // Forgetting what the chunks were loaded with unloads all of them.
+    loaded.settings = None;
+    for entity in movers.iter() {
+        cmds.entity(entity).despawn();
+    }
+}
+
+/// Replace every loaded chunk with `chunks`, e.g. those of a save, rather
+/// than generating them. Chunks which aren't given are generated as usual.
+pub fn restore_chunks(world: &mut World, chunks: impl IntoIterator<Item = (IVec2, BiomeGrid)>) {
+    let settings = world.resource::<MapSettings>().clone();
+    let old: Vec<Entity> = world
+        .resource_mut::<LoadedChunks>()
+        .chunks
+        .drain()
+        .map(|(_, entity)| entity)
+        .collect();
+    for entity in old {
+        world.despawn(entity);
+    }
+    let chunks: HashMap<IVec2, Entity> = chunks
+        .into_iter()
+        .map(|(coord, grid)| {
+            (
+                coord,
+                world.spawn((MapChunk { coord }, Map::new(grid))).id(),
+            )
+        })
+        .collect();
+    *world.resource_mut::<LoadedChunks>() = LoadedChunks {
+        chunks,
+        settings: Some(settings),
+    };
 }
 
 // When we spawn the map, we need to do it asynchronously so as not to block the main thread.
-fn sys_prepare_gen_map_task(req_q: Query<(Entity, &MapGenTaskRequest)>, mut cmds: Commands) {
-    for (entity, req) in req_q.iter() {
+#[allow(clippy::too_many_arguments)]
+fn sys_prepare_gen_map_task(
+    req_q: Query<(Entity, &MapChunk), With<MapGenTaskRequest>>,
+    rng: Res<SimRng>,
+    map_settings: Res<MapSettings>,
+    settings: Res<TerrainSettings>,
+    tiles: Res<TileTable>,
+    prefabs: Res<Prefabs>,
+    imported: Option<Res<ImportedSurface>>,
+    text_map: Option<Res<TextMap>>,
+    mut cmds: Commands,
+) {
+    for (entity, chunk) in req_q.iter() {
         let pool = AsyncComputeTaskPool::get();
-        //let task = pool.spawn(async move { gen_map_lite() });
-        let task = pool.spawn(async move { gen_map() });
+        let progress = Arc::new(GenProgress::default());
+        let job = MapGenJob {
+            // Every chunk shares a seed, noise is sampled in world coordinates
+            // so neighboring chunks line up.
+            seed: rng.derive_seed(TERRAIN_SEED),
+            rect: map_settings.chunk_rect(chunk.coord),
+            depth: map_settings.depth,
+            settings: settings.clone(),
+            tiles: tiles.clone(),
+            prefabs: prefabs.clone(),
+            keep_clear: Rect2D::from_corners(map_settings.origin, map_settings.origin + IVec2::ONE)
+                .inset(SPAWN_CLEARANCE),
+            imported: imported.as_ref().map(|imported| imported.0.clone()),
+            text_map: text_map.as_ref().map(|text_map| text_map.levels.clone()),
+            progress: progress.clone(),
+        };
+        let task = pool.spawn(async move { job.run() });
 
-        cmds.entity(entity).insert(MapGenTask { task: Some(task) });
+        cmds.entity(entity).insert(MapGenTask {
+            task: Some(task),
+            progress,
+        });
         cmds.entity(entity).remove::<MapGenTaskRequest>();
     }
 }
 
-fn sys_spawn_map_on_finish(mut cmds: Commands, mut q: Query<(Entity, &mut MapGenTask)>) {
-    for (entity, mut task) in q.iter_mut() {
+/// Spawn each chunk once it's generated. Prefab walls are left out wherever
+/// a mover already stands.
+fn sys_spawn_map_on_finish(
+    mut cmds: Commands,
+    mut status: ResMut<MapGenStatus>,
+    mut q: Query<(Entity, &MapChunk, &mut MapGenTask)>,
+    movers: Query<(&Transform2D, &Level), With<LayerableCollider>>,
+) {
+    let mut occupied: Option<HashSet<IVec3>> = None;
+    for (entity, chunk, mut task) in q.iter_mut() {
         if task.task.as_mut().unwrap().is_finished() {
-            cmds.entity(entity).despawn();
-            let pool = AsyncComputeTaskPool::get();
-            let task = task.task.take().unwrap();
-            pool.scope(|s| {
-                s.spawn_on_scope(async {
-                    let map = task.await;
-                    cmds.spawn(map);
-                })
+            // Already finished, so this won't block.
+            let result = future::block_on(task.task.take().unwrap());
+            cmds.entity(entity).remove::<MapGenTask>();
+            let Some(result) = result else {
+                continue;
+            };
+            status.finished += 1;
+            cmds.entity(entity).insert(result.map);
+            let occupied = occupied.get_or_insert_with(|| {
+                movers
+                    .iter()
+                    .map(|(transform, level)| transform.as_tile().extend(level.0 as i32))
+                    .collect()
             });
+            for wall in result
+                .walls
+                .into_iter()
+                .flat_map(|wall| wall.split_around(occupied))
+            {
+                cmds.spawn((
+                    wall.texture,
+                    wall.transform,
+                    Level(wall.level),
+                    ImmobileObstacle,
+                    ChunkMember { coord: chunk.coord },
+                ));
+            }
         }
     }
 }
 
-fn gen_map_lite() -> Map {
-    render_map_for_fn(|_x, _y| fastrand::f32())
+/// Count the chunks being generated by stage, for [`MapGenStatus`].
+fn sys_update_map_gen_status(
+    mut status: ResMut<MapGenStatus>,
+    settings: Res<MapSettings>,
+    camera: Res<TerminalCamera2D>,
+    requests: Query<&MapChunk, With<MapGenTaskRequest>>,
+    tasks: Query<(&MapChunk, &MapGenTask)>,
+) {
+    let mut stages = [0; GenStage::COUNT];
+    stages[GenStage::Queued as usize] = requests.iter().len();
+    for (_, task) in tasks.iter() {
+        stages[task.progress.stage() as usize] += 1;
+    }
+    let view = camera.transform().as_rect2d();
+    let in_view = requests
+        .iter()
+        .chain(tasks.iter().map(|(chunk, _)| chunk))
+        .filter(|chunk| !settings.chunk_rect(chunk.coord).intersect(view).is_empty())
+        .count();
+    let finished = if stages.iter().sum::<usize>() == 0 {
+        0
+    } else {
+        status.finished
+    };
+    status.set_if_neq(MapGenStatus {
+        finished,
+        stages,
+        in_view,
+    });
 }
 
-fn render_map_for_fn<F>(rand_fn: F) -> Map
-where
-    F: Fn(u32, u32) -> f32,
-{
-    // For now just generate random characters for the size of the map.
-    let mut bg = BiomeGrid::new(LOCAL_MAP_DIMMENSIONS);
-    let mut mesh = CharMeshTransform::new(Transform2D {
-        scale: bg.0.rect().size().as_uvec2(),
-        loc: Vec3::ZERO,
-    });
-    mesh.set_z_level(-1.0);
-    for x in 0..LOCAL_MAP_DIMMENSIONS.x {
-        for y in 0..LOCAL_MAP_DIMMENSIONS.y {
-            let biome = Biome::from_f32(rand_fn(x, y) * (Biome::COUNT - 1) as f32).unwrap();
-            bg.0.set(IVec2::new(x as i32, y as i32), biome);
-            *mesh.get_mut(x as i32, y as i32) = biome.texture();
+impl Map {
+    fn new(biome_grid: BiomeGrid) -> Self {
+        let rect = *biome_grid.rect();
+        let mut mesh = CharMeshTransform::new(Transform2D {
+            scale: rect.size().as_uvec2(),
+            loc: rect.min.as_vec2().extend(0.0),
+        });
+        mesh.set_z_level(-1.0);
+        // Left blank, filled with whichever level is in view once spawned.
+        Map { mesh, biome_grid }
+    }
+}
+
+/// Everything needed to generate a chunk, moved into its task.
+struct MapGenJob {
+    seed: u64,
+    rect: Rect2D,
+    depth: u32,
+    settings: TerrainSettings,
+    tiles: TileTable,
+    prefabs: Prefabs,
+    /// Prefabs aren't placed here.
+    keep_clear: Rect2D,
+    imported: Option<Arc<Grid2D<Biome>>>,
+    text_map: Option<Arc<Grid3D<Biome>>>,
+    progress: Arc<GenProgress>,
+}
+
+impl MapGenJob {
+    /// Generate the chunk covering `rect`, stage by stage, `None` if it was
+    /// cancelled along the way. Every level is taken from `text_map` instead
+    /// when that covers the whole chunk, otherwise the surface is taken from
+    /// `imported` when that does.
+    ///
+    /// Prefabs are only placed where the surface was generated.
+    fn run(&self) -> Option<MapGenResult> {
+        let rect = self.rect;
+        let progress = &self.progress;
+        let covers = |other: &Rect2D| other.intersect(rect) == rect;
+        if let Some(levels) = self.text_map.as_ref().filter(|l| covers(l.rect())) {
+            log::info!("Using text map for map {:?}", rect);
+            progress.enter(GenStage::Done)?;
+            return Some(MapGenResult {
+                map: Map::new(BiomeGrid::new(levels.crop(rect))),
+                walls: Vec::new(),
+            });
         }
+        let imported = self.imported.as_ref().filter(|s| covers(s.rect()));
+        let surface = match imported {
+            Some(surface) => {
+                log::info!("Using imported surface for map {:?}", rect);
+                surface.crop(rect)
+            }
+            None => self.gen_surface()?,
+        };
+        progress.enter(GenStage::Underground)?;
+        let mut levels = generate_underground(
+            &surface,
+            self.depth,
+            &self.settings.underground,
+            &self.tiles,
+            self.seed,
+        );
+        let mut walls = Vec::new();
+        if imported.is_none() {
+            progress.enter(GenStage::Prefabs)?;
+            walls = place_prefabs(
+                &mut levels,
+                &self.prefabs,
+                self.keep_clear,
+                mix(self.seed, 8),
+            );
+        }
+        progress.enter(GenStage::Done)?;
+        Some(MapGenResult {
+            map: Map::new(BiomeGrid::new(levels)),
+            walls,
+        })
     }
-    Map {
-        biome_grid: bg,
-        mesh,
+
+    fn gen_surface(&self) -> Option<Grid2D<Biome>> {
+        let (seed, rect, settings) = (self.seed, self.rect, &self.settings);
+        log::info!("Generating map {:?} with seed {}", rect, seed);
+        self.progress.enter(GenStage::Terrain)?;
+        // Generate past the edges so water flowing in from neighbors is included.
+        let padded = rect.inset(settings.hydrology.margin as i32);
+        let mut fields = TerrainFields::generate(settings, seed, padded);
+        self.progress.enter(GenStage::Hydrology)?;
+        let water = run_hydrology(&mut fields, &settings.hydrology, &settings.biomes, seed);
+        self.progress.enter(GenStage::Biomes)?;
+        let mut biomes = fields.classify(&settings.biomes);
+        for (tile, water) in water.iter() {
+            if let Some(water) = water {
+                biomes.set(tile, *water);
+            }
+        }
+        Some(biomes.crop(rect))
     }
 }
 
-fn gen_map() -> Map {
-    log::info!("Generating map!");
-    use noise::{utils::*, *};
-    // Base wood texture. Uses concentric cylinders aligned on the z-axis, like a log.
-    let base_wood = Cylinders::new().set_frequency(16.0);
-    //render_noise(&base_wood, "0_base_wood.png");
-
-    let src = base_wood.clone();
-    let planar_texture = PlaneMapBuilder::<_, 2>::new(src)
-        .set_size(
-            LOCAL_MAP_DIMMENSIONS.x as usize,
-            LOCAL_MAP_DIMMENSIONS.y as usize,
-        )
-        .build();
-    return render_map_for_fn(|x, y| planar_texture[(x as usize, y as usize)].fract().abs() as f32);
-
-    //// Basic Multifractal noise to use for the wood grain.
-    //let wood_grain_noise = BasicMulti::<Perlin>::new(0)
-    //    .set_frequency(48.0)
-    //    .set_persistence(0.5)
-    //    .set_lacunarity(2.20703125)
-    //    .set_octaves(3);
-    //render_noise(&wood_grain_noise, "1_grain_noise.png");
-
-    //// Stretch the perlin noise in the same direction as the center of the log. Should
-    //// produce a nice wood-grain texture.
-    //let scaled_base_wood_grain = ScalePoint::new(wood_grain_noise).set_z_scale(0.25);
-    //render_noise(&scaled_base_wood_grain, "2_scaled_grain_noise.png");
-
-    //// Scale the wood-grain values so that they can be added to the base wood texture.
-    //let wood_grain = ScaleBias::new(scaled_base_wood_grain)
-    //    .set_scale(0.25)
-    //    .set_bias(0.125);
-    //render_noise(&wood_grain, "3_biased_wood_grain.png");
-
-    //// Add the wood grain texture to the base wood texture.
-    //let combined_wood = Add::new(base_wood, wood_grain);
-    //render_noise(&combined_wood, "4_combined.png");
-
-    //// Slightly perturb the wood to create a more realistic texture.
-    //let perturbed_wood = Turbulence::<_, Perlin>::new(combined_wood)
-    //    .set_seed(1)
-    //    .set_frequency(4.0)
-    //    .set_power(1.0 / 256.0)
-    //    .set_roughness(4);
-    //render_noise(&perturbed_wood, "5_pertubed.png");
-
-    //// Cut the wood texture a small distance from the center of the log.
-    //let translated_wood = TranslatePoint::new(perturbed_wood).set_y_translation(1.48);
-
-    //// Set the cut on a angle to produce a more interesting texture.
-    //let rotated_wood = RotatePoint::new(translated_wood).set_angles(84.0, 0.0, 0.0, 0.0);
-
-    //// Finally, perturb the wood texture again to produce the final texture.
-    //let final_wood = Turbulence::<_, Perlin>::new(rotated_wood)
-    //    .set_seed(2)
-    //    .set_frequency(2.0)
-    //    .set_power(1.0 / 64.0)
-    //    .set_roughness(4);
-
-    //let planar_texture = PlaneMapBuilder::<_, 2>::new(final_wood)
-    //    .set_size(1024, 1024)
-    //    .build();
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generate(rect: Rect2D) -> BiomeGrid {
+        let job = MapGenJob {
+            seed: 11,
+            rect,
+            depth: 4,
+            settings: default(),
+            tiles: default(),
+            // Prefabs are placed per chunk, only the terrain is seamless.
+            prefabs: Prefabs(Vec::new()),
+            keep_clear: Rect2D::default(),
+            imported: None,
+            text_map: None,
+            progress: default(),
+        };
+        job.run().unwrap().map.biome_grid
+    }
 
-fn render_noise<SourceModule>(src: &SourceModule, string: &str)
-where
-    SourceModule: NoiseFn<f64, 2>,
-{
-    let planar_texture = PlaneMapBuilder::<_, 2>::new(src.clone())
-        .set_size(1024, 1024)
-        .build();
-    let mut renderer = ImageRenderer::new(); //.set_gradien0t(wood_gradient);
-    renderer.render(&planar_texture).write_to_file(string);
+    /// Chunks generated apart match one generated across both, so no seam
+    /// shows where they meet.
+    #[test]
+    fn chunks_are_seamless() {
+        let left = generate(Rect2D::new(0, 0, 48, 48));
+        let right = generate(Rect2D::new(48, 0, 96, 48));
+        let both = generate(Rect2D::new(0, 0, 96, 48));
+        let mut water = 0;
+        for level in 0..both.levels() {
+            for y in 0..48 {
+                for (chunk, x) in [(&left, 47), (&right, 48)] {
+                    let tile = IVec2::new(x, y);
+                    let biome = chunk.biome_at_level(tile, level);
+                    assert_eq!(
+                        biome,
+                        both.biome_at_level(tile, level),
+                        "{} on level {}",
+                        tile,
+                        level
+                    );
+                    if matches!(biome, Some(Biome::River | Biome::Lake)) {
+                        water += 1;
+                    }
+                }
+            }
+        }
+        assert!(water > 0, "no water crosses the border");
+    }
 }
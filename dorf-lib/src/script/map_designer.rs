@@ -0,0 +1,265 @@
+use bevy::input::ButtonState;
+
+use crate::prelude::*;
+
+use super::local_map::RegenerateMap;
+use super::terrain::{NoiseLayer, TerrainSettings};
+
+const MAP_DESIGNER_Z: f32 = 700.0;
+const PANEL_BG: RGB = RGB::new(20, 20, 40);
+/// Frequencies are scaled by this much each step.
+const FREQUENCY_STEP: f64 = 1.25;
+const MAX_OCTAVES: usize = 16;
+/// Biome thresholds are moved by this much each step.
+const LEVEL_STEP: f32 = 0.01;
+
+/// Lets designers browse seeds and tweak the terrain generator without
+/// recompiling. `G` opens a panel showing the seed and generator parameters,
+/// each of which regenerates the map as it is changed, and `R` in the panel
+/// regenerates it with a new seed. Regenerating despawns every mover, so
+/// neither works outside the panel.
+pub fn add_map_designer_systems(app: &mut App, enabled: bool) {
+    app.init_resource::<MapDesignerState>()
+        .add_system(sys_update_map_designer_panel)
+        .add_system(handle_map_designer_keys.in_base_set(CoreSet::PostUpdate));
+}
+
+#[derive(Resource, Debug, Default)]
+struct MapDesignerState {
+    /// Index into [`PARAMS`] of the parameter picked in the panel.
+    selected: usize,
+}
+
+#[derive(Component, Debug, Default)]
+struct MapDesignerPanel;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Elevation,
+    Moisture,
+    Temperature,
+}
+
+impl Field {
+    fn layer(self, settings: &TerrainSettings) -> &NoiseLayer {
+        match self {
+            Field::Elevation => &settings.elevation,
+            Field::Moisture => &settings.moisture,
+            Field::Temperature => &settings.temperature,
+        }
+    }
+
+    fn layer_mut(self, settings: &mut TerrainSettings) -> &mut NoiseLayer {
+        match self {
+            Field::Elevation => &mut settings.elevation,
+            Field::Moisture => &mut settings.moisture,
+            Field::Temperature => &mut settings.temperature,
+        }
+    }
+}
+
+/// A parameter of the generator shown in the panel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Param {
+    Seed,
+    Frequency(Field),
+    Octaves(Field),
+    SeaLevel,
+    CoastLevel,
+    MountainLevel,
+    LapseRate,
+}
+
+const PARAMS: [Param; 11] = [
+    Param::Seed,
+    Param::Frequency(Field::Elevation),
+    Param::Octaves(Field::Elevation),
+    Param::Frequency(Field::Moisture),
+    Param::Octaves(Field::Moisture),
+    Param::Frequency(Field::Temperature),
+    Param::Octaves(Field::Temperature),
+    Param::SeaLevel,
+    Param::CoastLevel,
+    Param::MountainLevel,
+    Param::LapseRate,
+];
+
+impl Param {
+    fn label(self) -> String {
+        match self {
+            Param::Seed => "seed".to_string(),
+            Param::Frequency(field) => format!("{:?} frequency", field).to_lowercase(),
+            Param::Octaves(field) => format!("{:?} octaves", field).to_lowercase(),
+            Param::SeaLevel => "sea level".to_string(),
+            Param::CoastLevel => "coast level".to_string(),
+            Param::MountainLevel => "mountain level".to_string(),
+            Param::LapseRate => "lapse rate".to_string(),
+        }
+    }
+
+    fn value(self, settings: &TerrainSettings, seed: u64) -> String {
+        match self {
+            Param::Seed => seed.to_string(),
+            Param::Frequency(field) => format!("{:.5}", field.layer(settings).frequency),
+            Param::Octaves(field) => field.layer(settings).octaves.to_string(),
+            Param::SeaLevel => format!("{:.2}", settings.biomes.sea_level),
+            Param::CoastLevel => format!("{:.2}", settings.biomes.coast_level),
+            Param::MountainLevel => format!("{:.2}", settings.biomes.mountain_level),
+            Param::LapseRate => format!("{:.2}", settings.biomes.lapse_rate),
+        }
+    }
+
+    /// Step the parameter up or down by `dir`. Returns whether it changed,
+    /// steps which would leave the [`BiomeTable`](super::terrain::BiomeTable)
+    /// out of order are refused.
+    fn step(self, settings: &mut TerrainSettings, seed: &mut u64, dir: i32) -> bool {
+        let mut tweaked = settings.clone();
+        let level = LEVEL_STEP * dir as f32;
+        match self {
+            Param::Seed => {
+                *seed = seed.wrapping_add_signed(dir as i64);
+                return true;
+            }
+            Param::Frequency(field) => {
+                field.layer_mut(&mut tweaked).frequency *= FREQUENCY_STEP.powi(dir)
+            }
+            Param::Octaves(field) => {
+                let layer = field.layer_mut(&mut tweaked);
+                layer.octaves = (layer.octaves as i32 + dir).clamp(1, MAX_OCTAVES as i32) as usize;
+            }
+            Param::SeaLevel => tweaked.biomes.sea_level += level,
+            Param::CoastLevel => tweaked.biomes.coast_level += level,
+            Param::MountainLevel => tweaked.biomes.mountain_level += level,
+            Param::LapseRate => {
+                tweaked.biomes.lapse_rate = (tweaked.biomes.lapse_rate + level).max(0.0)
+            }
+        }
+        if tweaked == *settings || tweaked.biomes.validate().is_err() {
+            return false;
+        }
+        *settings = tweaked;
+        true
+    }
+}
+
+/// Lay out the panel, returning its size and textures.
+fn layout_panel(
+    state: &MapDesignerState,
+    settings: &TerrainSettings,
+    seed: u64,
+) -> (UVec2, Vec<CharTexture>) {
+    let mut lines: Vec<(String, Option<RGB>)> =
+        vec![(" Map generator".to_string(), Some(Color::YELLOW))];
+    let label_width = PARAMS.iter().map(|p| p.label().len()).max().unwrap_or(0);
+    for (idx, param) in PARAMS.iter().enumerate() {
+        let selected = idx == state.selected;
+        let line = format!(
+            "{}{:<width$}  {}",
+            if selected { ">" } else { " " },
+            param.label(),
+            param.value(settings, seed),
+            width = label_width
+        );
+        lines.push((line, selected.then_some(Color::YELLOW)));
+    }
+    lines.push((" Up/Down: pick  Left/Right: change".to_string(), None));
+    lines.push((" R: new seed  G/Esc: close".to_string(), None));
+
+    let width = lines
+        .iter()
+        .map(|(line, _)| line.chars().count())
+        .max()
+        .unwrap_or(0)
+        + 1;
+    let mut textures = Vec::with_capacity(width * lines.len());
+    for (line, rgb) in lines.iter() {
+        textures.extend(format!("{line:<width$}").chars().map(|c| CharTexture {
+            c,
+            rgb: *rgb,
+            bg: Some(PANEL_BG),
+        }));
+    }
+    (UVec2::new(width as u32, lines.len() as u32), textures)
+}
+
+fn sys_update_map_designer_panel(
+    state: Res<MapDesignerState>,
+    settings: Res<TerrainSettings>,
+    rng: Res<SimRng>,
+    mut panel: Query<(&mut CharMesh, &mut Transform2D), With<MapDesignerPanel>>,
+) {
+    let Ok((mut mesh, mut transform)) = panel.get_single_mut() else {
+        return;
+    };
+    let (scale, textures) = layout_panel(&state, &settings, rng.seed());
+    if transform.scale != scale {
+        transform.scale = scale;
+    }
+    if mesh.texture_vec != textures {
+        mesh.texture_vec = textures;
+    }
+}
+
+fn regenerate(rng: &mut SimRng, seed: u64, regenerate: &mut EventWriter<RegenerateMap>) {
+    log::info!("Regenerating map with seed {}", seed);
+    *rng = SimRng::new(seed);
+    regenerate.send(RegenerateMap);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn handle_map_designer_keys(
+    mut cmd: Commands,
+    mut input: EventReader<KeyboardInput>,
+    mut focus: ResMut<InputFocus>,
+    mut state: ResMut<MapDesignerState>,
+    mut settings: ResMut<TerrainSettings>,
+    mut rng: ResMut<SimRng>,
+    mut writer: EventWriter<RegenerateMap>,
+    panels: Query<Entity, With<MapDesignerPanel>>,
+) {
+    for e in input.iter() {
+        if e.state != ButtonState::Pressed {
+            continue;
+        }
+        let Some(key) = e.key_code else {
+            continue;
+        };
+        let dir = match (*focus, key) {
+            (InputFocus::MapDesigner, KeyCode::R) => {
+                regenerate(&mut rng, fastrand::u64(..), &mut writer);
+                continue;
+            }
+            (InputFocus::World, KeyCode::G) => {
+                *focus = InputFocus::MapDesigner;
+                cmd.spawn((
+                    MapDesignerPanel,
+                    CharMeshTransform::new(Transform2D::default()),
+                    UIComponent::new(Vec3::new(1.0, 1.0, MAP_DESIGNER_Z)),
+                ));
+                continue;
+            }
+            (InputFocus::MapDesigner, KeyCode::G | KeyCode::Escape) => {
+                for entity in panels.iter() {
+                    cmd.entity(entity).despawn();
+                }
+                *focus = InputFocus::World;
+                continue;
+            }
+            (InputFocus::MapDesigner, KeyCode::Up) => {
+                state.selected = (state.selected + PARAMS.len() - 1) % PARAMS.len();
+                continue;
+            }
+            (InputFocus::MapDesigner, KeyCode::Down) => {
+                state.selected = (state.selected + 1) % PARAMS.len();
+                continue;
+            }
+            (InputFocus::MapDesigner, KeyCode::Left) => -1,
+            (InputFocus::MapDesigner, KeyCode::Right) => 1,
+            _ => continue,
+        };
+        let mut seed = rng.seed();
+        if PARAMS[state.selected].step(&mut settings, &mut seed, dir) {
+            regenerate(&mut rng, seed, &mut writer);
+        }
+    }
+}
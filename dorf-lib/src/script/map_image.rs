@@ -0,0 +1,359 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use bevy::input::ButtonState;
+use image::{Rgb, RgbImage};
+use num_traits::FromPrimitive;
+use strum::EnumCount;
+use thiserror::Error;
+
+use crate::prelude::*;
+
+use super::levels::ViewLevel;
+use super::local_map::{Biome, BiomeGrid, MapSettings};
+use super::pathing::CollisionGridCache;
+
+/// Directory maps are exported into.
+pub const EXPORT_DIR: &str = "export";
+
+const EXPORT_DIALOG: &str = "map_image::export";
+
+/// Colors of the obstacle map. Anything else is free to walk on.
+const FREE_COLOR: RGB = RGB::new(0, 0, 0);
+const TERRAIN_OBSTACLE_COLOR: RGB = RGB::new(255, 255, 255);
+const OBSTACLE_COLOR: RGB = Color::RED;
+
+pub fn add_map_image_systems(app: &mut App, enabled: bool) {
+    app.init_resource::<MapPalette>()
+        .add_system(handle_export_key);
+}
+
+#[derive(Debug, Error)]
+pub enum MapImageError {
+    #[error("couldn't create {}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("{}: {source}", path.display())]
+    Image {
+        path: PathBuf,
+        source: image::ImageError,
+    },
+
+    #[error(
+        "{}: pixel ({}, {}) is {}, which isn't in the palette",
+        path.display(), pixel.x, pixel.y, String::from(*color)
+    )]
+    UnknownColor {
+        path: PathBuf,
+        pixel: UVec2,
+        color: RGB,
+    },
+}
+
+/// The color standing for each [`Biome`] in map images. Every biome has its
+/// own color, so images can be read back in.
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct MapPalette {
+    /// Indexed by [`Biome`].
+    colors: Vec<RGB>,
+}
+
+impl MapPalette {
+    /// Build the palette from one color per biome. Fails if a biome is
+    /// missing or defined twice, or two biomes share a color.
+    pub fn from_entries(entries: impl IntoIterator<Item = (Biome, RGB)>) -> Result<Self, String> {
+        let mut colors: Vec<Option<RGB>> = vec![None; Biome::COUNT];
+        for (biome, color) in entries {
+            if let Some(other) = colors.iter().position(|c| *c == Some(color)) {
+                return Err(format!(
+                    "{:?} and {:?} are both {}",
+                    Biome::from_usize(other).unwrap(),
+                    biome,
+                    String::from(color)
+                ));
+            }
+            if colors[biome as usize].replace(color).is_some() {
+                return Err(format!("{:?} is defined twice", biome));
+            }
+        }
+        let colors = colors
+            .into_iter()
+            .enumerate()
+            .map(|(idx, color)| {
+                color.ok_or_else(|| format!("{:?} has no color", Biome::from_usize(idx).unwrap()))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { colors })
+    }
+
+    pub fn color(&self, biome: Biome) -> RGB {
+        self.colors[biome as usize]
+    }
+
+    pub fn biome(&self, color: RGB) -> Option<Biome> {
+        let idx = self.colors.iter().position(|c| *c == color)?;
+        Biome::from_usize(idx)
+    }
+}
+
+impl Default for MapPalette {
+    fn default() -> Self {
+        let hex = |s: &str| s.parse().unwrap();
+        Self::from_entries([
+            (Biome::Forest, hex("#006400")),
+            (Biome::Grassland, hex("#7cfc00")),
+            (Biome::Desert, hex("#edc9af")),
+            (Biome::Tundra, hex("#c8dcdc")),
+            (Biome::Ocean, hex("#000080")),
+            (Biome::Sand, hex("#ffd819")),
+            (Biome::Mountain, hex("#808080")),
+            (Biome::River, hex("#1e90ff")),
+            (Biome::Lake, hex("#40e0d0")),
+            (Biome::Soil, hex("#8b4513")),
+            (Biome::Rock, hex("#505050")),
+            (Biome::Ore, hex("#ffd700")),
+            (Biome::Cave, hex("#303030")),
+            (Biome::Open, hex("#ff00ff")),
+            (Biome::Stairs, hex("#ffffff")),
+            (Biome::Road, hex("#a0522d")),
+            (Biome::Null, hex("#000000")),
+        ])
+        .unwrap()
+    }
+}
+
+/// A surface read from a map image, used in place of generated terrain
+/// wherever it covers the map.
+#[derive(Resource, Debug, Clone)]
+pub struct ImportedSurface(pub Arc<Grid2D<Biome>>);
+
+fn to_rgb(color: RGB) -> Rgb<u8> {
+    Rgb([color.r, color.g, color.b])
+}
+
+fn save(image: &RgbImage, path: &Path) -> Result<(), MapImageError> {
+    image.save(path).map_err(|source| MapImageError::Image {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// Read a surface from the image at `path`, its topleft pixel becoming
+/// `origin`.
+pub fn import_surface(
+    path: &Path,
+    origin: IVec2,
+    palette: &MapPalette,
+) -> Result<Grid2D<Biome>, MapImageError> {
+    let image = image::open(path)
+        .map_err(|source| MapImageError::Image {
+            path: path.to_path_buf(),
+            source,
+        })?
+        .to_rgb8();
+    let size = UVec2::new(image.width(), image.height());
+    let mut surface = Grid2D::new(origin, size, Biome::Null);
+    for (x, y, Rgb([r, g, b])) in image.enumerate_pixels() {
+        let color = RGB::new(*r, *g, *b);
+        let biome = palette
+            .biome(color)
+            .ok_or_else(|| MapImageError::UnknownColor {
+                path: path.to_path_buf(),
+                pixel: UVec2::new(x, y),
+                color,
+            })?;
+        surface.set(origin + UVec2::new(x, y).as_ivec2(), biome);
+    }
+    Ok(surface)
+}
+
+/// Write `level` of the map to `<stem>.png`, and what blocks movement on it
+/// to `<stem>-obstacles.png`. Tiles which aren't loaded are left as
+/// [`Biome::Null`].
+fn export_level<'g>(
+    dir: &Path,
+    stem: &str,
+    map: &MapSettings,
+    level: u32,
+    grids: impl IntoIterator<Item = &'g BiomeGrid>,
+    col_cache: &CollisionGridCache,
+    palette: &MapPalette,
+) -> Result<PathBuf, MapImageError> {
+    fs::create_dir_all(dir).map_err(|source| MapImageError::Io {
+        path: dir.to_path_buf(),
+        source,
+    })?;
+    let rect = map.rect();
+    let mut biomes =
+        RgbImage::from_pixel(map.size.x, map.size.y, to_rgb(palette.color(Biome::Null)));
+    for grid in grids {
+        for (tile, biome) in grid.iter_level(level) {
+            if rect.index_for_point(tile).is_none() {
+                continue;
+            }
+            let pixel = tile - rect.min;
+            biomes.put_pixel(pixel.x as u32, pixel.y as u32, to_rgb(palette.color(biome)));
+        }
+    }
+    let obstacles = RgbImage::from_fn(map.size.x, map.size.y, |x, y| {
+        let point = (rect.min + UVec2::new(x, y).as_ivec2()).extend(level as i32);
+        let color = if col_cache.obstacle_at(point).is_some() {
+            OBSTACLE_COLOR
+        } else if col_cache.terrain_blocked(point) {
+            TERRAIN_OBSTACLE_COLOR
+        } else {
+            FREE_COLOR
+        };
+        to_rgb(color)
+    });
+    let path = dir.join(format!("{}.png", stem));
+    save(&biomes, &path)?;
+    save(&obstacles, &dir.join(format!("{}-obstacles.png", stem)))?;
+    Ok(path)
+}
+
+/// Export the level in view when `P` is pressed.
+#[allow(clippy::too_many_arguments)]
+fn handle_export_key(
+    mut input: EventReader<KeyboardInput>,
+    focus: Res<InputFocus>,
+    map: Res<MapSettings>,
+    view: Res<ViewLevel>,
+    rng: Res<SimRng>,
+    palette: Res<MapPalette>,
+    col_cache: Res<CollisionGridCache>,
+    grids: Query<&BiomeGrid>,
+    mut dialog: EventWriter<OpenDialog>,
+) {
+    if *focus != InputFocus::World {
+        input.clear();
+        return;
+    }
+    let pressed = input
+        .iter()
+        .any(|e| e.state == ButtonState::Pressed && e.key_code == Some(KeyCode::P));
+    if !pressed {
+        return;
+    }
+    let stem = format!(
+        "map-{}-{}_{}-lvl{}",
+        rng.seed(),
+        map.origin.x,
+        map.origin.y,
+        view.level
+    );
+    let message = match export_level(
+        Path::new(EXPORT_DIR),
+        &stem,
+        &map,
+        view.level,
+        grids.iter(),
+        &col_cache,
+        &palette,
+    ) {
+        Ok(path) => {
+            log::info!("Exported map to {}", path.display());
+            format!("Exported to {}", path.display())
+        }
+        Err(e) => {
+            log::error!("Map export failed: {}", e);
+            e.to_string()
+        }
+    };
+    dialog.send(OpenDialog {
+        tag: EXPORT_DIALOG,
+        title: "Export".to_string(),
+        message,
+        kind: DialogKind::Ok,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::script::tiles::TileTable;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("dorf-{}-{}", name, std::process::id()))
+    }
+
+    #[test]
+    fn palette_rejects_bad_entries() {
+        let entries = || {
+            let colors = MapPalette::default().colors.into_iter().enumerate();
+            colors.map(|(idx, color)| (Biome::from_usize(idx).unwrap(), color))
+        };
+
+        let twice = entries().chain([(Biome::Lake, RGB::new(1, 2, 3))]);
+        let err = MapPalette::from_entries(twice).unwrap_err();
+        assert!(err.contains("Lake is defined twice"), "{}", err);
+
+        let missing = entries().filter(|(biome, _)| *biome != Biome::Cave);
+        let err = MapPalette::from_entries(missing).unwrap_err();
+        assert!(err.contains("Cave has no color"), "{}", err);
+
+        let ocean = MapPalette::default().color(Biome::Ocean);
+        let shared = entries()
+            .map(|(biome, color)| (biome, if biome == Biome::Lake { ocean } else { color }));
+        let err = MapPalette::from_entries(shared).unwrap_err();
+        assert!(err.contains("Ocean and Lake are both"), "{}", err);
+    }
+
+    #[test]
+    fn unknown_color_is_reported_at_its_pixel() {
+        let dir = temp_dir("unknown-color");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("map.png");
+        let palette = MapPalette::default();
+        let mut image = RgbImage::from_pixel(4, 3, to_rgb(palette.color(Biome::Grassland)));
+        image.put_pixel(2, 1, Rgb([1, 2, 3]));
+        save(&image, &path).unwrap();
+        let result = import_surface(&path, IVec2::ZERO, &palette);
+        fs::remove_dir_all(&dir).unwrap();
+        match result {
+            Err(MapImageError::UnknownColor { pixel, color, .. }) => {
+                assert_eq!(pixel, UVec2::new(2, 1));
+                assert_eq!(color, RGB::new(1, 2, 3));
+            }
+            other => panic!("expected an unknown color, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn export_then_import_round_trip() {
+        let map = MapSettings {
+            origin: IVec2::new(-3, 5),
+            size: UVec2::new(6, 4),
+            ..default()
+        };
+        let rect = map.rect();
+        let mut levels = Grid3D::new(rect.min, map.size, 2, Biome::Grassland);
+        for (idx, x) in (rect.min.x..rect.max.x).enumerate() {
+            let biome = Biome::from_usize(idx).unwrap();
+            levels.set(IVec3::new(x, rect.min.y + 1, 0), biome);
+        }
+        levels.set(IVec3::new(rect.min.x, rect.min.y, 1), Biome::Rock);
+        let grid = BiomeGrid::new(levels);
+        let col_cache = CollisionGridCache::new(rect.min, map.size, 2, TileTable::default());
+        let palette = MapPalette::default();
+
+        let dir = temp_dir("export-round-trip");
+        let path = export_level(&dir, "map", &map, 0, [&grid], &col_cache, &palette).unwrap();
+        let surface = import_surface(&path, map.origin, &palette);
+        let obstacles = dir.join("map-obstacles.png").exists();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let surface = surface.unwrap();
+        assert!(obstacles);
+        assert_eq!(*surface.rect(), rect);
+        for (tile, biome) in grid.iter() {
+            assert_eq!(*surface.get(tile).unwrap(), biome, "at {}", tile);
+        }
+    }
+}
@@ -0,0 +1,265 @@
+use bevy::input::ButtonState;
+use strum::EnumCount;
+
+use crate::prelude::*;
+
+use super::cursor::TileCursor;
+use super::local_map::{Biome, BiomeGrid, MapSettings};
+use super::tiles::TileTable;
+
+const MINIMAP_Z: f32 = 600.0;
+
+pub fn add_minimap_systems(app: &mut App, enabled: bool) {
+    app.insert_resource(MinimapSettings::default())
+        .init_resource::<MinimapState>()
+        .add_startup_system(spawn_minimap)
+        .add_system(sys_update_minimap)
+        .add_system(handle_minimap_keys.in_base_set(CoreSet::PostUpdate));
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct MinimapSettings {
+    /// Number of tiles along each axis summarized by a single minimap cell.
+    pub cell_size: u32,
+    /// Largest the minimap may get, in cells. Bigger maps use bigger cells.
+    pub max_cells: UVec2,
+}
+
+impl Default for MinimapSettings {
+    fn default() -> Self {
+        Self {
+            cell_size: 4,
+            max_cells: UVec2::new(32, 16),
+        }
+    }
+}
+
+#[derive(Resource, Debug, Default)]
+struct MinimapState {
+    /// Cell highlighted while the minimap has [`InputFocus`].
+    selected: IVec2,
+}
+
+#[derive(Component, Debug, Default)]
+struct Minimap;
+
+/// The dominant biome of each minimap cell, only rebuilt when the map changes.
+/// Cells of chunks which aren't loaded are left empty.
+#[derive(Debug, Default)]
+struct MinimapCache {
+    layout: Option<MinimapLayout>,
+    dominant: Vec<Option<Biome>>,
+    grid_count: usize,
+}
+
+/// Where the minimap sits relative to the map it summarizes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct MinimapLayout {
+    /// Tile covered by the topleft cell.
+    origin: IVec2,
+    cell_size: i32,
+    cells: IVec2,
+}
+
+impl MinimapLayout {
+    fn new(map: &MapSettings, settings: &MinimapSettings) -> Self {
+        let bounds = map.rect();
+        // Smallest cell that still fits the whole map within `max_cells`.
+        let max_cells = settings.max_cells.max(UVec2::ONE);
+        let fit = (bounds.size().as_uvec2() + max_cells - UVec2::ONE) / max_cells;
+        let cell_size = settings.cell_size.max(fit.max_element()).max(1) as i32;
+        Self {
+            origin: bounds.min,
+            cell_size,
+            // Round up so partial cells on the far edges are still shown.
+            cells: (bounds.size() + cell_size - 1) / cell_size,
+        }
+    }
+
+    fn cell_for_tile(&self, tile: IVec2) -> IVec2 {
+        let offset = tile - self.origin;
+        IVec2::new(
+            offset.x.div_euclid(self.cell_size),
+            offset.y.div_euclid(self.cell_size),
+        )
+    }
+
+    fn cell_center(&self, cell: IVec2) -> IVec2 {
+        self.origin + cell * self.cell_size + self.cell_size / 2
+    }
+
+    fn contains(&self, cell: IVec2) -> bool {
+        cell.cmpge(IVec2::ZERO).all() && cell.cmplt(self.cells).all()
+    }
+
+    fn idx(&self, cell: IVec2) -> usize {
+        (cell.y * self.cells.x + cell.x) as usize
+    }
+}
+
+fn spawn_minimap(mut cmd: Commands) {
+    cmd.spawn((
+        Minimap,
+        CharMeshTransform::new(Transform2D::default()),
+        UIComponent::new(Vec3::new(0.0, 1.0, MINIMAP_Z)),
+    ));
+}
+
+fn dominant_biomes(layout: &MinimapLayout, grids: &Query<&BiomeGrid>) -> Vec<Option<Biome>> {
+    let mut counts = vec![[0u32; Biome::COUNT]; (layout.cells.x * layout.cells.y) as usize];
+    for grid in grids.iter() {
+        for (tile, biome) in grid.iter() {
+            // Chunks of a previous map may linger until they're despawned.
+            let cell = layout.cell_for_tile(tile);
+            if biome != Biome::Null && layout.contains(cell) {
+                counts[layout.idx(cell)][biome as usize] += 1;
+            }
+        }
+    }
+    counts
+        .iter()
+        .map(|count| {
+            let (biome, n) = count.iter().enumerate().max_by_key(|(_, n)| **n)?;
+            if *n == 0 {
+                return None;
+            }
+            num_traits::FromPrimitive::from_usize(biome)
+        })
+        .collect()
+}
+
+/// Returns the character outlining the camera's view, if `cell` falls on the
+/// outline spanning `min` to `max` (inclusive).
+fn view_outline_char(cell: IVec2, min: IVec2, max: IVec2) -> Option<char> {
+    if cell.cmplt(min).any() || cell.cmpgt(max).any() {
+        return None;
+    }
+    let left = cell.x == min.x;
+    let right = cell.x == max.x;
+    let top = cell.y == min.y;
+    let bottom = cell.y == max.y;
+    Some(match (left, right, top, bottom) {
+        (true, true, true, true) => '□',
+        (true, _, true, _) => '┌',
+        (_, true, true, _) => '┐',
+        (true, _, _, true) => '└',
+        (_, true, _, true) => '┘',
+        (true, _, _, _) | (_, true, _, _) => '│',
+        (_, _, true, _) | (_, _, _, true) => '─',
+        _ => return None,
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn sys_update_minimap(
+    mut cache: Local<MinimapCache>,
+    settings: Res<MinimapSettings>,
+    map: Res<MapSettings>,
+    state: Res<MinimapState>,
+    focus: Res<InputFocus>,
+    camera: Res<TerminalCamera2D>,
+    tiles: Res<TileTable>,
+    grids: Query<&BiomeGrid>,
+    changed_grids: Query<(), Changed<BiomeGrid>>,
+    mut minimap: Query<(&mut CharMesh, &mut Transform2D, &mut UIComponent), With<Minimap>>,
+) {
+    let grid_count = grids.iter().count();
+    let map_changed = !changed_grids.is_empty() || grid_count != cache.grid_count;
+    if !map_changed
+        && !settings.is_changed()
+        && !map.is_changed()
+        && !camera.is_changed()
+        && !state.is_changed()
+        && !focus.is_changed()
+        && !tiles.is_changed()
+    {
+        return;
+    }
+    let Ok((mut mesh, mut transform, mut ui_component)) = minimap.get_single_mut() else {
+        return;
+    };
+
+    let layout = MinimapLayout::new(&map, &settings);
+    if map_changed || Some(layout) != cache.layout {
+        cache.dominant = dominant_biomes(&layout, &grids);
+        cache.layout = Some(layout);
+        cache.grid_count = grid_count;
+    }
+
+    let view = camera.transform().as_rect2d();
+    let view_min = layout.cell_for_tile(view.min);
+    let view_max = layout.cell_for_tile(view.max - IVec2::ONE);
+    let mut textures = Vec::with_capacity(cache.dominant.len());
+    for y in 0..layout.cells.y {
+        for x in 0..layout.cells.x {
+            let cell = IVec2::new(x, y);
+            let texture = if *focus == InputFocus::Minimap && cell == state.selected {
+                CharTexture::new('X', Color::YELLOW)
+            } else if let Some(c) = view_outline_char(cell, view_min, view_max) {
+                CharTexture::new(c, Color::WHITE)
+            } else {
+                cache.dominant[layout.idx(cell)]
+                    .map(|biome| tiles.texture(biome))
+                    .unwrap_or_default()
+            };
+            textures.push(texture);
+        }
+    }
+
+    mesh.texture_vec = textures;
+    transform.scale = layout.cells.as_uvec2();
+    // Pin to the topright corner, just inside the camera frame.
+    let x = (camera.dim().x as i32 - layout.cells.x - 1).max(0);
+    ui_component.local_pos = Vec3::new(x as f32, 1.0, MINIMAP_Z);
+}
+
+fn handle_minimap_keys(
+    mut input: EventReader<KeyboardInput>,
+    mut focus: ResMut<InputFocus>,
+    mut state: ResMut<MinimapState>,
+    settings: Res<MinimapSettings>,
+    map: Res<MapSettings>,
+    mut camera: ResMut<TerminalCamera2D>,
+    mut cursor: Query<&mut TileCursor>,
+) {
+    let layout = MinimapLayout::new(&map, &settings);
+    for e in input.iter() {
+        if e.state != ButtonState::Pressed {
+            continue;
+        }
+        let Some(key) = e.key_code else {
+            continue;
+        };
+        let step = match (*focus, key) {
+            (InputFocus::World, KeyCode::M) => {
+                *focus = InputFocus::Minimap;
+                let center = tile_from_vec2(camera.transform().as_rect2d().center());
+                state.selected = layout
+                    .cell_for_tile(center)
+                    .clamp(IVec2::ZERO, layout.cells - IVec2::ONE);
+                continue;
+            }
+            (InputFocus::Minimap, KeyCode::M | KeyCode::Escape) => {
+                *focus = InputFocus::World;
+                continue;
+            }
+            (InputFocus::Minimap, KeyCode::Return) => {
+                let center = layout.cell_center(state.selected);
+                let loc = center.as_vec2() - camera.dim().as_vec2() / 2.0;
+                let z = camera.loc().z;
+                *camera.loc_mut() = tile_from_vec2(loc).as_vec2().extend(z);
+                if let Ok(mut cursor) = cursor.get_single_mut() {
+                    cursor.tile = center;
+                }
+                *focus = InputFocus::World;
+                continue;
+            }
+            (InputFocus::Minimap, KeyCode::Left) => IVec2::new(-1, 0),
+            (InputFocus::Minimap, KeyCode::Right) => IVec2::new(1, 0),
+            (InputFocus::Minimap, KeyCode::Up) => IVec2::new(0, -1),
+            (InputFocus::Minimap, KeyCode::Down) => IVec2::new(0, 1),
+            _ => continue,
+        };
+        state.selected = (state.selected + step).clamp(IVec2::ZERO, layout.cells - IVec2::ONE);
+    }
+}
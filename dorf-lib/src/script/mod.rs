@@ -1,13 +1,46 @@
 pub mod camera_frame;
+pub mod content;
+pub mod cursor;
+pub mod flow_field;
+pub mod hierarchy;
+pub mod hydrology;
+pub mod jump_point;
+pub mod levels;
+pub mod loading_screen;
 pub mod local_map;
+pub mod map_designer;
+pub mod map_image;
+pub mod minimap;
 pub mod pathing;
+pub mod prefabs;
+pub mod save;
+pub mod sim_time;
+pub mod status_bar;
+pub mod terrain;
+pub mod text_map;
+pub mod tiles;
+pub mod underground;
+pub mod world_map;
 
 use crate::terminal::*;
 use bevy::app::AppExit;
+use bevy::input::ButtonState;
 
 use self::camera_frame::*;
+use self::cursor::*;
+use self::flow_field::*;
+use self::hierarchy::*;
+use self::levels::*;
+use self::loading_screen::*;
 use self::local_map::*;
+use self::map_designer::*;
+use self::map_image::*;
+use self::minimap::*;
 use self::pathing::*;
+use self::save::*;
+use self::sim_time::*;
+use self::status_bar::*;
+use self::world_map::*;
 use crate::prelude::*;
 
 #[derive(Default)]
@@ -17,23 +50,80 @@ impl Plugin for ScriptPlugin {
     fn build(&self, app: &mut App) {
         log::debug!("Initializing ScriptPlugin");
         app.add_startup_system(spawn_centerpoint)
-            .add_system(sys_exit_key_handler);
+            .add_system(sys_exit_key_handler)
+            .add_system(sys_handle_quit_dialog);
         add_pathing_systems(app, true);
+        add_hierarchy_systems(app, true);
+        add_flow_field_systems(app, true);
         add_local_map_systems(app, true);
+        add_level_systems(app, true);
+        add_map_image_systems(app, true);
+        add_save_systems(app, true);
         add_camera_frame_systems(app, true);
+        add_cursor_systems(app, true);
+        add_sim_time_systems(app, true);
+        add_status_bar_systems(app, true);
+        add_loading_screen_systems(app, true);
+        add_minimap_systems(app, true);
+        add_world_map_systems(app, true);
+        add_map_designer_systems(app, true);
     }
 }
 
-fn sys_exit_key_handler(mut input: EventReader<KeyboardInput>, mut writer: EventWriter<AppExit>) {
+const QUIT_DIALOG: &str = "quit";
+/// Options of the quit dialog, in the order they're listed.
+const QUIT_SAVE: usize = 0;
+const QUIT_WITHOUT_SAVING: usize = 1;
+const QUIT_OPTIONS: [&str; 3] = ["Save and quit", "Quit without saving", "Cancel"];
+
+fn sys_exit_key_handler(
+    mut input: EventReader<KeyboardInput>,
+    focus: Res<InputFocus>,
+    mut writer: EventWriter<OpenDialog>,
+) {
+    if *focus != InputFocus::World {
+        input.clear();
+        return;
+    }
     for e in input.iter() {
+        if e.state != ButtonState::Pressed {
+            continue;
+        }
         if let Some(k) = e.key_code {
             if [KeyCode::Escape, KeyCode::Q].contains(&k) {
-                writer.send(AppExit);
+                writer.send(OpenDialog {
+                    tag: QUIT_DIALOG,
+                    title: "Quit".to_string(),
+                    message: "Save before quitting?".to_string(),
+                    kind: DialogKind::Choice(QUIT_OPTIONS.map(String::from).to_vec()),
+                });
+                // Don't ask twice for the same frame.
+                break;
             }
         }
     }
 }
 
+/// Quit right away, or once the exit save is written, as picked in the quit
+/// dialog.
+fn sys_handle_quit_dialog(
+    mut closed: EventReader<DialogClosed>,
+    settings: Res<SaveSettings>,
+    mut writer: EventWriter<SaveRequest>,
+    mut exit: EventWriter<AppExit>,
+) {
+    for e in closed.iter().filter(|e| e.tag == QUIT_DIALOG) {
+        match e.response {
+            DialogResponse::Choice(QUIT_SAVE) => writer.send(SaveRequest::Save {
+                path: settings.exit_path(),
+                after: AfterSave::Quit,
+            }),
+            DialogResponse::Choice(QUIT_WITHOUT_SAVING) => exit.send(AppExit),
+            _ => (),
+        }
+    }
+}
+
 fn spawn_centerpoint(mut cmd: Commands) {
     cmd.spawn((
         CharTexture::from_char('0'),
@@ -1,63 +1,301 @@
 use std::{
     cmp::{max, min, Reverse},
     collections::{BinaryHeap, HashMap},
+    sync::Arc,
 };
 
 use crate::{
     prelude::*,
-    script::{local_map::LOCAL_MAP_DIMMENSIONS, pathing},
+    script::{
+        content::{Creatures, Species},
+        flow_field::{FlowField, FlowFields, FollowFlowField},
+        hierarchy::{calc_hierarchical_path, AbstractPath, PathHierarchy},
+        jump_point::calc_jump_point_path,
+        levels::Level,
+        local_map::{Biome, BiomeGrid, MapSettings},
+        pathing,
+        sim_time::SimTime,
+        text_map::TextMap,
+        tiles::TileTable,
+    },
 };
 
+use bevy::tasks::{AsyncComputeTaskPool, Task};
 use bevy::{input::keyboard::KeyboardInput, transform};
 use bevy::{input::ButtonState, utils::Uuid};
+use futures_lite::future;
 use ordered_float::OrderedFloat;
+use serde::{Deserialize, Serialize};
+
+/// A point on a specific [`Level`] of the map.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LevelPoint {
+    pub loc: Vec2,
+    pub level: u32,
+}
+
+impl LevelPoint {
+    pub fn new(loc: Vec2, level: u32) -> Self {
+        Self { loc, level }
+    }
+
+    /// The tile containing the point, its level as z.
+    pub fn tile(&self) -> IVec3 {
+        self.loc.as_ivec2().extend(self.level as i32)
+    }
+}
 
 /// A component indicating a location to move towards. Once a `MovePath` has
-/// been assigned, the contained `Vec2` point will be cleared and set to `None`.
-#[derive(Component)]
-pub struct GoalLoc(Option<Vec2>);
+/// been assigned, the contained point will be cleared and set to `None`.
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
+pub struct GoalLoc(pub Option<LevelPoint>);
 
 /// A component indicating an Entity's move speed.
-#[derive(Component)]
-pub struct Speed(f32);
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
+pub struct Speed(pub f32);
+
+/// Cost of a step along a row or column, on tiles with a `move_cost` of 1.0.
+/// Steps cost more or less across other terrain, see
+/// [`CollisionGridCache::step_cost`].
+pub const STRAIGHT_COST: f32 = 1.0;
+/// Cost of a diagonal step.
+pub const DIAGONAL_COST: f32 = 1.4;
+/// Cost of climbing stairs to the next level.
+pub const STAIRS_COST: f32 = 1.0;
+/// Movers spawned on maps wider or taller than this use
+/// [`PathAlgorithm::Hierarchical`].
+const LARGE_MAP: u32 = 64;
+/// Most [`PathTask`]s searching at once, other requests wait for one to
+/// finish.
+const PATH_TASKS_IN_FLIGHT: usize = 16;
+/// Frames a [`PathSnapshot`] is searched before it's copied again, unless a
+/// path found on it crossed a change.
+const SNAPSHOT_FRAMES: u32 = 10;
+/// Most tiles [`random_point_on_local_map`] tries before giving up on finding
+/// one to stand on.
+const GOAL_TRIES: u32 = 32;
+
+/// A component choosing how the path to an entity's [`GoalLoc`] is searched
+/// for. Entities without one use [`PathAlgorithm::AStar`].
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PathAlgorithm {
+    #[default]
+    AStar,
+    /// Jump Point Search, which expands far fewer nodes than A* across open
+    /// ground of one terrain, finding paths that cost the same.
+    JumpPoint,
+    /// Search the [`PathHierarchy`](super::hierarchy::PathHierarchy), only
+    /// finding the tiles to walk near the mover. Paths aren't always the
+    /// cheapest, but long ones are found far quicker.
+    Hierarchical,
+    /// Follow the [`FlowField`](super::flow_field::FlowField) to the goal
+    /// rather than a [`MovePath`], which is shared by every mover headed
+    /// there.
+    FlowField,
+}
+
+/// Event sent whenever what collides in the [`CollisionGridCache`] changes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CollisionChanged {
+    /// The whole cache, e.g. once it's moved or the [`TileTable`] changed.
+    All,
+    Area {
+        rect: Rect2D,
+        level: u32,
+    },
+}
 
 /// A tag Component indicating an entity is collidable but will not move
-#[derive(Component, Debug, Default)]
+#[derive(Component, Debug, Default, Clone, Serialize, Deserialize)]
 pub struct ImmobileObstacle;
 
 /// A tag Component indicating an entity is moveable, should be verified it
 /// doesn't land on an `ImmobileObstacleTag`, and but it can layer with other
 /// `LayerableCollider` entities
-#[derive(Component, Debug, Default)]
+#[derive(Component, Debug, Default, Clone, Serialize, Deserialize)]
 pub struct LayerableCollider;
 
 /// A compnent to containing a computed path to a previously assigned `GoalLoc`
+///
+/// Steps are stored in reverse, the next point to move to is the last element.
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
+pub struct MovePath {
+    pub steps: Vec<LevelPoint>,
+}
+
+/// A tag Component queueing a search for the path to an entity's [`GoalLoc`],
+/// started once fewer than [`PATH_TASKS_IN_FLIGHT`] are searching.
+#[derive(Component, Debug, Default, Clone)]
+pub struct PathRequest;
+
+/// What a [`PathTask`] found leading to an entity's goal.
+enum FoundPath {
+    /// The steps of a path, and the rest of it to refine if it was found
+    /// over the [`PathHierarchy`].
+    Steps(Vec<LevelPoint>, Option<AbstractPath>),
+    /// The field to the goal, for [`PathAlgorithm::FlowField`].
+    Field(FlowField),
+}
+
+/// A path, or flow field, being searched for on the [`AsyncComputeTaskPool`].
+/// The search is cancelled when this is dropped, e.g. once the entity has a
+/// new goal.
 #[derive(Component)]
-struct MovePath {
-    steps: Vec<Vec2>,
+pub struct PathTask {
+    task: Task<Option<FoundPath>>,
+    /// What the path was searched for, it's stale if any have changed since.
+    goal: LevelPoint,
+    start: Vec3,
+    level: u32,
+    /// The [`PathSnapshot`] revision it searched.
+    revision: u64,
+}
+
+/// The cache and hierarchy [`PathTask`]s search, shared by every search until
+/// they're copied again, and what's changed since the oldest still searching.
+#[derive(Resource, Default)]
+pub struct PathSnapshot {
+    shared: Option<(Arc<CollisionGridCache>, Arc<PathHierarchy>)>,
+    /// Frames since `shared` was copied.
+    age: u32,
+    /// Each change with the [`CollisionGridCache::revision`] it's part of.
+    changes: Vec<(u64, CollisionChanged)>,
+}
+
+impl PathSnapshot {
+    fn revision(&self) -> Option<u64> {
+        self.shared
+            .as_ref()
+            .map(|(col_cache, _)| col_cache.revision())
+    }
+
+    fn changed_since(&self, revision: u64) -> impl Iterator<Item = &CollisionChanged> {
+        self.changes
+            .iter()
+            .filter(move |(changed, _)| *changed > revision)
+            .map(|(_, change)| change)
+    }
+
+    /// Whether anything that's changed since `revision` may change `field`,
+    /// as worked out then.
+    fn changes_field(&self, field: &FlowField, revision: u64) -> bool {
+        self.changed_since(revision).any(|change| match *change {
+            CollisionChanged::All => true,
+            CollisionChanged::Area { rect, level } => field.affected_by(rect, level),
+        })
+    }
+
+    /// Whether `steps` found as of `revision` step onto or beside anything
+    /// that's changed since. Paths elsewhere are as walkable as they were.
+    fn crosses_changes(&self, steps: &[LevelPoint], revision: u64) -> bool {
+        self.changed_since(revision).any(|change| match *change {
+            CollisionChanged::All => true,
+            CollisionChanged::Area { rect, level } => {
+                let around = rect.inset(1);
+                steps.iter().any(|step| {
+                    step.level == level && around.contains_exclusive_max(step.loc.floor())
+                })
+            }
+        })
+    }
+}
+
+/// Everything needed to search for a path off the main thread. The cache and
+/// hierarchy are the [`PathSnapshot`]'s, shared by every search of it.
+struct PathJob {
+    col_cache: Arc<CollisionGridCache>,
+    hierarchy: Arc<PathHierarchy>,
+    start: Transform2D,
+    level: u32,
+    goal: LevelPoint,
+    algorithm: PathAlgorithm,
+}
+
+impl PathJob {
+    fn run(self) -> Option<FoundPath> {
+        match self.algorithm {
+            PathAlgorithm::Hierarchical => calc_hierarchical_path(
+                &self.col_cache,
+                &self.hierarchy,
+                &self.start,
+                self.level,
+                self.goal,
+            )
+            .map(|(steps, abstract_path)| FoundPath::Steps(steps, Some(abstract_path))),
+            PathAlgorithm::FlowField => {
+                let start = self.start.as_tile().extend(self.level as i32);
+                let goal = self.goal.tile();
+                // Working out a field searches the whole map, so the goal is
+                // first looked for over the hierarchy, which is far cheaper
+                // for those that can't be reached.
+                self.hierarchy.find_path(&self.col_cache, start, goal)?;
+                let field = FlowField::new(&self.col_cache, goal);
+                field
+                    .reaches(&self.col_cache, start)
+                    .then_some(FoundPath::Field(field))
+            }
+            algorithm => calc_path(
+                &self.col_cache,
+                &self.start,
+                self.level,
+                self.goal,
+                algorithm,
+            )
+            .map(|steps| FoundPath::Steps(steps, None)),
+        }
+    }
 }
 
 /// A cache used to store the location of static Entities which objects should avoid.
-#[derive(Resource, Debug)]
-struct CollisionGridCache {
-    grid: Grid2D<Option<Entity>>,
-    entities: HashMap<Entity, Transform2D>,
+///
+/// Covers every level of the local map, points are `(x, y, level)`.
+#[derive(Resource, Debug, Clone)]
+pub struct CollisionGridCache {
+    grid: Grid3D<Option<Entity>>,
+    entities: HashMap<Entity, (Transform2D, u32)>,
+    /// Terrain of each tile, to find what can't be crossed (e.g. rivers) and
+    /// the stairs between levels. Shared with copies of the cache until
+    /// either changes it, as it rarely does.
+    terrain: Arc<Grid3D<Biome>>,
+    /// Copy of the [`TileTable`], so searches don't need the resource.
+    tiles: TileTable,
+    /// The area of each chunk whose terrain is mirrored in `terrain`.
+    chunks: HashMap<Entity, Rect2D>,
+    /// No more than the lowest `move_cost` of any tile movers can cross.
+    cheapest_move_cost: f32,
+    /// Counts every change to what collides, to tell when a path was
+    /// searched for on an older copy of the cache.
+    revision: u64,
 }
 
 /// Initialization function for pathing systems and an example spawner.
 pub fn add_pathing_systems(app: &mut App, enabled: bool) {
     if enabled {
         log::debug!("pathing system: enabled");
-        app.insert_resource(CollisionGridCache::new(
-            IVec2::default(),
-            LOCAL_MAP_DIMMENSIONS,
-        ))
-        .add_system(pathing::system_move_on_optimal_path)
-        .add_system(pathing::sys_update_collision_cache)
-        .add_system(pathing::system_assign_optimal_path.after(pathing::sys_update_collision_cache))
-        .add_system(pathing::sys_handle_collisions)
-        .add_system(pathing::spawn_mv_player_over_time)
-        .add_startup_system(pathing::spawn_collider_walls);
+        app.init_resource::<CollisionGridCache>()
+            .init_resource::<Creatures>()
+            .init_resource::<PathSnapshot>()
+            .add_event::<CollisionChanged>()
+            .add_system(
+                pathing::system_move_on_optimal_path.after(pathing::sys_update_collision_cache),
+            )
+            .add_system(pathing::sys_fit_to_map)
+            .add_system(pathing::sys_update_collision_cache.after(pathing::sys_fit_to_map))
+            .add_system(pathing::sys_update_terrain_obstacles.after(pathing::sys_fit_to_map))
+            .add_system(
+                pathing::system_assign_optimal_path.after(pathing::sys_update_collision_cache),
+            )
+            .add_system(
+                pathing::sys_dispatch_path_requests.after(pathing::system_assign_optimal_path),
+            )
+            .add_system(
+                pathing::sys_apply_finished_paths
+                    .after(pathing::system_assign_optimal_path)
+                    .before(pathing::system_move_on_optimal_path),
+            )
+            .add_system(pathing::sys_handle_collisions)
+            .add_system(pathing::spawn_mv_player_over_time)
+            .add_startup_system(pathing::spawn_collider_walls);
     } else {
         log::debug!("pathing system: disabled");
     }
@@ -74,20 +312,36 @@ fn for_points_in_rect<F, E>(rect: &Rect2D, mut f: F) -> Result<(), E>
 where
     F: FnMut(IVec2) -> Result<(), E>,
 {
-    for x in (rect.min.x as i32)..(rect.max.x as i32) {
-        for y in (rect.min.y as i32)..(rect.max.y as i32) {
-            f(IVec2 { x: x, y: y })?
+    for x in rect.min.x..rect.max.x {
+        for y in rect.min.y..rect.max.y {
+            f(IVec2 { x, y })?
         }
     }
     Ok(())
 }
 
+/// Sized to cover the local map described by [`MapSettings`].
+impl FromWorld for CollisionGridCache {
+    fn from_world(world: &mut World) -> Self {
+        let tiles = world
+            .get_resource_or_insert_with(TileTable::default)
+            .clone();
+        let settings = world.get_resource_or_insert_with(MapSettings::default);
+        Self::new(settings.origin, settings.size, settings.depth + 1, tiles)
+    }
+}
+
 impl CollisionGridCache {
     #[inline]
-    pub fn new(center: IVec2, size: UVec2) -> Self {
+    pub fn new(center: IVec2, size: UVec2, levels: u32, tiles: TileTable) -> Self {
         Self {
-            grid: Grid2D::new(center, size, None),
+            grid: Grid3D::new(center, size, levels, None),
             entities: default(),
+            terrain: Arc::new(Grid3D::new(center, size, levels, Biome::Null)),
+            tiles,
+            chunks: default(),
+            cheapest_move_cost: 1.0,
+            revision: 0,
         }
     }
     fn dbg_dump_to_log(&self) {
@@ -106,7 +360,7 @@ impl CollisionGridCache {
                 string.push_str(
                     format!(
                         " {}",
-                        match self.grid.get(IVec2 { x, y }).unwrap() {
+                        match self.grid.get(IVec3 { x, y, z: 0 }).unwrap() {
                             Some(_) => 'x',
                             None => '*',
                         }
@@ -118,28 +372,117 @@ impl CollisionGridCache {
         log::info!("Collision Grid: {}", string);
     }
 
+    /// Area of the local map covered.
+    #[inline]
+    pub fn rect(&self) -> &Rect2D {
+        self.grid.rect()
+    }
+
+    #[inline]
+    pub fn levels(&self) -> u32 {
+        self.grid.levels()
+    }
+
+    /// Changes whenever what collides does.
+    #[inline]
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Returns the obstacle occupying `point`, if any.
+    #[inline]
+    pub fn obstacle_at(&self, point: IVec3) -> Option<Entity> {
+        self.grid.get(point).ok().copied().flatten()
+    }
+
+    /// Returns whether the terrain at `point` blocks movement.
+    #[inline]
+    pub fn terrain_blocked(&self, point: IVec3) -> bool {
+        self.terrain
+            .get(point)
+            .map(|biome| self.tiles.get(*biome).is_obstacle())
+            .unwrap_or(false)
+    }
+
+    /// Returns how much slower than flat open ground the tile at `point` is to
+    /// cross, its `move_cost`.
+    #[inline]
+    pub fn move_cost(&self, point: IVec3) -> f32 {
+        self.terrain
+            .get(point)
+            .map(|biome| self.tiles.get(*biome).move_cost)
+            .unwrap_or(1.0)
+    }
+
+    /// Returns the cost of a step between the neighboring `from` and `to`,
+    /// half of which is spent crossing each tile. Steps cost the same both
+    /// ways.
+    #[inline]
+    pub fn step_cost(&self, from: IVec3, to: IVec3) -> f32 {
+        octile_distance(from, to) * self.step_move_cost(from, to)
+    }
+
+    /// Returns how much slower than flat open ground the step between the
+    /// neighboring `from` and `to` is to cross, the mean of their `move_cost`.
+    #[inline]
+    pub fn step_move_cost(&self, from: IVec3, to: IVec3) -> f32 {
+        (self.move_cost(from) + self.move_cost(to)) / 2.0
+    }
+
+    /// No more than the lowest `move_cost` of any tile movers can cross,
+    /// scaling [`octile_distance`] to never more than the real cost of a
+    /// path. It isn't raised again as chunks unload, so may be lower.
+    #[inline]
+    pub fn cheapest_move_cost(&self) -> f32 {
+        self.cheapest_move_cost
+    }
+
+    /// Find the lowest `move_cost` once all the terrain has changed.
+    fn update_move_costs(&mut self) {
+        let mut cheapest = f32::MAX;
+        for level in 0..self.terrain.levels() {
+            for (_, biome) in self.terrain.iter_level(level) {
+                let tile = self.tiles.get(*biome);
+                if !tile.is_obstacle() {
+                    cheapest = cheapest.min(tile.move_cost);
+                }
+            }
+        }
+        // Nowhere to move at all, nothing to estimate.
+        if cheapest == f32::MAX {
+            cheapest = 1.0;
+        }
+        self.cheapest_move_cost = cheapest;
+    }
+
+    /// Returns whether stairs lead directly between `from` and `to`.
+    pub fn stairs_connect(&self, from: IVec3, to: IVec3) -> bool {
+        let stairs = |point| self.terrain.get(point) == Ok(&Biome::Stairs);
+        from.xy() == to.xy() && (from.z - to.z).abs() == 1 && stairs(from) && stairs(to)
+    }
+
     /// Returns:
-    /// - `Ok(true)` if `point` would collide with cached colliders, `Ok(false)` if not
+    /// - `Ok(true)` if `point` would collide with cached colliders or
+    ///   impassable terrain, `Ok(false)` if not
     /// - `Err(OutOfBoundsError)` if the point isn't on the grid
     #[inline]
-    pub fn collides(&self, point: IVec2) -> Result<bool, crate::LightError> {
-        Ok(self.grid.get(point)?.is_some())
+    pub fn collides(&self, point: IVec3) -> Result<bool, crate::LightError> {
+        Ok(self.grid.get(point)?.is_some() || self.terrain_blocked(point))
     }
     #[inline]
     pub fn transform_collides_with(
         &self,
         obj: &Transform2D,
+        level: u32,
         uuid: Entity,
     ) -> Result<Option<Entity>, crate::LightError> {
         // Check overlapping rect
         let mut col = None;
         for_points_on_transform(obj, |point| {
-            let res = self.grid.get(point)?;
-            if res.is_some() {
-                if *res != Some(uuid) {
-                    col = *res;
-                    return Err(LightError::TerminateEarly);
-                }
+            let res = self.grid.get(point.extend(level as i32))?;
+            if res.is_some() && *res != Some(uuid) {
+                col = *res;
+                return Err(LightError::TerminateEarly);
             }
             Ok(())
         });
@@ -148,67 +491,232 @@ impl CollisionGridCache {
 
     /// Returns:
     /// - `Ok(true)` if `obj` would collide with cached colliders if it were
-    ///    moved to `point`, `Ok(false)` if not.
+    ///   moved to `point`, `Ok(false)` if not.
     /// - `Err(OutOfBoundsError)` if the point isn't on the grid
     pub fn would_collide_if_moved(
         &self,
         obj: &Transform2D,
-        new_loc: &IVec2,
+        new_loc: &IVec3,
     ) -> Result<bool, LightError> {
         let mut obj = obj.clone();
         obj.loc = new_loc.xyy().as_vec3();
         // Check overlapping rect
         let res = for_points_on_transform(&obj, |point| {
-            if self.collides(point)? {
+            if self.collides(point.extend(new_loc.z))? {
                 return Err(LightError::TerminateEarly);
             }
             Ok(())
         });
         match res {
-            Err(LightError::TerminateEarly) => return Ok(true),
+            Err(LightError::TerminateEarly) => Ok(true),
             Ok(_) => Ok(false),
             Err(e) => Err(e),
         }
     }
 
-    /// Updates the cache by moving the specific entity's colliders
+    /// Move the cache to cover a new area, keeping any colliders which still
+    /// fall inside of it.
+    fn resize(&mut self, topleft: IVec2, size: UVec2, levels: u32) {
+        self.revision += 1;
+        self.grid = Grid3D::new(topleft, size, levels, None);
+        // Refilled as the new map's chunks load.
+        self.terrain = Arc::new(Grid3D::new(topleft, size, levels, Biome::Null));
+        self.chunks.clear();
+        self.update_move_costs();
+        for (uuid, (transform, level)) in self.entities.iter() {
+            for_points_on_transform(transform, |point| {
+                let point = point.extend(*level as i32);
+                if self.grid.get(point)?.is_none() {
+                    self.grid.set(point, Some(*uuid));
+                }
+                Ok::<(), LightError>(())
+            });
+        }
+    }
+
+    /// Mirror the terrain of the chunk `entity`, returning the area it covers
+    /// if it's on the map.
+    fn load_chunk(&mut self, entity: Entity, grid: &BiomeGrid) -> Option<Rect2D> {
+        self.revision += 1;
+        let rect = grid.rect().intersect(*self.rect());
+        self.chunks.insert(entity, rect);
+        let terrain = Arc::make_mut(&mut self.terrain);
+        for level in 0..grid.levels() {
+            for (tile, biome) in grid.iter_level(level) {
+                if let Ok(terrain) = terrain.get_mut(tile.extend(level as i32)) {
+                    *terrain = biome;
+                    let tile = self.tiles.get(biome);
+                    if !tile.is_obstacle() {
+                        self.cheapest_move_cost = self.cheapest_move_cost.min(tile.move_cost);
+                    }
+                }
+            }
+        }
+        (!rect.is_empty()).then_some(rect)
+    }
+
+    /// Forget the terrain of the chunk `entity`, once it's unloaded, returning
+    /// the area it covered.
+    fn unload_chunk(&mut self, entity: Entity) -> Option<Rect2D> {
+        let rect = self.chunks.remove(&entity)?;
+        self.revision += 1;
+        let terrain = Arc::make_mut(&mut self.terrain);
+        for level in 0..terrain.levels() as i32 {
+            for_points_in_rect(&rect, |tile| {
+                terrain.set(tile.extend(level), Biome::Null);
+                Ok::<(), ()>(())
+            });
+        }
+        (!rect.is_empty()).then_some(rect)
+    }
+
+    /// Updates the cache by moving the specific entity's colliders, returning
+    /// where they were before.
     #[inline]
-    fn move_entity(&mut self, transform: &Transform2D, uuid: Entity) {
+    fn move_entity(
+        &mut self,
+        transform: &Transform2D,
+        level: u32,
+        uuid: Entity,
+    ) -> Option<(Transform2D, u32)> {
         // Note: Works on the assumption that there may only be a single
         // collidable on a given point.
-        if let Some(old_transform) = self.entities.insert(uuid, transform.clone()) {
-            for_points_on_transform(&old_transform, |point| {
-                self.grid.set(point, None);
+        self.revision += 1;
+        let old = self.entities.insert(uuid, (transform.clone(), level));
+        if let Some((old_transform, old_level)) = &old {
+            for_points_on_transform(old_transform, |point| {
+                self.grid.set(point.extend(*old_level as i32), None);
                 Ok::<(), ()>(())
             });
         }
         // Check if another exists, if so don't override. Collision detection will report.
         for_points_on_transform(transform, move |point| {
+            let point = point.extend(level as i32);
             if self.grid.get(point)?.is_none() {
                 self.grid.set(point, Some(uuid));
             }
             Ok::<(), LightError>(())
         });
+        old
+    }
+
+    /// Drop an entity's colliders, e.g. once it's despawned, returning where
+    /// they were.
+    fn remove_entity(&mut self, uuid: Entity) -> Option<(Transform2D, u32)> {
+        let old = self.entities.remove(&uuid);
+        if let Some((transform, level)) = &old {
+            self.revision += 1;
+            for_points_on_transform(transform, |point| {
+                let point = point.extend(*level as i32);
+                if self.grid.get(point)? == &Some(uuid) {
+                    self.grid.set(point, None);
+                }
+                Ok::<(), LightError>(())
+            });
+        }
+        old
     }
 }
 
-fn sys_update_collision_cache(
+/// Keep the cache covering the local map when the [`MapSettings`] change.
+/// Movers are part of the local map, those left outside of it are removed.
+fn sys_fit_to_map(
+    mut cmd: Commands,
+    map: Res<MapSettings>,
     mut cache: ResMut<CollisionGridCache>,
-    q: Query<(Entity, &Transform2D, &ImmobileObstacle), Changed<Transform2D>>,
+    mut changes: EventWriter<CollisionChanged>,
+    movers: Query<(Entity, &Transform2D), With<LayerableCollider>>,
 ) {
-    for (entity, transform, _obstacle) in q.iter() {
+    let rect = map.rect();
+    let levels = map.depth + 1;
+    if !map.is_changed() || (*cache.grid.rect() == rect && cache.grid.levels() == levels) {
+        return;
+    }
+    cache.resize(map.origin, map.size, levels);
+    changes.send(CollisionChanged::All);
+    for (entity, transform) in movers.iter() {
+        if !rect.contains_exclusive_max(transform.loc.xy()) {
+            cmd.entity(entity).despawn();
+        }
+    }
+}
+
+/// Mirror the terrain of every loaded [`BiomeGrid`] and the [`TileTable`]
+/// into the cache. Only the chunks loaded, changed or unloaded are copied,
+/// each sending a [`CollisionChanged::Area`] for every level, unless the
+/// tiles themselves changed.
+pub fn sys_update_terrain_obstacles(
+    mut cache: ResMut<CollisionGridCache>,
+    mut changes: EventWriter<CollisionChanged>,
+    tiles: Res<TileTable>,
+    grids: Query<(Entity, &BiomeGrid)>,
+    changed: Query<(), Changed<BiomeGrid>>,
+    mut removed: RemovedComponents<BiomeGrid>,
+) {
+    let all = tiles.is_changed();
+    if all {
+        changes.send(CollisionChanged::All);
+        cache.tiles = tiles.clone();
+    }
+    let mut areas = Vec::new();
+    for entity in removed.iter() {
+        areas.extend(cache.unload_chunk(entity));
+    }
+    // Including those the cache forgot when it was resized.
+    for (entity, grid) in grids.iter() {
+        if all || changed.contains(entity) || !cache.chunks.contains_key(&entity) {
+            areas.extend(cache.load_chunk(entity, grid));
+        }
+    }
+    if all {
+        cache.update_move_costs();
+        return;
+    }
+    for rect in areas {
+        for level in 0..cache.levels() {
+            changes.send(CollisionChanged::Area { rect, level });
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+pub fn sys_update_collision_cache(
+    mut cache: ResMut<CollisionGridCache>,
+    mut changes: EventWriter<CollisionChanged>,
+    q: Query<
+        (Entity, &Transform2D, &Level, &ImmobileObstacle),
+        Or<(Changed<Transform2D>, Changed<Level>)>,
+    >,
+    mut removed: RemovedComponents<ImmobileObstacle>,
+) {
+    let changed = |transform: &Transform2D, level: u32| CollisionChanged::Area {
+        rect: Rect2D::from_transform2d(transform),
+        level,
+    };
+    // Before moving the others, in case they're taking the removed ones' place.
+    for entity in removed.iter() {
+        if let Some((old, old_level)) = cache.remove_entity(entity) {
+            changes.send(changed(&old, old_level));
+        }
+    }
+    for (entity, transform, level, _obstacle) in q.iter() {
         log::info!("Moving {:?}", entity);
-        // TODO z_level
-        cache.move_entity(transform, entity);
+        if let Some((old, old_level)) = cache.move_entity(transform, level.0, entity) {
+            changes.send(changed(&old, old_level));
+        }
+        changes.send(changed(transform, level.0));
     }
 }
 
 fn sys_handle_collisions(
     cache: Res<CollisionGridCache>,
-    q: Query<(Entity, &Transform2D, &LayerableCollider), Changed<Transform2D>>,
+    q: Query<(Entity, &Transform2D, &Level, &LayerableCollider), Changed<Transform2D>>,
 ) {
-    for (entity, transform, collider) in q.iter() {
-        if let Some(uuid) = cache.transform_collides_with(transform, entity).unwrap() {
+    for (entity, transform, level, collider) in q.iter() {
+        if let Some(uuid) = cache
+            .transform_collides_with(transform, level.0, entity)
+            .unwrap()
+        {
             log::error!("Panic! Entity touching wall! {:?}", transform);
             panic!("Overlapping entities");
         }
@@ -218,13 +726,16 @@ fn sys_handle_collisions(
 #[derive(Debug)]
 struct AStar2DSearchState<'i> {
     calculated: HashMap<IVec2, OrderedFloat<f32>>,
-    calculated_: Grid2D<Option<OrderedFloat<f32>>>,
+    calculated_: SparseGrid3D<OrderedFloat<f32>>,
+    /// The point each explored point was most cheaply reached from.
+    came_from: SparseGrid3D<IVec3>,
     to_explore: BinaryHeap<Reverse<FunctionalTuple>>,
     col_cache: &'i CollisionGridCache,
 }
 
+/// A cost paired with the point it's for, ordered by the cost alone.
 #[derive(PartialEq, Debug)]
-struct FunctionalTuple(OrderedFloat<f32>, IVec2);
+pub struct FunctionalTuple(pub OrderedFloat<f32>, pub IVec3);
 
 impl Eq for FunctionalTuple {}
 impl Ord for FunctionalTuple {
@@ -234,24 +745,21 @@ impl Ord for FunctionalTuple {
 }
 impl PartialOrd for FunctionalTuple {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        self.0.partial_cmp(&other.0)
+        Some(self.cmp(other))
     }
 }
 
 impl<'i> AStar2DSearchState<'i> {
-    fn new(col_cache: &'i CollisionGridCache, start_node: &Transform2D) -> Self {
+    fn new(col_cache: &'i CollisionGridCache, start_node: IVec3) -> Self {
         let mut s = Self {
             calculated: default(),
-            calculated_: Grid2D::new(
-                col_cache.grid.rect().min,
-                col_cache.grid.rect().size().as_uvec2(),
-                None,
-            ),
+            calculated_: SparseGrid3D::new(*col_cache.grid.rect(), col_cache.grid.levels()),
+            came_from: SparseGrid3D::new(*col_cache.grid.rect(), col_cache.grid.levels()),
             to_explore: default(),
             col_cache,
         };
         //s.calculated.insert(start_node.as_tile(), 0.0.into());
-        s.calculated_.set(start_node.as_tile(), Some(0.0.into()));
+        s.calculated_.set(start_node, 0.0.into());
         s
     }
     fn dbg_dump_to_log(&self) {
@@ -299,207 +807,481 @@ impl<'i> AStar2DSearchState<'i> {
     }
 
     #[inline]
-    fn calc_heuristic_of_point(node: Vec2, goal: &Vec2) -> f32 {
-        goal.distance(node)
+    fn calc_heuristic_of_point(&self, node: IVec3, goal: &LevelPoint) -> f32 {
+        octile_distance(node, goal.tile()) * self.col_cache.cheapest_move_cost()
+    }
+    /// Whether the mover shaped like `start` could stand on `point`.
+    #[inline]
+    fn passable(&self, start: &Transform2D, point: IVec3) -> bool {
+        !self
+            .col_cache
+            .would_collide_if_moved(start, &point)
+            // If fails to unwrap, then it's because we looked at an out-of-bounds point.
+            .unwrap_or_else(|e| {
+                debug_assert_eq!(e, LightError::OutOfBoundsError);
+                true
+            })
     }
     #[inline]
-    fn explore_point(&mut self, start: &Transform2D, new_point: IVec2, goal: &Vec2, mut cost: f32) {
+    fn explore_point(
+        &mut self,
+        start: &Transform2D,
+        from: IVec3,
+        new_point: IVec3,
+        goal: &LevelPoint,
+        mut cost: f32,
+    ) {
         //if !self.calculated.contains_key(&new_point) {
         if let Ok(known) = self.calculated_.get(new_point) {
-            if known.is_some() {
-                return;
+            // Points found to collide are NaN, which is never beaten.
+            if let Some(known) = known {
+                if known.is_nan() || cost >= known.0 {
+                    return;
+                }
             }
-            if self
-                .col_cache
-                .would_collide_if_moved(start, &new_point)
-                // If fails to unwrap, then it's because we looked at an out-of-bounds point.
-                .unwrap_or_else(|e| {
-                    debug_assert_eq!(e, LightError::OutOfBoundsError);
-                    true
-                })
-            {
+            if known.is_none() && !self.passable(start, new_point) {
                 cost = f32::NAN;
             } else {
-                let functional =
-                    AStar2DSearchState::calc_heuristic_of_point(new_point.as_vec2(), goal) + cost;
+                let functional = self.calc_heuristic_of_point(new_point, goal) + cost;
                 self.to_explore.push(Reverse(FunctionalTuple(
                     OrderedFloat(functional),
                     new_point,
                 )));
+                self.came_from.set(new_point, from);
             }
-            self.calculated_.set(new_point, Some(OrderedFloat(cost)));
+            self.calculated_.set(new_point, OrderedFloat(cost));
             //self.calculated.insert(new_point, OrderedFloat(cost));
         }
     }
 
-    fn explore_neighbors(&mut self, transform: &Transform2D, goal: &Vec2, cost: f32) {
-        let node = transform.as_tile();
-        let left = IVec2 { x: -1, y: 0 } + node;
-        let right = IVec2 { x: 1, y: 0 } + node;
-        let up = IVec2 { x: 0, y: 1 } + node;
-        let down = IVec2 { x: 0, y: -1 } + node;
-        let upleft = IVec2 { x: -1, y: 1 } + node;
-        let upright = IVec2 { x: 1, y: 1 } + node;
-        let downleft = IVec2 { x: -1, y: -1 } + node;
-        let downright = IVec2 { x: 1, y: -1 } + node;
+    fn explore_neighbors(
+        &mut self,
+        transform: &Transform2D,
+        level: u32,
+        goal: &LevelPoint,
+        cost: f32,
+    ) {
+        let node = transform.as_tile().extend(level as i32);
+        let left = IVec3 { x: -1, y: 0, z: 0 } + node;
+        let right = IVec3 { x: 1, y: 0, z: 0 } + node;
+        let up = IVec3 { x: 0, y: 1, z: 0 } + node;
+        let down = IVec3 { x: 0, y: -1, z: 0 } + node;
+        let col_cache = self.col_cache;
+        let step = |to| col_cache.step_cost(node, to) + cost;
         // Note: Could speed up even more by only exploring in direction?
-        self.explore_point(&transform, left, goal, 1.0 + cost);
-        self.explore_point(&transform, right, goal, 1.0 + cost);
-        self.explore_point(&transform, up, goal, 1.0 + cost);
-        self.explore_point(&transform, down, goal, 1.0 + cost);
-        self.explore_point(&transform, upleft, goal, 1.4 + cost);
-        self.explore_point(&transform, upright, goal, 1.4 + cost);
-        self.explore_point(&transform, downleft, goal, 1.4 + cost);
-        self.explore_point(&transform, downright, goal, 1.4 + cost);
-    }
-    fn cheapest_neighbor(&mut self, node: IVec2) -> IVec2 {
-        let left = IVec2 { x: -1, y: 0 } + node;
-        let right = IVec2 { x: 1, y: 0 } + node;
-        let up = IVec2 { x: 0, y: 1 } + node;
-        let down = IVec2 { x: 0, y: -1 } + node;
-
-        let mut neighbors = [
-            (FunctionalTuple(
-                self.calculated_
-                    .get(up)
-                    .unwrap_or(&Some(f32::MAX.into()))
-                    .unwrap_or(f32::MAX.into()),
-                up,
-            )),
-            (FunctionalTuple(
-                self.calculated_
-                    .get(down)
-                    .unwrap_or(&Some(f32::MAX.into()))
-                    .unwrap_or(f32::MAX.into()),
-                down,
-            )),
-            (FunctionalTuple(
-                self.calculated_
-                    .get(right)
-                    .unwrap_or(&Some(f32::MAX.into()))
-                    .unwrap_or(f32::MAX.into()),
-                right,
-            )),
-            (FunctionalTuple(
-                self.calculated_
-                    .get(left)
-                    .unwrap_or(&Some(f32::MAX.into()))
-                    .unwrap_or(f32::MAX.into()),
-                left,
-            )),
-        ];
-        neighbors.sort();
-        neighbors.first().unwrap().1
+        self.explore_point(transform, node, left, goal, step(left));
+        self.explore_point(transform, node, right, goal, step(right));
+        self.explore_point(transform, node, up, goal, step(up));
+        self.explore_point(transform, node, down, goal, step(down));
+        // Diagonals can't cut the corner of anything, or movers would clip it
+        // on their way past.
+        for (x, y) in [(left, up), (right, up), (left, down), (right, down)] {
+            if self.passable(transform, x) && self.passable(transform, y) {
+                let diagonal = x + y - node;
+                let cost = self.col_cache.step_cost(node, diagonal) + cost;
+                self.explore_point(transform, node, diagonal, goal, cost);
+            }
+        }
+        for stairs in [node - IVec3::Z, node + IVec3::Z] {
+            if self.col_cache.stairs_connect(node, stairs) {
+                let cost = self.col_cache.step_cost(node, stairs) + cost;
+                self.explore_point(transform, node, stairs, goal, cost);
+            }
+        }
+    }
+    /// The point `node` was most cheaply reached from.
+    fn cheapest_neighbor(&mut self, node: IVec3) -> IVec3 {
+        *self.came_from.get(node).unwrap().unwrap()
     }
 
-    fn select_next_node(&mut self) -> Option<(f32, IVec2)> {
+    fn select_next_node(&mut self) -> Option<(f32, IVec3)> {
         self.to_explore
             .pop()
             //.and_then(|h| Some((self.calculated.get(&h.0 .1).unwrap().0, h.0 .1)))
-            .and_then(|h| Some((self.calculated_.get(h.0 .1).unwrap().unwrap().0, h.0 .1)))
+            .map(|h| (self.calculated_.get(h.0 .1).unwrap().unwrap().0, h.0 .1))
     }
 }
 
-/// Calculate the optimal path from `start` to `goal` using A* search algorithm
+/// Cost of the cheapest path between `from` and `to` if nothing were in the
+/// way, across tiles with a `move_cost` of 1.0.
+pub fn octile_distance(from: IVec3, to: IVec3) -> f32 {
+    let delta = (to - from).abs();
+    let diagonal = delta.x.min(delta.y) as f32;
+    let straight = (delta.x - delta.y).abs() as f32;
+    // Each level climbed costs at least a step.
+    diagonal * DIAGONAL_COST + straight * STRAIGHT_COST + delta.z as f32 * STAIRS_COST
+}
+
+/// The tile a mover at `loc`, part way to the tile at `next`, stepped from.
+pub fn step_origin(loc: Vec2, next: Vec2) -> IVec2 {
+    let behind = |loc: f32, next: f32| if next > loc { loc.floor() } else { loc.ceil() };
+    IVec2::new(behind(loc.x, next.x) as i32, behind(loc.y, next.y) as i32)
+}
+
+/// Total cost of walking `steps`, as returned by [`calc_path`].
+pub fn path_cost(col_cache: &CollisionGridCache, steps: &[LevelPoint]) -> f32 {
+    steps
+        .windows(2)
+        .map(|pair| col_cache.step_cost(pair[1].tile(), pair[0].tile()))
+        .sum()
+}
+
+/// Check a path from `start` to `goal` could exist before searching for one.
+/// `None` if it can't, `Some(false)` if `start` is the goal so there's
+/// nothing to search for.
+pub fn needs_search(col_cache: &CollisionGridCache, start: IVec3, goal: IVec3) -> Option<bool> {
+    // E.g. a mover left behind when the map moved, it's about to be despawned.
+    col_cache.collides(start).ok()?;
+    // No point searching the whole map for a goal nobody can stand on.
+    if col_cache.terrain_blocked(goal) {
+        return None;
+    }
+    Some(start != goal)
+}
+
+/// Calculate the optimal path from `start` on `level` to `goal` using
+/// `algorithm`.
+///
+/// Steps are in reverse, as in [`MovePath`]: the first is `goal` and the last
+/// is where `start` stands. Every [`PathAlgorithm`] returns them so, movers
+/// end up on their goal whichever they use. Empty if `start` is the goal.
+pub fn calc_path(
+    col_cache: &CollisionGridCache,
+    start: &Transform2D,
+    level: u32,
+    goal: LevelPoint,
+    algorithm: PathAlgorithm,
+) -> Option<Vec<LevelPoint>> {
+    let start_tile = start.as_tile().extend(level as i32);
+    if !needs_search(col_cache, start_tile, goal.tile())? {
+        return Some(Vec::new());
+    }
+    match algorithm {
+        // Hierarchical paths are found by `calc_hierarchical_path`, the whole
+        // of one is what A* would find, as is the way down a flow field.
+        PathAlgorithm::AStar | PathAlgorithm::Hierarchical | PathAlgorithm::FlowField => {
+            calc_optimal_path(col_cache, start, level, goal)
+        }
+        PathAlgorithm::JumpPoint => calc_jump_point_path(col_cache, start, level, goal),
+    }
+}
+
+/// Calculate the optimal path from `start` on `level` to `goal` using A* search
+/// algorithm
 fn calc_optimal_path(
     col_cache: &CollisionGridCache,
     start: &Transform2D,
-    goal: Vec2,
-) -> Option<Vec<Vec2>> {
-    let mut state = AStar2DSearchState::new(col_cache, start);
+    level: u32,
+    goal: LevelPoint,
+) -> Option<Vec<LevelPoint>> {
+    let start_tile = start.as_tile().extend(level as i32);
+    let mut state = AStar2DSearchState::new(col_cache, start_tile);
     let mut cur_node = start.clone();
+    let mut cur_level = level;
     let mut cost = 0.0;
     let mut next_loc;
     loop {
         // Collect costs for all neighbors
-        state.explore_neighbors(&cur_node, &goal, cost);
+        state.explore_neighbors(&cur_node, cur_level, &goal, cost);
         // Select the next best node to explore, carry the cost of getting to
         // that node. If there aren't any more nodes to explore then there was
         // no path so return None.
         (cost, next_loc) = state.select_next_node()?;
-        cur_node.loc = next_loc.as_vec2().xyy();
+        cur_node.loc = next_loc.xy().as_vec2().xyy();
+        cur_level = next_loc.z as u32;
         // If the next node gets us to the goal, we're done
-        if cur_node.as_tile() == goal.as_ivec2() {
+        if next_loc == goal.tile() {
             break;
         }
     }
 
     // Now just collect the cheapest path from the goal to where we started
-    let mut path = Vec::new();
-    let mut next = goal.as_ivec2();
+    let mut next = goal.tile();
+    let mut path = vec![LevelPoint::new(next.xy().as_vec2(), next.z as u32)];
     loop {
         next = state.cheapest_neighbor(next);
-        path.push(next.as_vec2());
-        if next == start.as_tile() {
+        path.push(LevelPoint::new(next.xy().as_vec2(), next.z as u32));
+        if next == start_tile {
             break;
         }
     }
     Some(path)
 }
 
-fn random_point_on_local_map() -> Vec2 {
-    Vec2::new(fastrand::f32(), fastrand::f32()) * LOCAL_MAP_DIMMENSIONS.as_vec2()
+/// Pick a random point of the local map a mover could stand on, on any level.
+/// Most of the levels below ground are solid rock, so tiles are tried until
+/// one isn't, up to [`GOAL_TRIES`] of them. The last is returned if none are,
+/// e.g. while the map is still loading.
+pub fn random_point_on_local_map(
+    rng: &fastrand::Rng,
+    col_cache: &CollisionGridCache,
+) -> LevelPoint {
+    let rect = col_cache.rect();
+    let mut point = LevelPoint::new(rect.min.as_vec2(), 0);
+    for _ in 0..GOAL_TRIES {
+        point = LevelPoint::new(
+            rect.min.as_vec2() + Vec2::new(rng.f32(), rng.f32()) * rect.size().as_vec2(),
+            rng.u32(0..col_cache.levels()),
+        );
+        if col_cache.collides(point.tile()) == Ok(false) {
+            break;
+        }
+    }
+    point
 }
 
-/// System which will act on Entities wth `Some(GoalLoc)` and compute an optimal
-/// `MovePath` using A* search.
-fn system_assign_optimal_path(
+/// System which will act on Entities wth `Some(GoalLoc)` and queue a
+/// [`PathRequest`] for an optimal `MovePath` using their [`PathAlgorithm`], or
+/// set them following a flow field already worked out. Entities stand still
+/// until their path is found, a new goal replaces any path still being
+/// searched for. Nothing is searched for here.
+#[allow(clippy::type_complexity)]
+pub fn system_assign_optimal_path(
     mut cmd: Commands,
     col_cache: Res<CollisionGridCache>,
-    mut q: Query<(Entity, &Transform2D, &mut GoalLoc), Changed<GoalLoc>>,
+    flow_fields: Res<FlowFields>,
+    mut rng: ResMut<SimRng>,
+    mut q: Query<
+        (
+            Entity,
+            &Transform2D,
+            &Level,
+            &mut GoalLoc,
+            Option<&PathAlgorithm>,
+        ),
+        Changed<GoalLoc>,
+    >,
 ) {
-    for (entity, transform, mut goal) in q.iter_mut() {
+    let rng = rng.stream("pathing::assign_optimal_path");
+    for (entity, transform, level, mut goal, algorithm) in q.iter_mut() {
         if let Some(goal_loc) = goal.0 {
-            match calc_optimal_path(&*col_cache, transform, goal_loc) {
-                // Path Found
-                Some(path) => {
-                    cmd.entity(entity).insert(MovePath { steps: path });
-                    goal.0 = None;
-                }
-                // No path found
-                None => {
-                    goal.0 = Some(random_point_on_local_map());
+            let mut entity = cmd.entity(entity);
+            entity.remove::<(MovePath, AbstractPath, FollowFlowField, PathTask)>();
+            let field = match algorithm.copied().unwrap_or_default() {
+                PathAlgorithm::FlowField => flow_fields.get(goal_loc.tile()),
+                _ => None,
+            };
+            // Flow fields are shared, those others already follow are
+            // followed straight away.
+            let Some(field) = field else {
+                entity.insert(PathRequest);
+                continue;
+            };
+            let start = transform.as_tile().extend(level.0 as i32);
+            goal.0 = if field.reaches(&col_cache, start) {
+                entity.insert(FollowFlowField::new(goal_loc));
+                None
+            } else {
+                Some(random_point_on_local_map(rng, &col_cache))
+            };
+        }
+    }
+}
+
+/// Start searching for the paths of queued [`PathRequest`]s on the
+/// [`AsyncComputeTaskPool`], keeping up to [`PATH_TASKS_IN_FLIGHT`] of them
+/// searching. There's no separate limit per frame: starting a task only
+/// clones a few `Arc`s, the searching itself is off the main thread, and
+/// capping those in flight already caps how many start in any one frame.
+#[allow(clippy::type_complexity)]
+fn sys_dispatch_path_requests(
+    mut cmd: Commands,
+    col_cache: Res<CollisionGridCache>,
+    hierarchy: Res<PathHierarchy>,
+    mut snapshot: ResMut<PathSnapshot>,
+    in_flight: Query<(), With<PathTask>>,
+    q: Query<
+        (
+            Entity,
+            &Transform2D,
+            &Level,
+            &GoalLoc,
+            Option<&PathAlgorithm>,
+        ),
+        With<PathRequest>,
+    >,
+) {
+    snapshot.age = snapshot.age.saturating_add(1);
+    let budget = PATH_TASKS_IN_FLIGHT.saturating_sub(in_flight.iter().len());
+    if q.is_empty() || budget == 0 {
+        return;
+    }
+    // Shared by every search for a while after what collides changes, paths
+    // found crossing a change since are searched for again.
+    let refresh = match snapshot.revision() {
+        Some(revision) => revision != col_cache.revision() && snapshot.age >= SNAPSHOT_FRAMES,
+        None => true,
+    };
+    // Copying clones the obstacle grid and the hierarchy whole, on the main
+    // thread, the terrain is only shared. Hence at most once every
+    // `SNAPSHOT_FRAMES` while things keep changing.
+    if refresh {
+        snapshot.shared = Some((Arc::new(col_cache.clone()), Arc::new(hierarchy.clone())));
+        snapshot.age = 0;
+    }
+    let (shared_cache, shared_hierarchy) = snapshot.shared.as_ref().unwrap();
+    let revision = shared_cache.revision();
+    let pool = AsyncComputeTaskPool::get();
+    for (entity, transform, level, goal, algorithm) in q.iter().take(budget) {
+        let mut entity = cmd.entity(entity);
+        entity.remove::<PathRequest>();
+        let Some(goal) = goal.0 else {
+            continue;
+        };
+        let job = PathJob {
+            col_cache: shared_cache.clone(),
+            hierarchy: shared_hierarchy.clone(),
+            start: transform.clone(),
+            level: level.0,
+            goal,
+            algorithm: algorithm.copied().unwrap_or_default(),
+        };
+        entity.insert(PathTask {
+            task: pool.spawn(async move { job.run() }),
+            goal,
+            start: transform.loc,
+            level: level.0,
+            revision,
+        });
+    }
+}
+
+/// Assign the paths of finished [`PathTask`]s, and cache the flow fields
+/// found. Those found for an old goal are dropped, and those found before the
+/// entity moved, or crossing anything that collides differently since, are
+/// searched for again.
+fn sys_apply_finished_paths(
+    mut cmd: Commands,
+    col_cache: Res<CollisionGridCache>,
+    mut flow_fields: ResMut<FlowFields>,
+    mut rng: ResMut<SimRng>,
+    mut snapshot: ResMut<PathSnapshot>,
+    mut changes: EventReader<CollisionChanged>,
+    mut q: Query<(Entity, &mut PathTask, &Transform2D, &Level, &mut GoalLoc)>,
+) {
+    let rng = rng.stream("pathing::apply_finished_paths");
+    for change in changes.iter() {
+        snapshot.changes.push((col_cache.revision(), *change));
+    }
+    for (entity, mut task, transform, level, mut goal) in q.iter_mut() {
+        if !task.task.is_finished() {
+            continue;
+        }
+        // Already finished, so this won't block.
+        let found = future::block_on(&mut task.task);
+        let mut entity = cmd.entity(entity);
+        entity.remove::<PathTask>();
+        if goal.0 != Some(task.goal) {
+            continue;
+        }
+        let crossed = match &found {
+            Some(FoundPath::Steps(steps, _)) => snapshot.crosses_changes(steps, task.revision),
+            Some(FoundPath::Field(field)) => snapshot.changes_field(field, task.revision),
+            // Somewhere may have opened up.
+            None => snapshot.changed_since(task.revision).next().is_some(),
+        };
+        if crossed {
+            // Search what collides now rather than the same snapshot again.
+            snapshot.age = SNAPSHOT_FRAMES;
+        }
+        if crossed || task.start != transform.loc || task.level != level.0 {
+            entity.insert(PathRequest);
+            continue;
+        }
+        match found {
+            // Path Found
+            Some(FoundPath::Steps(path, abstract_path)) => {
+                entity.insert(MovePath { steps: path });
+                if let Some(abstract_path) = abstract_path {
+                    entity.insert(abstract_path);
                 }
+                goal.0 = None;
+            }
+            // Field Found, kept for any others headed there
+            Some(FoundPath::Field(field)) => {
+                flow_fields.insert(field);
+                entity.insert(FollowFlowField::new(task.goal));
+                goal.0 = None;
+            }
+            // No path found
+            None => {
+                goal.0 = Some(random_point_on_local_map(rng, &col_cache));
             }
         }
     }
+    // Changes only matter to searches of older snapshots.
+    let oldest = q
+        .iter()
+        .map(|(_, task, ..)| task.revision)
+        .chain(snapshot.revision())
+        .min();
+    snapshot
+        .changes
+        .retain(|(changed, _)| oldest.is_some_and(|oldest| *changed > oldest));
 }
 
 /// System that will move Entities along their given `MovePath`, once they reach
-/// the end of their assignments, then assign a new goal.
-fn system_move_on_optimal_path(
-    time: Res<Time>,
+/// the end of their assignments, then assign a new goal. Paths blocked since
+/// they were found, e.g. by walls of a chunk loaded later, are given up on.
+///
+/// `Speed` is divided by the [`CollisionGridCache::step_move_cost`] of the
+/// step an entity is on, so crossing each step takes as long as it costs.
+#[allow(clippy::type_complexity)]
+pub fn system_move_on_optimal_path(
+    time: Res<SimTime>,
+    col_cache: Res<CollisionGridCache>,
+    mut rng: ResMut<SimRng>,
     mut q: Query<(
         Entity,
         &mut MovePath,
         &mut Transform2D,
+        &mut Level,
         &Speed,
         &mut GoalLoc,
+        Option<&AbstractPath>,
     )>,
 ) {
-    for (entity, mut path, mut rect, speed, mut goal) in q.iter_mut() {
-        let mut travel = speed.0 * time.delta().as_secs_f32();
+    let rng = rng.stream("pathing::move_on_optimal_path");
+    for (entity, mut path, mut rect, mut level, speed, mut goal, abstract_path) in q.iter_mut() {
+        let mut travel = speed.0 * time.delta_seconds();
         // While we have time to travel, contiue doing so.
         loop {
             // First check if there's nothing left to move, in which case we're
             // done. Just assign a new goal.
             if path.steps.is_empty() {
-                goal.0 = Some(random_point_on_local_map());
+                // Unless there's more of the path still to refine.
+                if abstract_path.map_or(true, |abstract_path| abstract_path.waypoints.is_empty()) {
+                    goal.0 = Some(random_point_on_local_map(rng, &col_cache));
+                }
                 break;
             }
-            let dist = rect.loc.xy().distance(*path.steps.last().unwrap());
+            let next = *path.steps.last().unwrap();
+            if col_cache
+                .would_collide_if_moved(&rect, &next.tile())
+                .unwrap_or(true)
+            {
+                path.steps.clear();
+                continue;
+            }
+            let from = step_origin(rect.loc.xy(), next.loc).extend(level.0 as i32);
+            let move_cost = col_cache.step_move_cost(from, next.tile());
+            let dist = rect.loc.xy().distance(next.loc) * move_cost;
             // Check if we can simply move to the point, with our currently alloted travel distance.
             if dist <= travel {
                 travel -= dist;
-                let res = path.steps.pop().unwrap();
-                (rect.loc.x, rect.loc.y) = (res[0], res[1]);
+                path.steps.pop();
+                (rect.loc.x, rect.loc.y) = (next.loc.x, next.loc.y);
+                // Stairs are climbed in place.
+                if level.0 != next.level {
+                    level.0 = next.level;
+                }
                 // We've moved to the point, need to loop back around to the next point.
                 continue;
             }
             // We can't move directly to the point, let's get as close as we can.
-            let direction = (*path.steps.last().unwrap() - rect.loc.xy()).normalize();
-            rect.loc.x += direction.x * travel;
-            rect.loc.y += direction.y * travel;
+            let direction = (next.loc - rect.loc.xy()).normalize();
+            rect.loc.x += direction.x * travel / move_cost;
+            rect.loc.y += direction.y * travel / move_cost;
             break;
         }
     }
@@ -507,10 +1289,13 @@ fn system_move_on_optimal_path(
 
 #[derive(Bundle)]
 struct Player {
+    species: Species,
     speed: Speed,
     goal: GoalLoc,
     rect: CharTexture,
     transform: Transform2D,
+    level: Level,
+    algorithm: PathAlgorithm,
     collider: LayerableCollider,
 }
 
@@ -518,27 +1303,82 @@ struct Player {
 struct ColliderWall {
     texture: CharTexture,
     transform: Transform2D,
+    level: Level,
     collider: ImmobileObstacle,
 }
 
-fn spawn_mv_player_over_time(mut cmd: Commands, mut cnt: Local<usize>) {
-    if *cnt > 100000 {
+/// Spawn a mover each frame. With a [`TextMap`] they start on one of its
+/// spawns and head for one of its goals, otherwise they start at the map's
+/// origin.
+fn spawn_mv_player_over_time(
+    mut cmd: Commands,
+    map: Res<MapSettings>,
+    text_map: Option<Res<TextMap>>,
+    creatures: Res<Creatures>,
+    mut rng: ResMut<SimRng>,
+    mut cnt: Local<usize>,
+) {
+    if *cnt > 100000 || creatures.0.is_empty() {
         return;
     }
     *cnt += 1;
+    let rng = rng.stream("pathing::spawn_mv_player_over_time");
+    let creature = &creatures.0[rng.usize(..creatures.0.len())];
+    let (start, goal) = match &text_map {
+        Some(text_map) if text_map.spawns.is_empty() => return,
+        Some(text_map) => {
+            let start = text_map.spawns[rng.usize(..text_map.spawns.len())];
+            let goal = (!text_map.goals.is_empty())
+                .then(|| text_map.goals[rng.usize(..text_map.goals.len())]);
+            (start, goal)
+        }
+        None => (
+            LevelPoint::new(map.origin.as_vec2(), 0),
+            Some(LevelPoint::new(
+                map.origin.as_vec2() + Vec2::new(0.0, 1.0),
+                0,
+            )),
+        ),
+    };
     cmd.spawn(Player {
-        speed: Speed(5.0),
-        goal: GoalLoc(Some(Vec2::new(0.0, 1.0))),
-        rect: CharTexture::new('▢', Color::RED),
+        species: Species(creature.name.clone()),
+        speed: Speed(creature.speed),
+        goal: GoalLoc(goal),
+        rect: CharTexture::new(creature.glyph, creature.color),
         transform: Transform2D {
             scale: UVec2::splat(1),
-            loc: Vec3::ZERO,
+            loc: start.loc.extend(0.0),
+        },
+        level: Level(start.level),
+        // Movers on a text map share its few goals. Jump Point Search falls
+        // back to A* where terrain is slower or faster to cross.
+        algorithm: if text_map
+            .as_ref()
+            .is_some_and(|text_map| !text_map.goals.is_empty())
+        {
+            PathAlgorithm::FlowField
+        } else if map.size.max_element() > LARGE_MAP {
+            PathAlgorithm::Hierarchical
+        } else {
+            PathAlgorithm::JumpPoint
         },
         collider: default(),
     });
 }
 
-fn spawn_collider_walls(mut cmd: Commands) {
+/// Spawn the walls of the [`TextMap`], or a single example wall without one.
+fn spawn_collider_walls(mut cmd: Commands, text_map: Option<Res<TextMap>>) {
+    if let Some(text_map) = text_map {
+        for wall in text_map.walls.iter() {
+            cmd.spawn(ColliderWall {
+                texture: wall.texture.clone(),
+                transform: wall.transform.clone(),
+                level: Level(wall.level),
+                collider: default(),
+            });
+        }
+        return;
+    }
     cmd.spawn(ColliderWall {
         texture: CharTexture::from_char('▢'),
         transform: Transform2D {
@@ -549,6 +1389,355 @@ fn spawn_collider_walls(mut cmd: Commands) {
                 z: 0.0,
             },
         },
+        level: default(),
         collider: default(),
     });
 }
+
+#[cfg(test)]
+impl CollisionGridCache {
+    /// The cache as it is once `text_map` has loaded and its walls spawned.
+    pub fn from_text_map(text_map: &TextMap, tiles: TileTable) -> Self {
+        let rect = *text_map.levels.rect();
+        let mut cache = Self::new(
+            rect.min,
+            rect.size().as_uvec2(),
+            text_map.levels.levels(),
+            tiles,
+        );
+        cache.terrain = text_map.levels.clone();
+        cache.update_move_costs();
+        for (id, wall) in text_map.walls.iter().enumerate() {
+            cache.move_entity(&wall.transform, wall.level, Entity::from_raw(id as u32));
+        }
+        cache
+    }
+
+    /// Stand `entity` on `point` as an obstacle, returning the change.
+    pub fn place_obstacle(&mut self, point: IVec3, entity: Entity) -> CollisionChanged {
+        let transform = Transform2D {
+            scale: UVec2::ONE,
+            loc: point.xy().as_vec2().extend(0.0),
+        };
+        self.move_entity(&transform, point.z as u32, entity);
+        CollisionChanged::Area {
+            rect: Rect2D::from_transform2d(&transform),
+            level: point.z as u32,
+        }
+    }
+
+    /// Take away the obstacle `entity`, returning the change.
+    pub fn remove_obstacle(&mut self, entity: Entity) -> Option<CollisionChanged> {
+        let (transform, level) = self.remove_entity(entity)?;
+        Some(CollisionChanged::Area {
+            rect: Rect2D::from_transform2d(&transform),
+            level,
+        })
+    }
+}
+
+/// Checks of every pathing algorithm against the text maps in `data/maps`.
+#[cfg(test)]
+pub mod tests {
+    use std::path::{Path, PathBuf};
+
+    use bevy::tasks::TaskPool;
+    use num_traits::FromPrimitive;
+    use strum::EnumCount;
+
+    use super::*;
+    use crate::script::content::{Content, DEFAULT_DATA_DIR};
+
+    /// Maps where no spawn can reach any goal.
+    const UNREACHABLE: &[&str] = &["unreachable.ron"];
+
+    pub struct Fixture {
+        pub name: String,
+        pub map: TextMap,
+        pub cache: CollisionGridCache,
+    }
+
+    impl Fixture {
+        pub fn reachable(&self) -> bool {
+            !UNREACHABLE.contains(&self.name.as_str())
+        }
+
+        /// Every trip from a spawn to a goal, the spawn as a mover stood on it.
+        pub fn trips(&self) -> impl Iterator<Item = (Transform2D, u32, LevelPoint)> + '_ {
+            self.map.spawns.iter().flat_map(|spawn| {
+                let mover = Transform2D {
+                    scale: UVec2::ONE,
+                    loc: spawn.loc.extend(0.0),
+                };
+                self.map
+                    .goals
+                    .iter()
+                    .map(move |goal| (mover.clone(), spawn.level, *goal))
+            })
+        }
+    }
+
+    fn data_dir() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("..")
+            .join(DEFAULT_DATA_DIR)
+    }
+
+    /// The tiles in `data/tiles.ron`.
+    pub fn shipped_tiles() -> TileTable {
+        Content::load(&data_dir()).unwrap().tiles
+    }
+
+    /// Every map in `data/maps`, loaded with the shipped tiles.
+    pub fn fixtures() -> Vec<Fixture> {
+        let data = data_dir();
+        let tiles = shipped_tiles();
+        let mut paths: Vec<_> = std::fs::read_dir(data.join("maps"))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "ron"))
+            .collect();
+        paths.sort();
+        assert!(!paths.is_empty());
+        paths
+            .into_iter()
+            .map(|path| {
+                let map = TextMap::read(&path, IVec2::new(-4, 7)).unwrap();
+                let name = path.file_name().unwrap().to_string_lossy().into_owned();
+                assert!(!map.spawns.is_empty() && !map.goals.is_empty(), "{}", name);
+                Fixture {
+                    cache: CollisionGridCache::from_text_map(&map, tiles.clone()),
+                    name,
+                    map,
+                }
+            })
+            .collect()
+    }
+
+    /// Check a mover could walk `steps`, as returned by [`calc_path`], from
+    /// `start` to `goal`, returning what it costs.
+    pub fn walk(
+        cache: &CollisionGridCache,
+        start: IVec3,
+        steps: &[LevelPoint],
+        goal: LevelPoint,
+    ) -> f32 {
+        let tiles: Vec<IVec3> = steps.iter().rev().map(|step| step.tile()).collect();
+        assert_eq!(tiles[0], start, "path doesn't start where the mover is");
+        assert_eq!(
+            tiles.last(),
+            Some(&goal.tile()),
+            "path doesn't end on the goal"
+        );
+        for pair in tiles.windows(2) {
+            let (from, to) = (pair[0], pair[1]);
+            let delta = to - from;
+            assert_eq!(cache.collides(to), Ok(false), "steps onto {}", to);
+            if delta.z != 0 {
+                assert!(
+                    cache.stairs_connect(from, to),
+                    "no stairs {} to {}",
+                    from,
+                    to
+                );
+                continue;
+            }
+            assert!(
+                delta != IVec3::ZERO && delta.x.abs() <= 1 && delta.y.abs() <= 1,
+                "{} to {} isn't a step",
+                from,
+                to
+            );
+            if delta.x != 0 && delta.y != 0 {
+                let corners = [from + IVec3::X * delta.x, from + IVec3::Y * delta.y];
+                assert!(
+                    corners
+                        .iter()
+                        .all(|corner| cache.collides(*corner) == Ok(false)),
+                    "{} to {} cuts a corner",
+                    from,
+                    to
+                );
+            }
+        }
+        path_cost(cache, steps)
+    }
+
+    /// Cost of the cheapest path from `start` to `goal`, found by searching
+    /// the whole map.
+    pub fn cheapest(cache: &CollisionGridCache, start: IVec3, goal: LevelPoint) -> Option<f32> {
+        FlowField::new(cache, goal.tile()).cost(start)
+    }
+
+    #[test]
+    fn a_star_on_fixtures() {
+        for fixture in fixtures() {
+            for (mover, level, goal) in fixture.trips() {
+                let start = mover.as_tile().extend(level as i32);
+                let path = calc_path(&fixture.cache, &mover, level, goal, PathAlgorithm::AStar);
+                let expected = cheapest(&fixture.cache, start, goal);
+                assert_eq!(
+                    path.is_some(),
+                    fixture.reachable(),
+                    "{}: {} to {:?}",
+                    fixture.name,
+                    start,
+                    goal
+                );
+                assert_eq!(path.is_some(), expected.is_some(), "{}", fixture.name);
+                if let (Some(path), Some(expected)) = (path, expected) {
+                    let cost = walk(&fixture.cache, start, &path, goal);
+                    assert!(
+                        (cost - expected).abs() < 1e-3,
+                        "{}: A* costs {}, cheapest is {}",
+                        fixture.name,
+                        cost,
+                        expected
+                    );
+                }
+            }
+        }
+    }
+
+    /// `tiles`, with `biome` costing `move_cost` to cross.
+    fn with_move_cost(tiles: &TileTable, biome: Biome, move_cost: f32) -> TileTable {
+        TileTable::from_entries((0..Biome::COUNT).map(|idx| {
+            let other = Biome::from_usize(idx).unwrap();
+            let mut def = tiles.get(other).clone();
+            if other == biome {
+                def.move_cost = move_cost;
+            }
+            (other, def)
+        }))
+        .unwrap()
+    }
+
+    fn check_jump_point_matches_a_star(fixture: &Fixture) {
+        for (mover, level, goal) in fixture.trips() {
+            let start = mover.as_tile().extend(level as i32);
+            let a_star = calc_path(&fixture.cache, &mover, level, goal, PathAlgorithm::AStar);
+            let jump_point = calc_jump_point_path(&fixture.cache, &mover, level, goal);
+            assert_eq!(
+                jump_point.is_some(),
+                a_star.is_some(),
+                "{}: {} to {:?}",
+                fixture.name,
+                start,
+                goal
+            );
+            if let (Some(jump_point), Some(a_star)) = (jump_point, a_star) {
+                let cost = walk(&fixture.cache, start, &jump_point, goal);
+                let expected = path_cost(&fixture.cache, &a_star);
+                assert!(
+                    (cost - expected).abs() < 1e-3,
+                    "{}: jump point path costs {}, A* found {}",
+                    fixture.name,
+                    cost,
+                    expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn jump_point_matches_a_star() {
+        for fixture in fixtures() {
+            check_jump_point_matches_a_star(&fixture);
+        }
+    }
+
+    /// The shipped stairs cost as much as a step, so a search counting every
+    /// climb as one step would still find the cheapest paths on them. Slow
+    /// stairs make the tunnel of `stairs_shortcut.ron` dearer than going
+    /// around.
+    #[test]
+    fn jump_point_matches_a_star_on_slow_stairs() {
+        for mut fixture in fixtures() {
+            let tiles = with_move_cost(&fixture.cache.tiles, Biome::Stairs, 4.0);
+            fixture.cache = CollisionGridCache::from_text_map(&fixture.map, tiles);
+            check_jump_point_matches_a_star(&fixture);
+        }
+    }
+
+    #[test]
+    fn only_paths_crossing_changes_are_stale() {
+        let steps: Vec<_> = (0..5)
+            .map(|x| LevelPoint::new(Vec2::new(x as f32, 0.0), 0))
+            .collect();
+        let wall = |x, y, level| CollisionChanged::Area {
+            rect: Rect2D::new(x, y, x + 1, y + 1),
+            level,
+        };
+        let snapshot = |changes: Vec<(u64, CollisionChanged)>| PathSnapshot {
+            changes,
+            ..Default::default()
+        };
+        // Beside the path, it may have cut that corner.
+        assert!(snapshot(vec![(2, wall(2, 1, 0))]).crosses_changes(&steps, 1));
+        // Already searched around.
+        assert!(!snapshot(vec![(1, wall(2, 1, 0))]).crosses_changes(&steps, 1));
+        // Too far away, or on another level.
+        assert!(!snapshot(vec![(2, wall(2, 2, 0))]).crosses_changes(&steps, 1));
+        assert!(!snapshot(vec![(2, wall(2, 0, 1))]).crosses_changes(&steps, 1));
+        assert!(snapshot(vec![(2, CollisionChanged::All)]).crosses_changes(&steps, 1));
+    }
+
+    /// Flow fields are worked out on the task pool like any path, assigning
+    /// a goal only queues the search.
+    #[test]
+    fn flow_fields_are_found_off_the_frame() {
+        AsyncComputeTaskPool::init(TaskPool::default);
+        let fixture = fixtures().into_iter().find(Fixture::reachable).unwrap();
+        let (mover, level, goal) = fixture.trips().next().unwrap();
+        let mut hierarchy = PathHierarchy::default();
+        hierarchy.rebuild(&fixture.cache);
+        let mut world = World::new();
+        world.insert_resource(fixture.cache.clone());
+        world.insert_resource(hierarchy);
+        world.insert_resource(SimRng::new(0));
+        world.init_resource::<FlowFields>();
+        world.init_resource::<PathSnapshot>();
+        world.init_resource::<Events<CollisionChanged>>();
+        let entity = world
+            .spawn((
+                mover,
+                Level(level),
+                GoalLoc(Some(goal)),
+                PathAlgorithm::FlowField,
+            ))
+            .id();
+
+        let mut assign = Schedule::new();
+        assign.add_system(system_assign_optimal_path);
+        assign.run(&mut world);
+        assert!(world.resource::<FlowFields>().get(goal.tile()).is_none());
+        assert!(world.get::<PathRequest>(entity).is_some());
+        assert!(world.get::<FollowFlowField>(entity).is_none());
+
+        let mut search = Schedule::new();
+        search.add_systems((
+            sys_dispatch_path_requests,
+            sys_apply_finished_paths.after(sys_dispatch_path_requests),
+        ));
+        for _ in 0..1000 {
+            search.run(&mut world);
+            if world.get::<FollowFlowField>(entity).is_some() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        assert!(world.get::<FollowFlowField>(entity).is_some());
+        assert!(world.resource::<FlowFields>().get(goal.tile()).is_some());
+        assert_eq!(world.get::<GoalLoc>(entity).unwrap().0, None);
+    }
+
+    #[test]
+    fn step_origin_is_behind_the_mover() {
+        let next = Vec2::new(4.0, 4.0);
+        assert_eq!(step_origin(Vec2::new(3.5, 3.5), next), IVec2::new(3, 3));
+        assert_eq!(step_origin(Vec2::new(4.5, 4.0), next), IVec2::new(5, 4));
+        assert_eq!(step_origin(Vec2::new(4.0, 4.5), next), IVec2::new(4, 5));
+        // Not yet moved off the tile.
+        assert_eq!(step_origin(Vec2::new(5.0, 3.0), next), IVec2::new(5, 3));
+    }
+}
@@ -0,0 +1,330 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use crate::prelude::*;
+
+use super::local_map::Biome;
+use super::text_map::{level_rows, push_wall, WallRun};
+
+/// Prefabs stay at least this many tiles clear of the map's origin, where
+/// movers are spawned.
+pub const SPAWN_CLEARANCE: i32 = 8;
+
+/// Spots tried for each prefab in a chunk before giving up on it.
+const PLACE_ATTEMPTS: usize = 8;
+
+/// What a glyph of a prefab stands for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum PrefabTile {
+    /// Leaves the generated terrain as it is, so prefabs needn't be square.
+    Keep,
+    Tile(Biome),
+    /// An [`ImmobileObstacle`](super::pathing::ImmobileObstacle) standing on
+    /// the generated terrain.
+    Wall,
+}
+
+/// Spaces keep the terrain and `#` is a wall in every prefab, unless its own
+/// legend says otherwise.
+fn default_legend() -> HashMap<char, PrefabTile> {
+    HashMap::from([(' ', PrefabTile::Keep), ('#', PrefabTile::Wall)])
+}
+
+/// A prefab as written, e.g.
+///
+/// ```ron
+/// (
+///     name: "hut",
+///     chance: 0.5,
+///     allowed: [Grassland],
+///     legend: { '.': Tile(Cave) },
+///     rows: r"
+/// ###
+/// #.#
+/// # #
+/// ",
+/// )
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PrefabDef {
+    pub name: String,
+    /// Chance of placing one in each chunk.
+    pub chance: f64,
+    /// Placed on the surface unless given.
+    #[serde(default)]
+    pub level: u32,
+    /// Every tile the prefab covers must be one of these.
+    pub allowed: Vec<Biome>,
+    /// What the glyphs of `rows` stand for, besides those of the
+    /// [`default_legend`].
+    #[serde(default)]
+    pub legend: HashMap<char, PrefabTile>,
+    /// Blank lines before and after the rows are ignored. Short rows are
+    /// padded with [`PrefabTile::Keep`].
+    pub rows: String,
+}
+
+/// A structure stamped into the generated terrain, such as a ruin or a camp.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Prefab {
+    pub name: String,
+    pub chance: f64,
+    pub level: u32,
+    pub allowed: Vec<Biome>,
+    pub size: UVec2,
+    /// Every tile which isn't kept, by offset from the topleft corner, in
+    /// row order.
+    tiles: Vec<(IVec2, char, PrefabTile)>,
+}
+
+impl Prefab {
+    fn from_def(def: PrefabDef) -> Result<Self, String> {
+        if !(0.0..=1.0).contains(&def.chance) {
+            return Err(format!(
+                "prefab {:?} has chance {}, expected between 0 and 1",
+                def.name, def.chance
+            ));
+        }
+        if def.allowed.is_empty() {
+            return Err(format!("prefab {:?} isn't allowed anywhere", def.name));
+        }
+        let mut legend = default_legend();
+        legend.extend(def.legend);
+
+        let rows = level_rows(&def.rows);
+        let size = UVec2::new(
+            rows.iter().map(|row| row.len()).max().unwrap_or(0) as u32,
+            rows.len() as u32,
+        );
+        if size.x == 0 || size.y == 0 {
+            return Err(format!("prefab {:?} is empty", def.name));
+        }
+        let mut tiles = Vec::new();
+        for (y, row) in rows.iter().enumerate() {
+            for (x, glyph) in row.iter().enumerate() {
+                let tile = legend.get(glyph).ok_or_else(|| {
+                    format!(
+                        "prefab {:?}, tile ({}, {}): {:?} isn't in the legend",
+                        def.name, x, y, glyph
+                    )
+                })?;
+                if *tile != PrefabTile::Keep {
+                    tiles.push((IVec2::new(x as i32, y as i32), *glyph, *tile));
+                }
+            }
+        }
+        Ok(Self {
+            name: def.name,
+            chance: def.chance,
+            level: def.level,
+            allowed: def.allowed,
+            size,
+            tiles,
+        })
+    }
+
+    /// Whether the prefab fits with its topleft corner at `at`.
+    fn fits(&self, levels: &Grid3D<Biome>, at: IVec2) -> bool {
+        let rect = Rect2D::from_corners(at, at + self.size.as_ivec2());
+        (rect.min.y..rect.max.y)
+            .flat_map(|y| (rect.min.x..rect.max.x).map(move |x| IVec2::new(x, y)))
+            .all(|tile| {
+                levels
+                    .get(tile.extend(self.level as i32))
+                    .is_ok_and(|biome| self.allowed.contains(biome))
+            })
+    }
+
+    /// Stamp the prefab's tiles with its topleft corner at `at`, returning its
+    /// walls.
+    fn stamp(&self, levels: &mut Grid3D<Biome>, at: IVec2) -> Vec<WallRun> {
+        let mut walls = Vec::new();
+        for (offset, glyph, tile) in self.tiles.iter() {
+            let tile_at = at + *offset;
+            match tile {
+                PrefabTile::Keep => (),
+                PrefabTile::Tile(biome) => levels.set(tile_at.extend(self.level as i32), *biome),
+                PrefabTile::Wall => push_wall(&mut walls, tile_at, self.level, *glyph),
+            }
+        }
+        walls
+    }
+}
+
+/// Every [`Prefab`], in the order they were defined. Earlier prefabs get the
+/// first pick of each chunk.
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct Prefabs(pub Vec<Prefab>);
+
+impl Prefabs {
+    pub fn from_defs(defs: impl IntoIterator<Item = PrefabDef>) -> Result<Self, String> {
+        let prefabs = defs
+            .into_iter()
+            .map(Prefab::from_def)
+            .collect::<Result<_, _>>()?;
+        Ok(Self(prefabs))
+    }
+}
+
+impl Default for Prefabs {
+    fn default() -> Self {
+        let n =
+            |name: &str, chance, allowed: &[Biome], legend: &[(char, PrefabTile)], rows: &str| {
+                PrefabDef {
+                    name: name.to_string(),
+                    chance,
+                    level: 0,
+                    allowed: allowed.to_vec(),
+                    legend: legend.iter().copied().collect(),
+                    rows: String::from(rows),
+                }
+            };
+        Self::from_defs([
+            n(
+                "ruin",
+                0.3,
+                &[
+                    Biome::Grassland,
+                    Biome::Desert,
+                    Biome::Tundra,
+                    Biome::Forest,
+                ],
+                &[('.', PrefabTile::Tile(Biome::Cave))],
+                r"
+##.# #
+#....#
+.....#
+# #.##
+",
+            ),
+            n(
+                "camp",
+                0.2,
+                &[Biome::Grassland, Biome::Forest, Biome::Tundra],
+                &[('A', PrefabTile::Wall), ('*', PrefabTile::Wall)],
+                r"
+A   A
+
+  *
+
+A   A
+",
+            ),
+            n(
+                "cave",
+                0.3,
+                &[Biome::Mountain],
+                &[('.', PrefabTile::Tile(Biome::Cave))],
+                r"
+  ...
+ .....
+.......
+ .....
+   .
+",
+            ),
+            n(
+                "fort",
+                0.1,
+                &[Biome::Grassland, Biome::Desert, Biome::Tundra],
+                &[('.', PrefabTile::Tile(Biome::Cave))],
+                r"
+####.####
+#.......#
+#.##.##.#
+..#...#..
+#.#####.#
+#.......#
+####.####
+",
+            ),
+        ])
+        .unwrap()
+    }
+}
+
+/// Stamp `prefabs` into the chunk held by `levels`, returning the walls to
+/// spawn along with it. Each prefab has its `chance` of being tried, at a few
+/// random spots in the chunk, and is placed at the first where every tile it
+/// covers is allowed, it's clear of `keep_clear` and it keeps a tile away
+/// from the prefabs placed before it.
+///
+/// Prefabs never cross the edge of their chunk, so chunks can be placed
+/// independently of each other. Placement only depends on the chunk's
+/// position and `seed`.
+pub fn place_prefabs(
+    levels: &mut Grid3D<Biome>,
+    prefabs: &Prefabs,
+    keep_clear: Rect2D,
+    seed: u64,
+) -> Vec<WallRun> {
+    let rect = *levels.rect();
+    let seed = mix(
+        seed,
+        ((rect.min.x as u32 as u64) << 32) | rect.min.y as u32 as u64,
+    );
+    let mut taken: Vec<Rect2D> = Vec::new();
+    let mut walls = Vec::new();
+    for (idx, prefab) in prefabs.0.iter().enumerate() {
+        let rng = fastrand::Rng::with_seed(mix(seed, idx as u64));
+        let room = rect.size() - prefab.size.as_ivec2();
+        if rng.f64() >= prefab.chance || room.x < 0 || room.y < 0 || prefab.level >= levels.levels()
+        {
+            continue;
+        }
+        let spot = (0..PLACE_ATTEMPTS)
+            .map(|_| rect.min + IVec2::new(rng.i32(0..=room.x), rng.i32(0..=room.y)))
+            .find(|at| {
+                let footprint = Rect2D::from_corners(*at, *at + prefab.size.as_ivec2());
+                footprint.intersect(keep_clear).is_empty()
+                    && taken
+                        .iter()
+                        .all(|other| footprint.intersect(other.inset(1)).is_empty())
+                    && prefab.fits(levels, *at)
+            });
+        if let Some(at) = spot {
+            log::info!("Placing prefab {} at {:?}", prefab.name, at);
+            walls.extend(prefab.stamp(levels, at));
+            taken.push(Rect2D::from_corners(at, at + prefab.size.as_ivec2()));
+        }
+    }
+    walls
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn walls_split_around_movers() {
+        let wall = WallRun {
+            transform: Transform2D {
+                scale: UVec2::new(6, 1),
+                loc: Vec3::new(2.0, 5.0, 0.0),
+            },
+            level: 1,
+            texture: CharTexture::from_char('#'),
+        };
+        let runs = |occupied: &[IVec3]| -> Vec<(i32, u32)> {
+            wall.clone()
+                .split_around(&occupied.iter().copied().collect())
+                .iter()
+                .map(|run| (run.transform.loc.x as i32, run.transform.scale.x))
+                .collect()
+        };
+        assert_eq!(runs(&[]), [(2, 6)]);
+        // On another level, or off the wall, nothing's in the way.
+        assert_eq!(runs(&[IVec3::new(3, 5, 0), IVec3::new(3, 6, 1)]), [(2, 6)]);
+        assert_eq!(runs(&[IVec3::new(4, 5, 1)]), [(2, 2), (5, 3)]);
+        assert_eq!(
+            runs(&[
+                IVec3::new(2, 5, 1),
+                IVec3::new(6, 5, 1),
+                IVec3::new(7, 5, 1)
+            ]),
+            [(3, 3)]
+        );
+    }
+}
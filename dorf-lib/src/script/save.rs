@@ -0,0 +1,635 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::app::AppExit;
+use bevy::input::ButtonState;
+use bevy::utils::Duration;
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::prelude::*;
+
+use super::content::Species;
+use super::flow_field::FollowFlowField;
+use super::hierarchy::AbstractPath;
+use super::levels::{Level, ViewLevel};
+use super::local_map::{restore_chunks, BiomeGrid, ChunkMember, MapChunk, MapSettings};
+use super::pathing::{
+    GoalLoc, ImmobileObstacle, LayerableCollider, MovePath, PathAlgorithm, Speed,
+};
+use super::sim_time::SimTime;
+use super::terrain::TerrainSettings;
+
+/// Directory saves are written to.
+pub const SAVE_DIR: &str = "saves";
+
+/// Bumped whenever saves change in a way older ones can't be read. Adding an
+/// optional field, such as a new component on [`SavedEntity`], doesn't need a
+/// new version.
+pub const SAVE_VERSION: u32 = 2;
+
+const QUICKSAVE_FILE: &str = "quicksave.ron";
+const AUTOSAVE_FILE: &str = "autosave.ron";
+const EXIT_FILE: &str = "exit.ron";
+
+const SAVE_DIALOG: &str = "save::result";
+const QUIT_UNSAVED_DIALOG: &str = "save::quit_unsaved";
+
+pub fn add_save_systems(app: &mut App, enabled: bool) {
+    app.init_resource::<SaveSettings>()
+        .add_event::<SaveRequest>()
+        .add_system(handle_save_keys)
+        .add_system(sys_autosave)
+        .add_system(sys_handle_quit_unsaved_dialog)
+        // Before the simulation runs, so everything sees a loaded save at once.
+        .add_system(sys_handle_save_requests.in_base_set(CoreSet::PreUpdate));
+}
+
+#[derive(Debug, Error)]
+pub enum SaveError {
+    #[error("couldn't access {}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("{}: couldn't write save: {source}", path.display())]
+    Serialize { path: PathBuf, source: ron::Error },
+
+    #[error("{}:{source}", path.display())]
+    Parse {
+        path: PathBuf,
+        source: ron::error::SpannedError,
+    },
+
+    #[error(
+        "{}: save is version {found}, only version {} can be loaded",
+        path.display(), SAVE_VERSION
+    )]
+    Version { path: PathBuf, found: u32 },
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct SaveSettings {
+    pub dir: PathBuf,
+    /// Real time between autosaves, never autosaved if `None`.
+    pub autosave_interval: Option<Duration>,
+}
+
+impl Default for SaveSettings {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from(SAVE_DIR),
+            autosave_interval: Some(Duration::from_secs(5 * 60)),
+        }
+    }
+}
+
+impl SaveSettings {
+    pub fn quicksave_path(&self) -> PathBuf {
+        self.dir.join(QUICKSAVE_FILE)
+    }
+    pub fn autosave_path(&self) -> PathBuf {
+        self.dir.join(AUTOSAVE_FILE)
+    }
+    /// Written on quitting, apart from the quicksave so it isn't lost.
+    pub fn exit_path(&self) -> PathBuf {
+        self.dir.join(EXIT_FILE)
+    }
+}
+
+/// What to do once a save is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AfterSave {
+    /// Tell the player where it was saved.
+    Notify,
+    /// Only log it, for saves the player didn't ask for.
+    Log,
+    /// Exit the app. If saving fails the player is asked first.
+    Quit,
+}
+
+/// Event requesting the simulation be saved or loaded. Handled at the start
+/// of the next frame.
+#[derive(Debug, Clone)]
+pub enum SaveRequest {
+    Save { path: PathBuf, after: AfterSave },
+    Load { path: PathBuf },
+}
+
+/// A save to load once the app starts, e.g. one given on the command line.
+#[derive(Resource, Debug)]
+pub struct PendingLoad(pub SaveFile);
+
+/// An entity on the map and every component worth keeping. Components the
+/// entity doesn't have are left out of the file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedEntity {
+    pub transform: Transform2D,
+    pub level: Level,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub texture: Option<CharTexture>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub species: Option<Species>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub speed: Option<Speed>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub goal: Option<GoalLoc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<MovePath>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub algorithm: Option<PathAlgorithm>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub abstract_path: Option<AbstractPath>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flow_field: Option<FollowFlowField>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub layerable: Option<LayerableCollider>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub obstacle: Option<ImmobileObstacle>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunk: Option<ChunkMember>,
+}
+
+impl SavedEntity {
+    fn spawn(self, world: &mut World) {
+        let mut entity = world.spawn((self.transform, self.level));
+        if let Some(texture) = self.texture {
+            entity.insert(texture);
+        }
+        if let Some(species) = self.species {
+            entity.insert(species);
+        }
+        if let Some(speed) = self.speed {
+            entity.insert(speed);
+        }
+        if let Some(goal) = self.goal {
+            entity.insert(goal);
+        }
+        if let Some(path) = self.path {
+            entity.insert(path);
+        }
+        if let Some(algorithm) = self.algorithm {
+            entity.insert(algorithm);
+        }
+        if let Some(abstract_path) = self.abstract_path {
+            entity.insert(abstract_path);
+        }
+        if let Some(flow_field) = self.flow_field {
+            entity.insert(flow_field);
+        }
+        if let Some(layerable) = self.layerable {
+            entity.insert(layerable);
+        }
+        if let Some(obstacle) = self.obstacle {
+            entity.insert(obstacle);
+        }
+        if let Some(chunk) = self.chunk {
+            entity.insert(chunk);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedChunk {
+    pub coord: IVec2,
+    pub grid: BiomeGrid,
+}
+
+/// The whole state of the simulation. Loading a save and saving it again
+/// gives back the same file.
+///
+/// Only loaded chunks are saved, the rest are generated again from the seed
+/// and the saved [`TerrainSettings`] once loaded.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SaveFile {
+    pub version: u32,
+    pub rng: SimRngState,
+    pub sim_time: SimTime,
+    pub map: MapSettings,
+    pub terrain: TerrainSettings,
+    pub view: ViewLevel,
+    pub camera: Vec3,
+    pub chunks: Vec<SavedChunk>,
+    /// Every entity with a [`Level`], i.e. those on the map.
+    pub entities: Vec<SavedEntity>,
+}
+
+/// Just enough of a [`SaveFile`] to check its version before reading the rest.
+#[derive(Deserialize)]
+struct SaveHeader {
+    version: u32,
+}
+
+impl SaveFile {
+    /// Take the state of the simulation.
+    pub fn capture(world: &mut World) -> Self {
+        let mut chunks: Vec<SavedChunk> = world
+            .query::<(&MapChunk, &BiomeGrid)>()
+            .iter(world)
+            .map(|(chunk, grid)| SavedChunk {
+                coord: chunk.coord,
+                grid: grid.clone(),
+            })
+            .collect();
+        chunks.sort_by_key(|chunk| (chunk.coord.y, chunk.coord.x));
+
+        let mut entities: Vec<SavedEntity> = world
+            .query::<(
+                &Transform2D,
+                &Level,
+                Option<&CharTexture>,
+                Option<&Species>,
+                Option<&Speed>,
+                Option<&GoalLoc>,
+                Option<&MovePath>,
+                Option<&PathAlgorithm>,
+                Option<&AbstractPath>,
+                Option<&FollowFlowField>,
+                Option<&LayerableCollider>,
+                Option<&ImmobileObstacle>,
+                Option<&ChunkMember>,
+            )>()
+            .iter(world)
+            .map(
+                |(
+                    transform,
+                    level,
+                    texture,
+                    species,
+                    speed,
+                    goal,
+                    path,
+                    algorithm,
+                    abstract_path,
+                    flow_field,
+                    layerable,
+                    obstacle,
+                    chunk,
+                )| {
+                    SavedEntity {
+                        transform: transform.clone(),
+                        level: *level,
+                        texture: texture.cloned(),
+                        species: species.cloned(),
+                        speed: speed.cloned(),
+                        goal: goal.cloned(),
+                        path: path.cloned(),
+                        algorithm: algorithm.copied(),
+                        abstract_path: abstract_path.cloned(),
+                        flow_field: flow_field.cloned(),
+                        layerable: layerable.cloned(),
+                        obstacle: obstacle.cloned(),
+                        chunk: chunk.copied(),
+                    }
+                },
+            )
+            .collect();
+        // Entity ids change across a load, so order by where entities are,
+        // then by everything else, to keep the file the same.
+        entities.sort_by(|l, r| {
+            l.level
+                .0
+                .cmp(&r.level.0)
+                .then(l.transform.loc.y.total_cmp(&r.transform.loc.y))
+                .then(l.transform.loc.x.total_cmp(&r.transform.loc.x))
+                .then_with(|| format!("{:?}", l).cmp(&format!("{:?}", r)))
+        });
+
+        Self {
+            version: SAVE_VERSION,
+            rng: world.resource_mut::<SimRng>().state(),
+            sim_time: world.resource::<SimTime>().clone(),
+            map: world.resource::<MapSettings>().clone(),
+            terrain: world.resource::<TerrainSettings>().clone(),
+            view: world.resource::<ViewLevel>().clone(),
+            camera: world.resource::<TerminalCamera2D>().transform().loc,
+            chunks,
+            entities,
+        }
+    }
+
+    /// Replace the state of the simulation with the save's.
+    pub fn restore(self, world: &mut World) {
+        *world.resource_mut::<SimRng>() = SimRng::from_state(self.rng);
+        *world.resource_mut::<SimTime>() = self.sim_time;
+        world.resource_mut::<MapSettings>().set_if_neq(self.map);
+        world
+            .resource_mut::<TerrainSettings>()
+            .set_if_neq(self.terrain);
+        world.resource_mut::<ViewLevel>().set_if_neq(self.view);
+        *world.resource_mut::<TerminalCamera2D>().loc_mut() = self.camera;
+
+        restore_chunks(
+            world,
+            self.chunks
+                .into_iter()
+                .map(|chunk| (chunk.coord, chunk.grid)),
+        );
+
+        let old: Vec<Entity> = world
+            .query_filtered::<Entity, With<Level>>()
+            .iter(world)
+            .collect();
+        for entity in old {
+            world.despawn(entity);
+        }
+        for entity in self.entities {
+            entity.spawn(world);
+        }
+    }
+
+    pub fn read(path: &Path) -> Result<Self, SaveError> {
+        let text = fs::read_to_string(path).map_err(|source| SaveError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let parse_err = |source| SaveError::Parse {
+            path: path.to_path_buf(),
+            source,
+        };
+        let header: SaveHeader = ron::from_str(&text).map_err(parse_err)?;
+        if header.version != SAVE_VERSION {
+            return Err(SaveError::Version {
+                path: path.to_path_buf(),
+                found: header.version,
+            });
+        }
+        ron::from_str(&text).map_err(parse_err)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), SaveError> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|source| SaveError::Io {
+                path: dir.to_path_buf(),
+                source,
+            })?;
+        }
+        // Deeper than the entities and chunks is written on a single line,
+        // otherwise each tile of a chunk would get its own.
+        let text = ron::ser::to_string_pretty(self, PrettyConfig::new().depth_limit(3)).map_err(
+            |source| SaveError::Serialize {
+                path: path.to_path_buf(),
+                source,
+            },
+        )?;
+        fs::write(path, text).map_err(|source| SaveError::Io {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+}
+
+/// Quicksave with `F5`, quickload with `F9`.
+fn handle_save_keys(
+    mut input: EventReader<KeyboardInput>,
+    focus: Res<InputFocus>,
+    settings: Res<SaveSettings>,
+    mut requests: EventWriter<SaveRequest>,
+) {
+    if *focus != InputFocus::World {
+        input.clear();
+        return;
+    }
+    for e in input.iter() {
+        if e.state != ButtonState::Pressed {
+            continue;
+        }
+        match e.key_code {
+            Some(KeyCode::F5) => requests.send(SaveRequest::Save {
+                path: settings.quicksave_path(),
+                after: AfterSave::Notify,
+            }),
+            Some(KeyCode::F9) => requests.send(SaveRequest::Load {
+                path: settings.quicksave_path(),
+            }),
+            _ => (),
+        }
+    }
+}
+
+fn sys_autosave(
+    time: Res<Time>,
+    settings: Res<SaveSettings>,
+    mut since_save: Local<Duration>,
+    mut requests: EventWriter<SaveRequest>,
+) {
+    let interval = match settings.autosave_interval {
+        Some(interval) => interval,
+        None => return,
+    };
+    *since_save += time.delta();
+    if *since_save >= interval {
+        *since_save = Duration::ZERO;
+        requests.send(SaveRequest::Save {
+            path: settings.autosave_path(),
+            after: AfterSave::Log,
+        });
+    }
+}
+
+fn sys_handle_save_requests(world: &mut World) {
+    if let Some(PendingLoad(save)) = world.remove_resource::<PendingLoad>() {
+        save.restore(world);
+    }
+    let requests: Vec<SaveRequest> = world
+        .resource_mut::<Events<SaveRequest>>()
+        .drain()
+        .collect();
+    for request in requests {
+        match request {
+            SaveRequest::Save { path, after } => {
+                let result = SaveFile::capture(world).write(&path);
+                handle_save_result(world, &path, result, after);
+            }
+            SaveRequest::Load { path } => match SaveFile::read(&path) {
+                Ok(save) => {
+                    save.restore(world);
+                    log::info!("Loaded {}", path.display());
+                }
+                Err(e) => {
+                    log::error!("Load failed: {}", e);
+                    open_dialog(world, SAVE_DIALOG, "Load", e.to_string(), DialogKind::Ok);
+                }
+            },
+        }
+    }
+}
+
+fn handle_save_result(
+    world: &mut World,
+    path: &Path,
+    result: Result<(), SaveError>,
+    after: AfterSave,
+) {
+    let e = match result {
+        Ok(()) => {
+            log::info!("Saved to {}", path.display());
+            match after {
+                AfterSave::Notify => {
+                    let message = format!("Saved to {}", path.display());
+                    open_dialog(world, SAVE_DIALOG, "Save", message, DialogKind::Ok);
+                }
+                AfterSave::Log => (),
+                AfterSave::Quit => world.resource_mut::<Events<AppExit>>().send(AppExit),
+            }
+            return;
+        }
+        Err(e) => e,
+    };
+    log::error!("Save failed: {}", e);
+    if after == AfterSave::Quit {
+        let message = format!("{}\nQuit anyway?", e);
+        open_dialog(
+            world,
+            QUIT_UNSAVED_DIALOG,
+            "Save failed",
+            message,
+            DialogKind::YesNo,
+        );
+    } else {
+        open_dialog(
+            world,
+            SAVE_DIALOG,
+            "Save failed",
+            e.to_string(),
+            DialogKind::Ok,
+        );
+    }
+}
+
+fn open_dialog(
+    world: &mut World,
+    tag: &'static str,
+    title: &str,
+    message: String,
+    kind: DialogKind,
+) {
+    world.resource_mut::<Events<OpenDialog>>().send(OpenDialog {
+        tag,
+        title: title.to_string(),
+        message,
+        kind,
+    });
+}
+
+fn sys_handle_quit_unsaved_dialog(
+    mut closed: EventReader<DialogClosed>,
+    mut exit: EventWriter<AppExit>,
+) {
+    for e in closed.iter() {
+        if e.tag == QUIT_UNSAVED_DIALOG && e.response == DialogResponse::Yes {
+            exit.send(AppExit);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::script::local_map::{Biome, LoadedChunks};
+    use crate::script::pathing::LevelPoint;
+
+    fn empty_world() -> World {
+        let mut world = World::new();
+        world.insert_resource(SimRng::new(7));
+        world.init_resource::<SimTime>();
+        world.init_resource::<MapSettings>();
+        world.init_resource::<TerrainSettings>();
+        world.init_resource::<ViewLevel>();
+        world.init_resource::<TerminalCamera2D>();
+        world.init_resource::<LoadedChunks>();
+        world
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("dorf-{}-{}.ron", name, std::process::id()))
+    }
+
+    /// A chunk of two levels of grassland, and a wall and a mover on it.
+    fn sample_world() -> World {
+        let mut world = empty_world();
+        let grid = Grid3D::new(IVec2::ZERO, UVec2::new(4, 3), 2, Biome::Grassland);
+        restore_chunks(&mut world, [(IVec2::ZERO, BiomeGrid::new(grid))]);
+        world.spawn((
+            Transform2D {
+                scale: UVec2::ONE,
+                loc: Vec3::new(1.0, 2.0, 0.0),
+            },
+            Level(0),
+            ImmobileObstacle,
+        ));
+        let goal = LevelPoint::new(Vec2::new(3.0, 2.0), 1);
+        world.spawn((
+            Transform2D {
+                scale: UVec2::ONE,
+                loc: Vec3::new(0.5, 1.0, 0.0),
+            },
+            Level(1),
+            Speed(5.0),
+            GoalLoc(Some(goal)),
+            MovePath {
+                steps: vec![goal, LevelPoint::new(Vec2::new(1.0, 1.0), 1)],
+            },
+            PathAlgorithm::JumpPoint,
+        ));
+        world.resource_mut::<SimRng>().stream("save::tests").u64(..);
+        // As if tweaked in the map designer.
+        let mut terrain = world.resource_mut::<TerrainSettings>();
+        terrain.elevation.frequency = 0.05;
+        terrain.underground.soil_depth = 1;
+        terrain.biomes.sea_level = 0.3;
+        world
+    }
+
+    #[test]
+    fn round_trip() {
+        let path = temp_path("round-trip");
+        SaveFile::capture(&mut sample_world()).write(&path).unwrap();
+        let written = fs::read_to_string(&path).unwrap();
+
+        let mut world = empty_world();
+        SaveFile::read(&path).unwrap().restore(&mut world);
+        SaveFile::capture(&mut world).write(&path).unwrap();
+        let rewritten = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(written, rewritten);
+        assert_eq!(
+            world.resource::<TerrainSettings>(),
+            sample_world().resource::<TerrainSettings>()
+        );
+        assert_eq!(world.query::<&MapChunk>().iter(&world).count(), 1);
+        assert_eq!(world.query::<&Level>().iter(&world).count(), 2);
+    }
+
+    #[test]
+    fn corrupt_grid_is_rejected() {
+        let path = temp_path("corrupt-grid");
+        let save = SaveFile::capture(&mut sample_world());
+        let text = ron::to_string(&save).unwrap();
+        // Claim a level the chunk has no tiles for.
+        let corrupt = text.replacen("levels:2", "levels:3", 1);
+        assert_ne!(text, corrupt);
+        fs::write(&path, corrupt).unwrap();
+        let result = SaveFile::read(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(SaveError::Parse { .. })));
+    }
+
+    #[test]
+    fn unknown_speed_is_rejected() {
+        let path = temp_path("unknown-speed");
+        let save = SaveFile::capture(&mut sample_world());
+        let text = ron::to_string(&save).unwrap();
+        let corrupt = text.replacen("speed_step:2", "speed_step:9", 1);
+        assert_ne!(text, corrupt);
+        fs::write(&path, corrupt).unwrap();
+        let result = SaveFile::read(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(SaveError::Parse { .. })));
+    }
+}
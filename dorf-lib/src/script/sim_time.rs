@@ -0,0 +1,129 @@
+use bevy::input::ButtonState;
+use bevy::utils::Duration;
+
+use crate::prelude::*;
+
+/// Speed multipliers selectable with the `+`/`-` keys.
+const SPEED_STEPS: [f32; 6] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0];
+const DEFAULT_SPEED_STEP: usize = 2;
+
+pub fn add_sim_time_systems(app: &mut App, enabled: bool) {
+    app.insert_resource(SimTime::default())
+        .add_system(sys_advance_sim_time.in_base_set(CoreSet::PreUpdate))
+        .add_system(handle_sim_speed_keys);
+}
+
+/// The simulation clock. Unlike [`Time`] this clock can be paused and scaled,
+/// so systems driving the simulation should take their delta from here.
+#[derive(Resource, Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "SimTimeParts")]
+pub struct SimTime {
+    elapsed: Duration,
+    #[serde(skip)]
+    delta: Duration,
+    speed_step: usize,
+    paused: bool,
+}
+
+/// A [`SimTime`] as read from a file, before its speed is checked.
+#[derive(serde::Deserialize)]
+struct SimTimeParts {
+    elapsed: Duration,
+    speed_step: usize,
+    paused: bool,
+}
+
+impl TryFrom<SimTimeParts> for SimTime {
+    type Error = String;
+
+    fn try_from(parts: SimTimeParts) -> Result<Self, Self::Error> {
+        if parts.speed_step >= SPEED_STEPS.len() {
+            return Err(format!(
+                "speed_step {} is out of range, expected less than {}",
+                parts.speed_step,
+                SPEED_STEPS.len()
+            ));
+        }
+        Ok(Self {
+            elapsed: parts.elapsed,
+            delta: Duration::ZERO,
+            speed_step: parts.speed_step,
+            paused: parts.paused,
+        })
+    }
+}
+
+impl Default for SimTime {
+    fn default() -> Self {
+        Self {
+            elapsed: Duration::ZERO,
+            delta: Duration::ZERO,
+            speed_step: DEFAULT_SPEED_STEP,
+            paused: false,
+        }
+    }
+}
+
+impl SimTime {
+    /// Simulated time passed since the previous frame, zero while paused.
+    pub fn delta(&self) -> Duration {
+        self.delta
+    }
+    pub fn delta_seconds(&self) -> f32 {
+        self.delta.as_secs_f32()
+    }
+    /// Total simulated time since startup.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+    pub fn speed(&self) -> f32 {
+        SPEED_STEPS[self.speed_step]
+    }
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+    pub fn speed_up(&mut self) {
+        self.speed_step = (self.speed_step + 1).min(SPEED_STEPS.len() - 1);
+    }
+    pub fn slow_down(&mut self) {
+        self.speed_step = self.speed_step.saturating_sub(1);
+    }
+
+    fn advance(&mut self, real_delta: Duration) {
+        self.delta = if self.paused {
+            Duration::ZERO
+        } else {
+            real_delta.mul_f32(self.speed())
+        };
+        self.elapsed += self.delta;
+    }
+}
+
+fn sys_advance_sim_time(time: Res<Time>, mut sim_time: ResMut<SimTime>) {
+    sim_time.advance(time.delta());
+}
+
+fn handle_sim_speed_keys(
+    mut input: EventReader<KeyboardInput>,
+    focus: Res<InputFocus>,
+    mut sim_time: ResMut<SimTime>,
+) {
+    if *focus != InputFocus::World {
+        input.clear();
+        return;
+    }
+    for e in input.iter() {
+        if e.state != ButtonState::Pressed {
+            continue;
+        }
+        match e.key_code {
+            Some(KeyCode::Space) => sim_time.toggle_pause(),
+            Some(KeyCode::Plus | KeyCode::Equals) => sim_time.speed_up(),
+            Some(KeyCode::Minus) => sim_time.slow_down(),
+            _ => (),
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use crate::prelude::*;
+
+use super::cursor::TileCursor;
+use super::levels::{Level, ViewLevel};
+use super::local_map::{biome_at, BiomeGrid};
+use super::pathing::{CollisionGridCache, LayerableCollider};
+use super::sim_time::SimTime;
+
+/// Drawn over the bottom wall of the camera frame.
+const STATUS_BAR_Z: f32 = 1024.0;
+/// Weight given to the newest frame when smoothing the FPS readout.
+const FPS_SMOOTHING: f32 = 0.1;
+
+pub fn add_status_bar_systems(app: &mut App, enabled: bool) {
+    app.add_startup_system(spawn_status_bar)
+        .add_system(sys_update_status_bar);
+}
+
+/// Tag for the single line of diagnostics pinned to the bottom of the camera.
+#[derive(Component, Debug, Default)]
+pub struct StatusBar;
+
+#[derive(Bundle)]
+struct StatusBarBundle {
+    tag: StatusBar,
+    mesh: CharMeshTransform,
+    ui_component: UIComponent,
+}
+
+/// Smoothed frame timings, in seconds.
+#[derive(Default, Debug)]
+struct FrameStats {
+    frame_time: f32,
+}
+
+impl FrameStats {
+    fn record(&mut self, delta: f32) {
+        if self.frame_time == 0.0 {
+            self.frame_time = delta;
+        } else {
+            self.frame_time += (delta - self.frame_time) * FPS_SMOOTHING;
+        }
+    }
+    fn fps(&self) -> f32 {
+        if self.frame_time > 0.0 {
+            1.0 / self.frame_time
+        } else {
+            0.0
+        }
+    }
+}
+
+fn spawn_status_bar(mut cmd: Commands) {
+    cmd.spawn(StatusBarBundle {
+        tag: StatusBar,
+        // Relative UI elements keep the z level of their transform.
+        mesh: CharMeshTransform::new(Transform2D {
+            scale: UVec2::new(0, 1),
+            loc: Vec3::new(0.0, 0.0, STATUS_BAR_Z),
+        }),
+        ui_component: UIComponent {
+            local_pos: Vec3::new(0.0, 1.0, STATUS_BAR_Z),
+            relative_pos: true,
+        },
+    });
+}
+
+fn format_sim_clock(sim_time: &SimTime) -> String {
+    let secs = sim_time.elapsed().as_secs();
+    format!(
+        "{:02}:{:02}:{:02}",
+        secs / 3600,
+        (secs / 60) % 60,
+        secs % 60
+    )
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn sys_update_status_bar(
+    mut stats: Local<FrameStats>,
+    time: Res<Time>,
+    sim_time: Res<SimTime>,
+    camera: Res<TerminalCamera2D>,
+    cursor: Query<&TileCursor>,
+    col_cache: Res<CollisionGridCache>,
+    grids: Query<&BiomeGrid>,
+    view: Res<ViewLevel>,
+    movers: Query<(&Transform2D, &Level), With<LayerableCollider>>,
+    mut bar: Query<
+        (&mut CharMesh, &mut Transform2D),
+        (With<StatusBar>, Without<LayerableCollider>),
+    >,
+) {
+    stats.record(time.delta_seconds());
+    let Ok((mut mesh, mut transform)) = bar.get_single_mut() else {
+        return;
+    };
+
+    let Ok(cursor) = cursor.get_single().map(|c| c.tile) else {
+        return;
+    };
+    let biome = biome_at(grids.iter(), cursor, view.level)
+        .map(|b| format!("{:?}", b))
+        .unwrap_or_else(|| "Unexplored".to_string());
+    let obstacle = match col_cache.obstacle_at(cursor.extend(view.level as i32)) {
+        Some(_) => " wall",
+        None => "",
+    };
+    let units = movers
+        .iter()
+        .filter(|(t, level)| {
+            level.0 == view.level && t.as_rect2d().contains_exclusive_max(cursor.as_vec2())
+        })
+        .count();
+    let speed = if sim_time.paused() {
+        "PAUSED".to_string()
+    } else {
+        format!("x{}", sim_time.speed())
+    };
+
+    let text = format!(
+        " {} {} | {:.0} fps {:.1}ms | ({},{}) lvl {} {}{} units:{}",
+        format_sim_clock(&sim_time),
+        speed,
+        stats.fps(),
+        stats.frame_time * 1000.0,
+        cursor.x,
+        cursor.y,
+        view.level,
+        biome,
+        obstacle,
+        units,
+    );
+
+    let width = camera.dim().x;
+    if transform.scale.x != width {
+        transform.scale.x = width;
+        let scale = transform.scale;
+        mesh.resize(scale);
+    }
+    mesh.write_row(width, 0, &text, None);
+}
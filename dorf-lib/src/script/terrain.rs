@@ -0,0 +1,276 @@
+use noise::{Fbm, MultiFractal, NoiseFn, Perlin};
+
+use crate::prelude::*;
+
+use super::hydrology::HydrologySettings;
+use super::local_map::Biome;
+use super::underground::UndergroundSettings;
+
+/// Settings for a single fractal noise field.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct NoiseLayer {
+    /// Frequency of the first octave, in cycles per tile.
+    pub frequency: f64,
+    pub octaves: usize,
+    pub persistence: f64,
+    pub lacunarity: f64,
+}
+
+impl NoiseLayer {
+    pub fn build(&self, seed: u32) -> Fbm<Perlin> {
+        Fbm::<Perlin>::new(seed)
+            .set_frequency(self.frequency)
+            .set_octaves(self.octaves)
+            .set_persistence(self.persistence)
+            .set_lacunarity(self.lacunarity)
+    }
+}
+
+/// Whittaker style biome classification. Elevation alone decides water,
+/// coasts and mountains, the remaining land is looked up by its temperature
+/// and moisture.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BiomeTable {
+    /// Anything lower is ocean.
+    pub sea_level: f32,
+    /// Land lower than this is sand.
+    pub coast_level: f32,
+    /// Anything higher is mountain.
+    pub mountain_level: f32,
+    /// How much colder land gets per unit of elevation above `sea_level`.
+    pub lapse_rate: f32,
+    /// Upper bound of each temperature band, coldest first.
+    pub temperature_bands: Vec<f32>,
+    /// Upper bound of each moisture band, driest first.
+    pub moisture_bands: Vec<f32>,
+    /// `biomes[t][m]` is the biome for temperature band `t`, moisture band `m`.
+    pub biomes: Vec<Vec<Biome>>,
+}
+
+impl Default for BiomeTable {
+    fn default() -> Self {
+        Self {
+            sea_level: 0.42,
+            coast_level: 0.46,
+            mountain_level: 0.68,
+            lapse_rate: 0.5,
+            temperature_bands: vec![0.4, 0.6, 1.0],
+            moisture_bands: vec![0.4, 0.55, 1.0],
+            biomes: vec![
+                vec![Biome::Tundra, Biome::Tundra, Biome::Forest],
+                vec![Biome::Grassland, Biome::Grassland, Biome::Forest],
+                vec![Biome::Desert, Biome::Grassland, Biome::Forest],
+            ],
+        }
+    }
+}
+
+impl BiomeTable {
+    /// Check the levels and bands are in order, and `biomes` has an entry for
+    /// every pair of bands.
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.sea_level <= self.coast_level && self.coast_level <= self.mountain_level) {
+            return Err(format!(
+                "expected sea_level <= coast_level <= mountain_level, got {} {} {}",
+                self.sea_level, self.coast_level, self.mountain_level
+            ));
+        }
+        for (name, bands) in [
+            ("temperature_bands", &self.temperature_bands),
+            ("moisture_bands", &self.moisture_bands),
+        ] {
+            if bands.is_empty() {
+                return Err(format!("{} is empty", name));
+            }
+            if bands.windows(2).any(|pair| pair[0] > pair[1]) {
+                return Err(format!("{} must be in increasing order", name));
+            }
+        }
+        if self.biomes.len() != self.temperature_bands.len() {
+            return Err(format!(
+                "biomes has {} rows, expected one per temperature band ({})",
+                self.biomes.len(),
+                self.temperature_bands.len()
+            ));
+        }
+        for (t, row) in self.biomes.iter().enumerate() {
+            if row.len() != self.moisture_bands.len() {
+                return Err(format!(
+                    "biomes row {} has {} entries, expected one per moisture band ({})",
+                    t,
+                    row.len(),
+                    self.moisture_bands.len()
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn classify(&self, elevation: f32, moisture: f32, temperature: f32) -> Biome {
+        if elevation < self.sea_level {
+            return Biome::Ocean;
+        }
+        if elevation > self.mountain_level {
+            return Biome::Mountain;
+        }
+        if elevation < self.coast_level {
+            return Biome::Sand;
+        }
+        let temperature = temperature - (elevation - self.sea_level) * self.lapse_rate;
+        let band = |bands: &Vec<f32>, value: f32| {
+            bands
+                .iter()
+                .position(|upper| value <= *upper)
+                .unwrap_or(bands.len() - 1)
+        };
+        let t = band(&self.temperature_bands, temperature);
+        let m = band(&self.moisture_bands, moisture);
+        self.biomes[t][m]
+    }
+}
+
+/// Parameters for terrain generation, handed to each map generation task.
+/// Saved along with the chunks, so those generated after loading match them.
+#[derive(Resource, Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct TerrainSettings {
+    pub elevation: NoiseLayer,
+    pub moisture: NoiseLayer,
+    pub temperature: NoiseLayer,
+    pub biomes: BiomeTable,
+    pub hydrology: HydrologySettings,
+    pub underground: UndergroundSettings,
+}
+
+impl Default for TerrainSettings {
+    fn default() -> Self {
+        Self {
+            elevation: NoiseLayer {
+                frequency: 0.02,
+                octaves: 5,
+                persistence: 0.5,
+                lacunarity: 2.0,
+            },
+            moisture: NoiseLayer {
+                frequency: 0.03,
+                octaves: 4,
+                persistence: 0.5,
+                lacunarity: 2.0,
+            },
+            temperature: NoiseLayer {
+                frequency: 0.01,
+                octaves: 3,
+                persistence: 0.5,
+                lacunarity: 2.0,
+            },
+            biomes: default(),
+            hydrology: default(),
+            underground: default(),
+        }
+    }
+}
+
+/// Name of the [`SimRng`] seed terrain is generated from. Shared by every
+/// map so they all describe the same world.
+pub const TERRAIN_SEED: &str = "terrain";
+
+/// The value of every terrain field at a single point, each in `[0, 1]`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TerrainSample {
+    pub elevation: f32,
+    pub moisture: f32,
+    pub temperature: f32,
+}
+
+impl TerrainSample {
+    pub fn biome(&self, table: &BiomeTable) -> Biome {
+        table.classify(self.elevation, self.moisture, self.temperature)
+    }
+}
+
+/// The noise functions behind each terrain field.
+pub struct TerrainNoise {
+    elevation: Fbm<Perlin>,
+    moisture: Fbm<Perlin>,
+    temperature: Fbm<Perlin>,
+}
+
+impl TerrainNoise {
+    pub fn new(settings: &TerrainSettings, seed: u64) -> Self {
+        // Each field needs its own seed, otherwise they'd all be identical.
+        let seed_for = |salt| mix(seed, salt) as u32;
+        Self {
+            elevation: settings.elevation.build(seed_for(1)),
+            moisture: settings.moisture.build(seed_for(2)),
+            temperature: settings.temperature.build(seed_for(3)),
+        }
+    }
+
+    /// Sample every field at `tile`. Points are sampled in world coordinates,
+    /// so neighboring areas line up seamlessly.
+    pub fn sample(&self, tile: IVec2) -> TerrainSample {
+        // Rescale from `[-1, 1]` to `[0, 1]`.
+        let get = |noise: &Fbm<Perlin>| {
+            let value = noise.get([tile.x as f64, tile.y as f64]) as f32;
+            (value * 0.5 + 0.5).clamp(0.0, 1.0)
+        };
+        TerrainSample {
+            elevation: get(&self.elevation),
+            moisture: get(&self.moisture),
+            temperature: get(&self.temperature),
+        }
+    }
+}
+
+/// The raw fields biomes are classified from, each value in `[0, 1]`.
+#[derive(Debug, Clone)]
+pub struct TerrainFields {
+    pub elevation: Grid2D<f32>,
+    pub moisture: Grid2D<f32>,
+    pub temperature: Grid2D<f32>,
+}
+
+impl TerrainFields {
+    pub fn generate(settings: &TerrainSettings, seed: u64, rect: Rect2D) -> Self {
+        let noise = TerrainNoise::new(settings, seed);
+        let len = (rect.size().x * rect.size().y) as usize;
+        let (mut elevation, mut moisture, mut temperature) = (
+            Vec::with_capacity(len),
+            Vec::with_capacity(len),
+            Vec::with_capacity(len),
+        );
+        for y in rect.min.y..rect.max.y {
+            for x in rect.min.x..rect.max.x {
+                let sample = noise.sample(IVec2::new(x, y));
+                elevation.push(sample.elevation);
+                moisture.push(sample.moisture);
+                temperature.push(sample.temperature);
+            }
+        }
+        Self {
+            elevation: Grid2D::from_parts(elevation, rect),
+            moisture: Grid2D::from_parts(moisture, rect),
+            temperature: Grid2D::from_parts(temperature, rect),
+        }
+    }
+
+    pub fn rect(&self) -> &Rect2D {
+        self.elevation.rect()
+    }
+
+    pub fn classify(&self, table: &BiomeTable) -> Grid2D<Biome> {
+        let rect = *self.rect();
+        let data = self
+            .elevation
+            .iter()
+            .map(|(point, elevation)| {
+                table.classify(
+                    *elevation,
+                    *self.moisture.get(point).unwrap(),
+                    *self.temperature.get(point).unwrap(),
+                )
+            })
+            .collect();
+        Grid2D::from_parts(data, rect)
+    }
+}
@@ -0,0 +1,264 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::prelude::*;
+
+use super::local_map::Biome;
+use super::pathing::LevelPoint;
+
+#[derive(Debug, Error)]
+pub enum TextMapError {
+    #[error("couldn't read {}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("{}:{source}", path.display())]
+    Parse {
+        path: PathBuf,
+        source: ron::error::SpannedError,
+    },
+
+    #[error("{}: {message}", path.display())]
+    Invalid { path: PathBuf, message: String },
+}
+
+/// What a glyph of a text map stands for. Walls, spawns and goals stand on a
+/// [`Biome`] of their own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum LegendEntry {
+    Tile(Biome),
+    /// An [`ImmobileObstacle`](super::pathing::ImmobileObstacle).
+    Wall(Biome),
+    /// Where movers are spawned.
+    Spawn(Biome),
+    /// Where movers head towards.
+    Goal(Biome),
+}
+
+impl LegendEntry {
+    pub fn biome(&self) -> Biome {
+        match *self {
+            Self::Tile(biome) | Self::Wall(biome) | Self::Spawn(biome) | Self::Goal(biome) => biome,
+        }
+    }
+}
+
+/// Glyphs every text map may use without listing them in its legend.
+fn default_legend() -> HashMap<char, LegendEntry> {
+    HashMap::from([
+        ('.', LegendEntry::Tile(Biome::Grassland)),
+        ('~', LegendEntry::Tile(Biome::River)),
+        ('%', LegendEntry::Tile(Biome::Rock)),
+        ('X', LegendEntry::Tile(Biome::Stairs)),
+        ('=', LegendEntry::Tile(Biome::Road)),
+        ('#', LegendEntry::Wall(Biome::Grassland)),
+        ('@', LegendEntry::Spawn(Biome::Grassland)),
+        ('x', LegendEntry::Goal(Biome::Grassland)),
+    ])
+}
+
+/// A text map as written, e.g.
+///
+/// ```ron
+/// (
+///     legend: { ',': Tile(Tundra) },
+///     levels: [r"
+/// #####
+/// #@,x#
+/// #####
+/// "],
+/// )
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TextMapDef {
+    /// Added to, and overriding, the [`default_legend`].
+    #[serde(default)]
+    legend: HashMap<char, LegendEntry>,
+    /// One block of rows per level, the surface first. Blank lines before
+    /// and after the rows are ignored.
+    levels: Vec<String>,
+}
+
+/// A run of wall tiles along a row, spawned as a single obstacle. Used by
+/// text maps and [`Prefab`](super::prefabs::Prefab)s alike.
+#[derive(Debug, Clone)]
+pub struct WallRun {
+    pub transform: Transform2D,
+    pub level: u32,
+    pub texture: CharTexture,
+}
+
+impl WallRun {
+    fn new(tile: IVec2, level: u32, glyph: char) -> Self {
+        Self {
+            transform: Transform2D {
+                scale: UVec2::ONE,
+                loc: tile.as_vec2().extend(0.0),
+            },
+            level,
+            texture: CharTexture::from_char(glyph),
+        }
+    }
+
+    /// Whether a wall drawn with `glyph` on `tile` carries on from the end of
+    /// the run.
+    fn continues_to(&self, tile: IVec2, level: u32, glyph: char) -> bool {
+        let end = self.transform.as_tile() + IVec2::new(self.transform.scale.x as i32, 0);
+        self.texture.c == glyph && self.level == level && end == tile
+    }
+
+    /// Split the run around any tiles in `occupied`, e.g. those movers stand
+    /// on, so nobody ends up inside a wall.
+    pub fn split_around(self, occupied: &HashSet<IVec3>) -> Vec<WallRun> {
+        let start = self.transform.as_tile();
+        let mut runs = Vec::new();
+        for x in 0..self.transform.scale.x as i32 {
+            let tile = start + IVec2::new(x, 0);
+            if !occupied.contains(&tile.extend(self.level as i32)) {
+                push_wall(&mut runs, tile, self.level, self.texture.c);
+            }
+        }
+        runs
+    }
+}
+
+/// Add a wall drawn with `glyph` on `tile`, joining it onto the last run if
+/// it's the next tile along drawn with the same glyph. Walls pushed in row
+/// order end up as one run per stretch of a row.
+pub fn push_wall(walls: &mut Vec<WallRun>, tile: IVec2, level: u32, glyph: char) {
+    match walls.last_mut() {
+        Some(run) if run.continues_to(tile, level, glyph) => run.transform.scale.x += 1,
+        _ => walls.push(WallRun::new(tile, level, glyph)),
+    }
+}
+
+/// A map drawn by hand, used in place of generating one. Mostly useful for
+/// setting up scenarios to test against.
+#[derive(Resource, Debug, Clone)]
+pub struct TextMap {
+    /// Every level, the surface first.
+    pub levels: Arc<Grid3D<Biome>>,
+    pub walls: Vec<WallRun>,
+    pub spawns: Vec<LevelPoint>,
+    pub goals: Vec<LevelPoint>,
+}
+
+impl TextMap {
+    /// Read the text map at `path`, its topleft tile becoming `origin`.
+    pub fn read(path: &Path, origin: IVec2) -> Result<Self, TextMapError> {
+        let text = fs::read_to_string(path).map_err(|source| TextMapError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let def: TextMapDef = ron::from_str(&text).map_err(|source| TextMapError::Parse {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Self::from_def(def, origin).map_err(|message| TextMapError::Invalid {
+            path: path.to_path_buf(),
+            message,
+        })
+    }
+
+    fn from_def(def: TextMapDef, origin: IVec2) -> Result<Self, String> {
+        let mut legend = default_legend();
+        legend.extend(def.legend);
+
+        let rows: Vec<Vec<Vec<char>>> = def.levels.iter().map(|level| level_rows(level)).collect();
+        let first = rows.first().ok_or("there are no levels")?;
+        let size = UVec2::new(
+            first.first().map_or(0, |row| row.len()) as u32,
+            first.len() as u32,
+        );
+        if size.x == 0 || size.y == 0 {
+            return Err("level 0 is empty".to_string());
+        }
+
+        let mut walls = Vec::new();
+        let mut spawns = Vec::new();
+        let mut goals = Vec::new();
+        let mut levels = Grid3D::new(origin, size, rows.len() as u32, Biome::Null);
+        for (level, level_rows) in rows.iter().enumerate() {
+            if level_rows.len() as u32 != size.y {
+                return Err(format!(
+                    "level {} has {} rows, expected {} like level 0",
+                    level,
+                    level_rows.len(),
+                    size.y
+                ));
+            }
+            for (y, row) in level_rows.iter().enumerate() {
+                if row.len() as u32 != size.x {
+                    return Err(format!(
+                        "level {}, row {} is {} tiles wide, expected {}",
+                        level,
+                        y,
+                        row.len(),
+                        size.x
+                    ));
+                }
+                for (x, glyph) in row.iter().enumerate() {
+                    let tile = origin + IVec2::new(x as i32, y as i32);
+                    let entry = legend.get(glyph).ok_or_else(|| {
+                        format!(
+                            "level {}, tile ({}, {}): {:?} isn't in the legend",
+                            level, x, y, glyph
+                        )
+                    })?;
+                    levels.set(tile.extend(level as i32), entry.biome());
+
+                    let point = LevelPoint::new(tile.as_vec2(), level as u32);
+                    match entry {
+                        LegendEntry::Spawn(_) => spawns.push(point),
+                        LegendEntry::Goal(_) => goals.push(point),
+                        // Neighboring walls drawn with the same glyph are joined.
+                        LegendEntry::Wall(_) => push_wall(&mut walls, tile, level as u32, *glyph),
+                        LegendEntry::Tile(_) => (),
+                    }
+                }
+            }
+        }
+        Ok(Self {
+            levels: Arc::new(levels),
+            walls,
+            spawns,
+            goals,
+        })
+    }
+
+    pub fn size(&self) -> UVec2 {
+        self.levels.rect().size().as_uvec2()
+    }
+
+    /// Number of levels beneath the surface, as in [`MapSettings::depth`](super::local_map::MapSettings::depth).
+    pub fn depth(&self) -> u32 {
+        self.levels.levels() - 1
+    }
+}
+
+/// Split a level into rows of glyphs, dropping the blank lines around them.
+pub fn level_rows(level: &str) -> Vec<Vec<char>> {
+    let lines: Vec<&str> = level.lines().collect();
+    let start = lines
+        .iter()
+        .position(|line| !line.trim().is_empty())
+        .unwrap_or(lines.len());
+    let end = lines
+        .iter()
+        .rposition(|line| !line.trim().is_empty())
+        .map_or(start, |end| end + 1);
+    lines[start..end]
+        .iter()
+        .map(|line| line.chars().collect())
+        .collect()
+}
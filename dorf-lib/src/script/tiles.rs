@@ -0,0 +1,229 @@
+use num_traits::FromPrimitive;
+use serde::{Deserialize, Serialize};
+use strum::EnumCount;
+
+use crate::prelude::*;
+
+use super::local_map::Biome;
+
+/// What a tile is made of, e.g. what digging it out yields.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Material {
+    /// Nothing at all, such as open air.
+    None,
+    Earth,
+    Sand,
+    Snow,
+    Stone,
+    Ore,
+    Water,
+    Wood,
+}
+
+/// Everything the simulation knows about a kind of tile.
+///
+/// In data files only `glyph`, `fg` and `material` are required, the rest
+/// default to flat, walkable ground.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TileDef {
+    pub glyph: char,
+    pub fg: RGB,
+    #[serde(default)]
+    pub bg: Option<RGB>,
+    /// Whether movers can stand on the tile.
+    #[serde(default = "default_walkable")]
+    pub walkable: bool,
+    /// Cost of crossing the tile, relative to flat open ground.
+    #[serde(default = "default_move_cost")]
+    pub move_cost: f32,
+    #[serde(default)]
+    pub blocks_sight: bool,
+    /// Whether the tile is solid ground which can be dug out.
+    #[serde(default)]
+    pub diggable: bool,
+    pub material: Material,
+}
+
+fn default_walkable() -> bool {
+    true
+}
+
+fn default_move_cost() -> f32 {
+    1.0
+}
+
+impl TileDef {
+    /// Flat, walkable ground.
+    pub fn ground(glyph: char, (r, g, b): (f32, f32, f32), material: Material) -> Self {
+        Self {
+            glyph,
+            fg: RGB::new_f32(r, g, b),
+            bg: None,
+            walkable: default_walkable(),
+            move_cost: default_move_cost(),
+            blocks_sight: false,
+            diggable: false,
+            material,
+        }
+    }
+
+    /// Solid ground, which has to be dug through.
+    pub fn solid(glyph: char, rgb: (f32, f32, f32), material: Material) -> Self {
+        Self {
+            walkable: false,
+            blocks_sight: true,
+            diggable: true,
+            ..Self::ground(glyph, rgb, material)
+        }
+    }
+
+    pub fn texture(&self) -> CharTexture {
+        let texture = CharTexture::new(self.glyph, self.fg);
+        match self.bg {
+            Some(bg) => texture.with_bg(bg),
+            None => texture,
+        }
+    }
+
+    /// Whether the tile blocks movement across it.
+    pub fn is_obstacle(&self) -> bool {
+        !self.walkable
+    }
+}
+
+/// The [`TileDef`] of every [`Biome`].
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct TileTable {
+    /// Indexed by [`Biome`].
+    defs: Vec<TileDef>,
+}
+
+impl TileTable {
+    /// Build the table from one entry per biome. Fails if a biome is missing,
+    /// defined twice, or has a nonsensical def.
+    pub fn from_entries(
+        entries: impl IntoIterator<Item = (Biome, TileDef)>,
+    ) -> Result<Self, String> {
+        let mut defs: Vec<Option<TileDef>> = vec![None; Biome::COUNT];
+        for (biome, def) in entries {
+            if !(def.move_cost.is_finite() && def.move_cost > 0.0) {
+                return Err(format!(
+                    "{:?} has move_cost {}, expected a positive number",
+                    biome, def.move_cost
+                ));
+            }
+            if defs[biome as usize].replace(def).is_some() {
+                return Err(format!("{:?} is defined twice", biome));
+            }
+        }
+        let defs = defs
+            .into_iter()
+            .enumerate()
+            .map(|(idx, def)| {
+                def.ok_or_else(|| format!("{:?} has no tile def", Biome::from_usize(idx).unwrap()))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { defs })
+    }
+
+    #[inline]
+    pub fn get(&self, biome: Biome) -> &TileDef {
+        &self.defs[biome as usize]
+    }
+
+    pub fn texture(&self, biome: Biome) -> CharTexture {
+        self.get(biome).texture()
+    }
+}
+
+impl Default for TileTable {
+    fn default() -> Self {
+        use Material as M;
+        let ground = TileDef::ground;
+        let solid = TileDef::solid;
+        Self::from_entries([
+            (
+                Biome::Forest,
+                TileDef {
+                    move_cost: 1.5,
+                    blocks_sight: true,
+                    ..ground('|', (0.0, 0.3, 0.3), M::Wood)
+                },
+            ),
+            (Biome::Grassland, ground('^', (0.53, 1.0, 0.3), M::Earth)),
+            (
+                Biome::Desert,
+                TileDef {
+                    move_cost: 1.2,
+                    ..ground('.', (0.9, 0.65, 0.3), M::Sand)
+                },
+            ),
+            (
+                Biome::Tundra,
+                TileDef {
+                    move_cost: 1.3,
+                    ..ground(',', (0.75, 0.9, 0.9), M::Snow)
+                },
+            ),
+            (
+                Biome::Ocean,
+                TileDef {
+                    walkable: false,
+                    ..ground('~', (0.0, 0.0, 0.7), M::Water)
+                },
+            ),
+            (
+                Biome::Sand,
+                TileDef {
+                    move_cost: 1.2,
+                    ..ground(':', (1.0, 0.85, 0.1), M::Sand)
+                },
+            ),
+            (
+                Biome::Mountain,
+                TileDef {
+                    move_cost: 2.0,
+                    blocks_sight: true,
+                    ..ground('^', (0.85, 0.85, 0.85), M::Stone)
+                },
+            ),
+            (
+                Biome::River,
+                TileDef {
+                    walkable: false,
+                    ..ground('≈', (0.2, 0.5, 1.0), M::Water)
+                },
+            ),
+            (
+                Biome::Lake,
+                TileDef {
+                    walkable: false,
+                    ..ground('~', (0.3, 0.7, 0.9), M::Water)
+                },
+            ),
+            (Biome::Soil, solid('%', (0.55, 0.35, 0.2), M::Earth)),
+            (Biome::Rock, solid('#', (0.5, 0.5, 0.5), M::Stone)),
+            (Biome::Ore, solid('*', (0.95, 0.8, 0.2), M::Ore)),
+            (Biome::Cave, ground('·', (0.35, 0.35, 0.35), M::Stone)),
+            (
+                Biome::Open,
+                TileDef {
+                    walkable: false,
+                    ..ground(' ', (0.0, 0.0, 0.0), M::None)
+                },
+            ),
+            (Biome::Stairs, ground('X', (0.9, 0.9, 0.9), M::Stone)),
+            (
+                Biome::Road,
+                TileDef {
+                    move_cost: 0.5,
+                    ..ground('=', (0.6, 0.5, 0.4), M::Stone)
+                },
+            ),
+            // Terrain which hasn't loaded yet.
+            (Biome::Null, ground(' ', (0.0, 0.0, 0.0), M::None)),
+        ])
+        .unwrap()
+    }
+}
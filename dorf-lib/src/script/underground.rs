@@ -0,0 +1,187 @@
+use noise::{Fbm, NoiseFn, Perlin};
+
+use crate::prelude::*;
+
+use super::local_map::Biome;
+use super::terrain::NoiseLayer;
+use super::tiles::TileTable;
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct UndergroundSettings {
+    /// Layers of soil before reaching rock. Mountains are bare rock.
+    pub soil_depth: u32,
+    /// Chance for each rock tile to start out open, before the caves are smoothed.
+    pub cave_fill: f64,
+    /// Smoothing passes run over the caves, more gives rounder caverns.
+    pub cave_steps: u32,
+    /// An open tile fills in with at least this many solid neighbors...
+    pub cave_birth: usize,
+    /// ...and a solid tile stays solid with at least this many.
+    pub cave_survival: usize,
+    /// Ore veins run where this noise is above `ore_threshold`.
+    pub ore: NoiseLayer,
+    pub ore_threshold: f32,
+    /// Chance for each cave tile above another cave to hold stairs down to it.
+    pub stair_chance: f64,
+    /// Chance for each walkable surface tile to hold a shaft down to the
+    /// first cave beneath it.
+    pub shaft_chance: f64,
+}
+
+impl Default for UndergroundSettings {
+    fn default() -> Self {
+        Self {
+            soil_depth: 2,
+            cave_fill: 0.4,
+            cave_steps: 4,
+            cave_birth: 5,
+            cave_survival: 4,
+            ore: NoiseLayer {
+                frequency: 0.08,
+                octaves: 3,
+                persistence: 0.5,
+                lacunarity: 2.0,
+            },
+            ore_threshold: 0.72,
+            stair_chance: 0.01,
+            shaft_chance: 0.001,
+        }
+    }
+}
+
+/// Generate `depth` levels beneath `surface`: soil, then rock hollowed out by
+/// caves and threaded with ore. Where a cave sits over the middle of another
+/// cave its floor is left open, and stairs connect the levels.
+///
+/// Returns every level, with `surface` as level 0. Like the surface, every
+/// tile only depends on its world coordinates so neighboring chunks line up.
+pub fn generate_underground(
+    surface: &Grid2D<Biome>,
+    depth: u32,
+    settings: &UndergroundSettings,
+    tiles: &TileTable,
+    seed: u64,
+) -> Grid3D<Biome> {
+    let rect = *surface.rect();
+    let ore = settings.ore.build(mix(seed, 4) as u32);
+    // One past the edges, so open floors can check the cave beneath. Caves
+    // only form below the soil.
+    let first_cave = settings.soil_depth + 1;
+    let caves: Vec<_> = (first_cave..=depth)
+        .map(|level| generate_caves(rect.inset(1), settings, mix(mix(seed, 5), level as u64)))
+        .collect();
+    let is_cave = |tile: IVec2, level: u32| {
+        (first_cave..=depth).contains(&level)
+            && *caves[(level - first_cave) as usize].get(tile).unwrap()
+    };
+    let mut levels = vec![surface.clone()];
+    for level in 1..=depth {
+        let data = surface
+            .iter()
+            .map(|(tile, surface)| {
+                let soil = match surface {
+                    Biome::Mountain => 0,
+                    _ => settings.soil_depth,
+                };
+                if level <= soil {
+                    Biome::Soil
+                } else if is_cave(tile, level) {
+                    let over_cave = (-1..=1)
+                        .flat_map(|y| (-1..=1).map(move |x| tile + IVec2::new(x, y)))
+                        .all(|p| is_cave(p, level + 1));
+                    if over_cave {
+                        Biome::Open
+                    } else {
+                        Biome::Cave
+                    }
+                } else if ore_at(&ore, tile, level) > settings.ore_threshold {
+                    Biome::Ore
+                } else {
+                    Biome::Rock
+                }
+            })
+            .collect();
+        levels.push(Grid2D::from_parts(data, rect));
+    }
+    let mut levels = Grid3D::from_levels(levels);
+    place_stairs(&mut levels, settings, tiles, seed);
+    levels
+}
+
+/// Connect caves to the caves beneath them, and dig shafts from the surface
+/// down to the first cave.
+fn place_stairs(
+    levels: &mut Grid3D<Biome>,
+    settings: &UndergroundSettings,
+    tiles: &TileTable,
+    seed: u64,
+) {
+    let rect = *levels.rect();
+    let points = (rect.min.y..rect.max.y)
+        .flat_map(|y| (rect.min.x..rect.max.x).map(move |x| IVec2::new(x, y)));
+    for tile in points {
+        let at = |level: u32| tile.extend(level as i32);
+        if tiles.get(*levels.get(at(0)).unwrap()).walkable
+            && tile_roll(mix(seed, 6), tile) < settings.shaft_chance
+        {
+            let bottom = (1..levels.levels())
+                .find(|level| !tiles.get(*levels.get(at(*level)).unwrap()).diggable);
+            if let Some(bottom) = bottom.filter(|b| *levels.get(at(*b)).unwrap() == Biome::Cave) {
+                for level in 0..=bottom {
+                    levels.set(at(level), Biome::Stairs);
+                }
+            }
+        }
+        for level in 1..levels.levels() - 1 {
+            let caves = [at(level), at(level + 1)]
+                .iter()
+                .all(|p| matches!(levels.get(*p).unwrap(), Biome::Cave | Biome::Stairs));
+            if caves && tile_roll(mix(mix(seed, 7), level as u64), tile) < settings.stair_chance {
+                levels.set(at(level), Biome::Stairs);
+                levels.set(at(level + 1), Biome::Stairs);
+            }
+        }
+    }
+}
+
+/// Levels are treated as this many tiles apart when sampling ore, so veins
+/// change between levels about as quickly as they do across them.
+const ORE_LEVEL_SPACING: f64 = 8.0;
+
+fn ore_at(ore: &Fbm<Perlin>, tile: IVec2, level: u32) -> f32 {
+    let z = level as f64 * ORE_LEVEL_SPACING;
+    let value = ore.get([tile.x as f64, tile.y as f64, z]) as f32;
+    (value * 0.5 + 0.5).clamp(0.0, 1.0)
+}
+
+/// Carve caves with a cellular automaton, returning which tiles are open.
+fn generate_caves(rect: Rect2D, settings: &UndergroundSettings, seed: u64) -> Grid2D<bool> {
+    // Each step reads the neighbors of every tile, so tiles further than
+    // `cave_steps` from the edge would see a different result in a bigger
+    // area. Run over a padded area to keep chunk borders seamless.
+    let padded = rect.inset(settings.cave_steps as i32);
+    let data = (padded.min.y..padded.max.y)
+        .flat_map(|y| (padded.min.x..padded.max.x).map(move |x| IVec2::new(x, y)))
+        .map(|tile| tile_roll(seed, tile) < settings.cave_fill)
+        .collect();
+    let mut open = Grid2D::from_parts(data, padded);
+    for _ in 0..settings.cave_steps {
+        let data = open
+            .iter()
+            .map(|(tile, is_open)| {
+                // Anything past the edge counts as solid.
+                let solid = (-1..=1)
+                    .flat_map(|y| (-1..=1).map(move |x| tile + IVec2::new(x, y)))
+                    .filter(|p| *p != tile && !open.get(*p).copied().unwrap_or(false))
+                    .count();
+                if *is_open {
+                    solid < settings.cave_birth
+                } else {
+                    solid < settings.cave_survival
+                }
+            })
+            .collect();
+        open = Grid2D::from_parts(data, padded);
+    }
+    open.crop(rect)
+}
@@ -0,0 +1,405 @@
+use bevy::input::ButtonState;
+use strum::EnumCount;
+
+use crate::prelude::*;
+
+use super::cursor::TileCursor;
+use super::local_map::{Biome, MapSettings, RegenerateMap};
+use super::terrain::{BiomeTable, TerrainNoise, TerrainSettings, TERRAIN_SEED};
+use super::tiles::TileTable;
+
+const WORLD_MAP_Z: f32 = 800.0;
+/// Civilizations are never settled closer than this many regions apart.
+const CIVILIZATION_SPACING: i32 = 4;
+
+pub fn add_world_map_systems(app: &mut App, enabled: bool) {
+    app.init_resource::<WorldMapSettings>()
+        .init_resource::<WorldMapView>()
+        .add_startup_system(sys_generate_world_map)
+        .add_system(sys_generate_world_map.run_if(on_event::<RegenerateMap>()))
+        .add_system(sys_update_world_map_panel)
+        .add_system(handle_world_map_keys.in_base_set(CoreSet::PostUpdate));
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct WorldMapSettings {
+    /// Number of regions along each axis.
+    pub size: UVec2,
+    /// Size in tiles of the local map generated for a region.
+    pub region_size: UVec2,
+    /// A region is summarized from a grid of this many terrain samples along
+    /// each axis. Terrain varies within a region, so too few and the summary
+    /// misses what's there.
+    pub samples_per_region: u32,
+    pub river_count: usize,
+    pub civilization_count: usize,
+}
+
+impl Default for WorldMapSettings {
+    fn default() -> Self {
+        Self {
+            size: UVec2::new(64, 32),
+            region_size: UVec2::splat(64),
+            samples_per_region: 8,
+            river_count: 12,
+            civilization_count: 6,
+        }
+    }
+}
+
+/// A coarse summary of the terrain of a single region.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Region {
+    /// The most common biome in the region.
+    pub biome: Biome,
+    /// Mean elevation of the region, in `[0, 1]`.
+    pub elevation: f32,
+    pub river: bool,
+    /// Index of the civilization settled in the region, if any.
+    pub civilization: Option<usize>,
+}
+
+/// The whole world, split into regions which each have a local map generated
+/// from the same terrain noise.
+#[derive(Resource, Debug, Clone)]
+pub struct WorldMap {
+    regions: Grid2D<Region>,
+    region_size: UVec2,
+}
+
+impl WorldMap {
+    pub fn generate(settings: &WorldMapSettings, terrain: &TerrainSettings, rng: &SimRng) -> Self {
+        let noise = TerrainNoise::new(terrain, rng.derive_seed(TERRAIN_SEED));
+        let region_size = settings.region_size.max(UVec2::ONE);
+        let mut data = Vec::with_capacity((settings.size.x * settings.size.y) as usize);
+        for y in 0..settings.size.y as i32 {
+            for x in 0..settings.size.x as i32 {
+                let min = IVec2::new(x, y) * region_size.as_ivec2();
+                let rect = Rect2D::from_corners(min, min + region_size.as_ivec2());
+                data.push(summarize_region(
+                    &noise,
+                    &terrain.biomes,
+                    rect,
+                    settings.samples_per_region,
+                ));
+            }
+        }
+        let mut regions = Grid2D::from_parts(
+            data,
+            Rect2D::from_corners(IVec2::ZERO, settings.size.as_ivec2()),
+        );
+
+        let rng = fastrand::Rng::with_seed(rng.derive_seed("world_map"));
+        trace_rivers(&mut regions, settings.river_count, &rng);
+        settle_civilizations(&mut regions, settings.civilization_count, &rng);
+        Self {
+            regions,
+            region_size,
+        }
+    }
+
+    pub fn size(&self) -> IVec2 {
+        self.regions.rect().size()
+    }
+
+    pub fn region(&self, coord: IVec2) -> Option<&Region> {
+        self.regions.get(coord).ok()
+    }
+
+    /// The region containing `tile`.
+    pub fn region_for_tile(&self, tile: IVec2) -> IVec2 {
+        let size = self.region_size.as_ivec2();
+        IVec2::new(tile.x.div_euclid(size.x), tile.y.div_euclid(size.y))
+    }
+
+    /// The tiles of the local map generated for `region`.
+    pub fn local_map_rect(&self, region: IVec2) -> Rect2D {
+        let min = region * self.region_size.as_ivec2();
+        Rect2D::from_corners(min, min + self.region_size.as_ivec2())
+    }
+}
+
+fn summarize_region(
+    noise: &TerrainNoise,
+    table: &BiomeTable,
+    rect: Rect2D,
+    samples: u32,
+) -> Region {
+    let samples = samples.max(1) as i32;
+    let step = (rect.size() / samples).max(IVec2::ONE);
+    let mut counts = [0u32; Biome::COUNT];
+    let mut elevation = 0.0;
+    for y in 0..samples {
+        for x in 0..samples {
+            let sample = noise.sample(rect.min + IVec2::new(x, y) * step + step / 2);
+            counts[sample.biome(table) as usize] += 1;
+            elevation += sample.elevation;
+        }
+    }
+    let dominant = counts
+        .iter()
+        .enumerate()
+        .max_by_key(|(_, n)| **n)
+        .and_then(|(biome, _)| num_traits::FromPrimitive::from_usize(biome))
+        .unwrap_or(Biome::Null);
+    Region {
+        biome: dominant,
+        elevation: elevation / (samples * samples) as f32,
+        river: false,
+        civilization: None,
+    }
+}
+
+fn neighbors(point: IVec2) -> impl Iterator<Item = IVec2> {
+    (-1..=1)
+        .flat_map(move |y| (-1..=1).map(move |x| point + IVec2::new(x, y)))
+        .filter(move |p| *p != point)
+}
+
+/// Run rivers downhill from random highland regions until they reach the ocean
+/// or get stuck in a basin.
+fn trace_rivers(regions: &mut Grid2D<Region>, count: usize, rng: &fastrand::Rng) {
+    let mut land: Vec<(IVec2, f32)> = regions
+        .iter()
+        .filter(|(_, r)| !matches!(r.biome, Biome::Ocean | Biome::Sand))
+        .map(|(p, r)| (p, r.elevation))
+        .collect();
+    land.sort_by(|l, r| r.1.total_cmp(&l.1));
+    // Only start rivers in the upper half of the land.
+    let sources = &land[..land.len() / 2];
+    if sources.is_empty() {
+        return;
+    }
+    for _ in 0..count {
+        let mut cur = sources[rng.usize(..sources.len())].0;
+        loop {
+            let region = regions.get_mut(cur).unwrap();
+            if region.biome == Biome::Ocean || region.river {
+                break;
+            }
+            region.river = true;
+            let elevation = region.elevation;
+            let lowest = neighbors(cur)
+                .filter_map(|p| Some((p, regions.get(p).ok()?.elevation)))
+                .filter(|(_, e)| *e < elevation)
+                .min_by(|l, r| l.1.total_cmp(&r.1));
+            match lowest {
+                Some((next, _)) => cur = next,
+                None => break,
+            }
+        }
+    }
+}
+
+fn settle_civilizations(regions: &mut Grid2D<Region>, count: usize, rng: &fastrand::Rng) {
+    let mut candidates: Vec<IVec2> = regions
+        .iter()
+        .filter(|(_, r)| !matches!(r.biome, Biome::Ocean | Biome::Mountain))
+        .map(|(p, _)| p)
+        .collect();
+    rng.shuffle(&mut candidates);
+    let mut settled: Vec<IVec2> = vec![];
+    for candidate in candidates {
+        if settled.len() >= count {
+            break;
+        }
+        let too_close = settled.iter().any(|s| {
+            let d = (*s - candidate).abs();
+            d.x.max(d.y) < CIVILIZATION_SPACING
+        });
+        if !too_close {
+            regions.get_mut(candidate).unwrap().civilization = Some(settled.len());
+            settled.push(candidate);
+        }
+    }
+}
+
+fn sys_generate_world_map(
+    mut cmd: Commands,
+    settings: Res<WorldMapSettings>,
+    terrain: Res<TerrainSettings>,
+    rng: Res<SimRng>,
+) {
+    let world = WorldMap::generate(&settings, &terrain, &rng);
+    log::info!("Generated world map of {} regions", world.size());
+    cmd.insert_resource(world);
+}
+
+#[derive(Resource, Debug, Default)]
+struct WorldMapView {
+    /// Region highlighted while the world map has [`InputFocus`].
+    selected: IVec2,
+}
+
+#[derive(Component, Debug, Default)]
+struct WorldMapPanel;
+
+impl Region {
+    fn texture(&self, tiles: &TileTable) -> CharTexture {
+        if self.civilization.is_some() {
+            CharTexture::new('⌂', Color::RED)
+        } else if self.river {
+            CharTexture::new('≈', RGB::new_f32(0.3, 0.6, 1.0))
+        } else {
+            tiles.texture(self.biome)
+        }
+    }
+
+    fn describe(&self) -> String {
+        let mut text = format!("{:?} elevation {:.2}", self.biome, self.elevation);
+        if self.river {
+            text.push_str(" river");
+        }
+        if let Some(civ) = self.civilization {
+            text.push_str(&format!(" civilization #{}", civ));
+        }
+        text
+    }
+}
+
+/// Offset of the first region shown along an axis, keeping `selected` in view
+/// and centering the world if it fits.
+fn scroll_offset(world: i32, view: i32, selected: i32) -> i32 {
+    if world <= view {
+        -(view - world) / 2
+    } else {
+        (selected - view / 2).clamp(0, world - view)
+    }
+}
+
+fn sys_update_world_map_panel(
+    world: Option<Res<WorldMap>>,
+    view: Res<WorldMapView>,
+    map: Res<MapSettings>,
+    camera: Res<TerminalCamera2D>,
+    tiles: Res<TileTable>,
+    mut panel: Query<(&mut CharMesh, &mut Transform2D), With<WorldMapPanel>>,
+) {
+    let (Some(world), Ok((mut mesh, mut transform))) = (world, panel.get_single_mut()) else {
+        return;
+    };
+    // Drawn just inside the camera frame.
+    let dim = camera.dim().as_ivec2() - 2;
+    if dim.x <= 0 || dim.y < 2 {
+        return;
+    }
+    // The last row describes the selected region.
+    let rows = dim.y - 1;
+    let offset = IVec2::new(
+        scroll_offset(world.size().x, dim.x, view.selected.x),
+        scroll_offset(world.size().y, rows, view.selected.y),
+    );
+    let current = world.region_for_tile(map.origin);
+    let current = (world.local_map_rect(current) == map.rect()).then_some(current);
+
+    let mut textures = Vec::with_capacity((dim.x * dim.y) as usize);
+    for y in 0..rows {
+        for x in 0..dim.x {
+            let coord = offset + IVec2::new(x, y);
+            let texture = match world.region(coord) {
+                Some(_) if coord == view.selected => CharTexture::new('X', Color::YELLOW),
+                Some(_) if Some(coord) == current => CharTexture::new('@', Color::WHITE),
+                Some(region) => region.texture(&tiles),
+                None => default(),
+            };
+            textures.push(texture);
+        }
+    }
+    let mut info = CharMesh {
+        texture_vec: vec![default(); dim.x as usize],
+    };
+    let description = world
+        .region(view.selected)
+        .map(|r| r.describe())
+        .unwrap_or_default();
+    info.write_row(
+        dim.x as u32,
+        0,
+        &format!(
+            " ({},{}) {} | Enter: embark  V/Esc: back",
+            view.selected.x, view.selected.y, description
+        ),
+        None,
+    );
+    textures.extend(info.texture_vec);
+
+    if transform.scale != dim.as_uvec2() {
+        transform.scale = dim.as_uvec2();
+    }
+    if mesh.texture_vec != textures {
+        mesh.texture_vec = textures;
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn handle_world_map_keys(
+    mut cmd: Commands,
+    mut input: EventReader<KeyboardInput>,
+    mut focus: ResMut<InputFocus>,
+    mut view: ResMut<WorldMapView>,
+    world: Option<Res<WorldMap>>,
+    mut map: ResMut<MapSettings>,
+    mut camera: ResMut<TerminalCamera2D>,
+    mut cursor: Query<&mut TileCursor>,
+    panels: Query<Entity, With<WorldMapPanel>>,
+) {
+    let Some(world) = world else {
+        input.clear();
+        return;
+    };
+    let close = |cmd: &mut Commands, focus: &mut InputFocus| {
+        for entity in panels.iter() {
+            cmd.entity(entity).despawn();
+        }
+        *focus = InputFocus::World;
+    };
+    for e in input.iter() {
+        if e.state != ButtonState::Pressed {
+            continue;
+        }
+        let Some(key) = e.key_code else {
+            continue;
+        };
+        let step = match (*focus, key) {
+            (InputFocus::World, KeyCode::V) => {
+                *focus = InputFocus::WorldMap;
+                let center = tile_from_vec2(camera.transform().as_rect2d().center());
+                view.selected = world
+                    .region_for_tile(center)
+                    .clamp(IVec2::ZERO, world.size() - IVec2::ONE);
+                cmd.spawn((
+                    WorldMapPanel,
+                    CharMeshTransform::new(Transform2D::default()),
+                    UIComponent::new(Vec3::new(1.0, 1.0, WORLD_MAP_Z)),
+                ));
+                continue;
+            }
+            (InputFocus::WorldMap, KeyCode::V | KeyCode::Escape) => {
+                close(&mut cmd, &mut focus);
+                continue;
+            }
+            (InputFocus::WorldMap, KeyCode::Return) => {
+                let rect = world.local_map_rect(view.selected);
+                // Changing the map settings regenerates the local map.
+                if map.rect() != rect {
+                    log::info!("Embarking on region {}", view.selected);
+                    map.origin = rect.min;
+                    map.size = rect.size().as_uvec2();
+                }
+                let z = camera.loc().z;
+                *camera.loc_mut() = rect.min.as_vec2().extend(z);
+                if let Ok(mut cursor) = cursor.get_single_mut() {
+                    cursor.tile = rect.min + IVec2::ONE;
+                }
+                close(&mut cmd, &mut focus);
+                continue;
+            }
+            (InputFocus::WorldMap, KeyCode::Left) => IVec2::new(-1, 0),
+            (InputFocus::WorldMap, KeyCode::Right) => IVec2::new(1, 0),
+            (InputFocus::WorldMap, KeyCode::Up) => IVec2::new(0, -1),
+            (InputFocus::WorldMap, KeyCode::Down) => IVec2::new(0, 1),
+            _ => continue,
+        };
+        view.selected = (view.selected + step).clamp(IVec2::ZERO, world.size() - IVec2::ONE);
+    }
+}
@@ -5,6 +5,29 @@ pub struct CharMesh {
     pub texture_vec: Vec<CharTexture>,
 }
 
+impl CharMesh {
+    /// Resize the mesh to cover `scale` textures, blanking all of them.
+    pub fn resize(&mut self, scale: UVec2) {
+        self.texture_vec.clear();
+        self.texture_vec
+            .resize((scale.x * scale.y) as usize, default());
+    }
+
+    /// Write `text` into `row` of a mesh which is `width` textures wide,
+    /// blanking the rest of the row. Text past the end of the row is dropped.
+    pub fn write_row(&mut self, width: u32, row: u32, text: &str, rgb: Option<RGB>) {
+        let start = (row * width) as usize;
+        let mut chars = text.chars();
+        for texture in self.texture_vec[start..start + width as usize].iter_mut() {
+            *texture = CharTexture {
+                c: chars.next().unwrap_or(' '),
+                rgb,
+                bg: None,
+            };
+        }
+    }
+}
+
 #[derive(Bundle, Clone, Debug)]
 pub struct CharMeshTransform {
     mesh: CharMesh,
@@ -41,7 +64,7 @@ impl CharMeshTransform {
     /// Panics if the mesh does not cover the provided coordinates.
     pub fn get(&self, x: i32, y: i32) -> &CharTexture {
         // TODO: Release version with unsafe unwrap
-        return self
+        self
             .mesh
             .texture_vec
             .get(
@@ -50,13 +73,13 @@ impl CharMeshTransform {
                     .index_for_point(IVec2::new(x, y))
                     .unwrap(),
             )
-            .unwrap();
+            .unwrap()
     }
 
     /// Mutable verison of [`get`].
     pub fn get_mut(&mut self, x: i32, y: i32) -> &mut CharTexture {
         // TODO: Release version with unsafe unwrap
-        return self
+        self
             .mesh
             .texture_vec
             .get_mut(
@@ -65,7 +88,7 @@ impl CharMeshTransform {
                     .index_for_point(IVec2::new(x, y))
                     .unwrap(),
             )
-            .unwrap();
+            .unwrap()
     }
 
     /// Return a reference to the given texture using coordinates local to the
@@ -74,7 +97,7 @@ impl CharMeshTransform {
     pub fn get_local(&self, x: usize, y: usize) -> &CharTexture {
         // TODO: Release version with unsafe unwrap
         let width = self.transform.scale.x;
-        return self.mesh.texture_vec.get(width as usize * x + y).unwrap();
+        self.mesh.texture_vec.get(width as usize * x + y).unwrap()
     }
 
     pub fn fill(&mut self, texture: &CharTexture) {
@@ -86,10 +109,10 @@ impl CharMeshTransform {
     pub fn get_local_mut(&mut self, x: usize, y: usize) -> &mut CharTexture {
         // TODO: Release version with unsafe unwrap
         let width = self.transform.scale.x;
-        return self
+        self
             .mesh
             .texture_vec
             .get_mut(width as usize * x + y)
-            .unwrap();
+            .unwrap()
     }
 }
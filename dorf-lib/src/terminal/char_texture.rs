@@ -6,14 +6,20 @@ pub struct CharTextureTransform {
     pub transform: Transform2D,
 }
 /// Simple texture on top of transform
-#[derive(Component, Debug, Clone, PartialEq)]
+#[derive(Component, Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct CharTexture {
     pub c: char,
     pub rgb: Option<RGB>,
+    /// Background color, the terminal's own if `None`.
+    pub bg: Option<RGB>,
 }
 impl Default for CharTexture {
     fn default() -> Self {
-        Self { c: ' ', rgb: None }
+        Self {
+            c: ' ',
+            rgb: None,
+            bg: None,
+        }
     }
 }
 
@@ -22,12 +28,26 @@ impl CharTexture {
         Self {
             c: texture,
             rgb: Some(rgb),
+            bg: None,
         }
     }
     pub fn from_char(texture: char) -> Self {
         Self {
             c: texture,
             rgb: None,
+            bg: None,
+        }
+    }
+    pub fn with_bg(mut self, bg: RGB) -> Self {
+        self.bg = Some(bg);
+        self
+    }
+    /// The same character, darkened by `factor` (0 being black).
+    pub fn dimmed(&self, factor: f32) -> Self {
+        Self {
+            c: self.c,
+            rgb: Some(self.rgb.unwrap_or(Color::WHITE).scaled(factor)),
+            bg: self.bg.map(|bg| bg.scaled(factor)),
         }
     }
 }
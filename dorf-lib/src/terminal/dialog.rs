@@ -0,0 +1,341 @@
+use std::collections::VecDeque;
+
+use bevy::input::ButtonState;
+
+use crate::prelude::*;
+
+use super::input::InputFocus;
+
+const OVERLAY_Z: f32 = 900.0;
+const DIALOG_Z: f32 = 901.0;
+const OVERLAY_RGB: RGB = RGB::new(60, 60, 60);
+
+/// Provides modal dialogs drawn on top of everything else. A dialog is opened
+/// by sending [`OpenDialog`], holds [`InputFocus`] until it is dismissed, and
+/// reports the user's choice with a [`DialogClosed`] event.
+#[derive(Default)]
+pub struct TerminalDialogPlugin {}
+
+impl Plugin for TerminalDialogPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DialogQueue>()
+            .add_event::<OpenDialog>()
+            .add_event::<DialogClosed>()
+            .add_system(handle_dialog_keys)
+            .add_system(sys_update_dialog.in_base_set(CoreSet::PostUpdate));
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DialogKind {
+    Ok,
+    OkCancel,
+    YesNo,
+    /// A list of options, answered with [`DialogResponse::Choice`].
+    Choice(Vec<String>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DialogResponse {
+    Ok,
+    Cancel,
+    Yes,
+    No,
+    /// Index into the options of a [`DialogKind::Choice`].
+    Choice(usize),
+}
+
+/// Event requesting a modal dialog. Only one dialog is shown at a time, any
+/// others are queued and shown in the order requested.
+#[derive(Debug, Clone)]
+pub struct OpenDialog {
+    /// Identifies the dialog in the matching [`DialogClosed`] event.
+    pub tag: &'static str,
+    pub title: String,
+    pub message: String,
+    pub kind: DialogKind,
+}
+
+/// Event sent when a dialog opened through [`OpenDialog`] is dismissed.
+#[derive(Debug, Clone)]
+pub struct DialogClosed {
+    pub tag: &'static str,
+    pub response: DialogResponse,
+}
+
+impl DialogKind {
+    fn options(&self) -> Vec<(String, DialogResponse)> {
+        let o = |text: &str, response| (text.to_string(), response);
+        match self {
+            DialogKind::Ok => vec![o("OK", DialogResponse::Ok)],
+            DialogKind::OkCancel => vec![
+                o("OK", DialogResponse::Ok),
+                o("Cancel", DialogResponse::Cancel),
+            ],
+            DialogKind::YesNo => vec![o("Yes", DialogResponse::Yes), o("No", DialogResponse::No)],
+            DialogKind::Choice(choices) => choices
+                .iter()
+                .enumerate()
+                .map(|(idx, text)| {
+                    (
+                        format!("{}. {}", idx + 1, text),
+                        DialogResponse::Choice(idx),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    /// The response used when the dialog is dismissed with escape.
+    fn escape_response(&self) -> DialogResponse {
+        match self {
+            DialogKind::Ok => DialogResponse::Ok,
+            DialogKind::YesNo => DialogResponse::No,
+            DialogKind::OkCancel | DialogKind::Choice(_) => DialogResponse::Cancel,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ActiveDialog {
+    request: OpenDialog,
+    selected: usize,
+    /// Set once the user answers, the dialog is torn down at the end of the frame.
+    response: Option<DialogResponse>,
+}
+
+#[derive(Resource, Debug, Default)]
+struct DialogQueue {
+    pending: VecDeque<OpenDialog>,
+    active: Option<ActiveDialog>,
+}
+
+#[derive(Component, Debug, Default)]
+struct DialogOverlay;
+
+#[derive(Component, Debug, Default)]
+struct DialogBox;
+
+fn digit_index(key: KeyCode) -> Option<usize> {
+    Some(match key {
+        KeyCode::Key1 => 0,
+        KeyCode::Key2 => 1,
+        KeyCode::Key3 => 2,
+        KeyCode::Key4 => 3,
+        KeyCode::Key5 => 4,
+        KeyCode::Key6 => 5,
+        KeyCode::Key7 => 6,
+        KeyCode::Key8 => 7,
+        KeyCode::Key9 => 8,
+        _ => return None,
+    })
+}
+
+fn handle_dialog_keys(
+    mut input: EventReader<KeyboardInput>,
+    focus: Res<InputFocus>,
+    mut queue: ResMut<DialogQueue>,
+) {
+    // Only act on input once the dialog has actually taken focus, otherwise we
+    // could act on the very key press that opened it.
+    let Some(active) = queue
+        .active
+        .as_mut()
+        .filter(|active| *focus == InputFocus::Dialog && active.response.is_none())
+    else {
+        input.clear();
+        return;
+    };
+
+    let options = active.request.kind.options();
+    for e in input.iter() {
+        if e.state != ButtonState::Pressed {
+            continue;
+        }
+        let Some(key) = e.key_code else {
+            continue;
+        };
+        match key {
+            KeyCode::Left | KeyCode::Up => {
+                active.selected = (active.selected + options.len() - 1) % options.len()
+            }
+            KeyCode::Right | KeyCode::Down | KeyCode::Tab => {
+                active.selected = (active.selected + 1) % options.len()
+            }
+            KeyCode::Return => active.response = Some(options[active.selected].1),
+            KeyCode::Escape => active.response = Some(active.request.kind.escape_response()),
+            KeyCode::Y if active.request.kind == DialogKind::YesNo => {
+                active.response = Some(DialogResponse::Yes)
+            }
+            KeyCode::N if active.request.kind == DialogKind::YesNo => {
+                active.response = Some(DialogResponse::No)
+            }
+            key => {
+                if let Some(idx) = digit_index(key).filter(|idx| *idx < options.len()) {
+                    active.response = Some(options[idx].1);
+                }
+            }
+        }
+        if active.response.is_some() {
+            break;
+        }
+    }
+}
+
+/// Lay out the dialog box, returning its size and textures.
+fn layout_dialog(active: &ActiveDialog) -> (UVec2, Vec<CharTexture>) {
+    let request = &active.request;
+    let label = |idx: usize, text: &str| {
+        if idx == active.selected {
+            (format!("[{}]", text), Some(Color::YELLOW))
+        } else {
+            (format!(" {} ", text), None)
+        }
+    };
+
+    // Each line of the body is a list of spans of text sharing a color.
+    let mut lines: Vec<Vec<(String, Option<RGB>)>> = vec![vec![]];
+    lines.extend(request.message.lines().map(|l| vec![(l.to_string(), None)]));
+    lines.push(vec![]);
+    let options = request.kind.options();
+    match request.kind {
+        DialogKind::Choice(_) => {
+            for (idx, (text, _)) in options.iter().enumerate() {
+                lines.push(vec![label(idx, text)]);
+            }
+        }
+        _ => lines.push(
+            options
+                .iter()
+                .enumerate()
+                .flat_map(|(idx, (text, _))| [label(idx, text), (" ".to_string(), None)])
+                .collect(),
+        ),
+    }
+    lines.push(vec![]);
+
+    let title = format!(" {} ", request.title);
+    let line_len = |line: &Vec<(String, Option<RGB>)>| -> usize {
+        line.iter().map(|(text, _)| text.chars().count()).sum()
+    };
+    let inner = lines
+        .iter()
+        .map(line_len)
+        .max()
+        .unwrap_or(0)
+        .max(title.chars().count() + 1);
+    // Border and a column of padding on each side.
+    let width = inner + 4;
+
+    let plain = |c| CharTexture::from_char(c);
+    let mut textures = Vec::with_capacity(width * (lines.len() + 2));
+    textures.push(plain('┌'));
+    textures.push(plain('─'));
+    textures.extend(title.chars().map(plain));
+    textures.extend((0..width - title.chars().count() - 3).map(|_| plain('─')));
+    textures.push(plain('┐'));
+    for line in lines.iter() {
+        textures.push(plain('│'));
+        textures.push(plain(' '));
+        for (text, rgb) in line.iter() {
+            textures.extend(text.chars().map(|c| CharTexture {
+                c,
+                rgb: *rgb,
+                bg: None,
+            }));
+        }
+        textures.extend((0..inner - line_len(line)).map(|_| plain(' ')));
+        textures.push(plain(' '));
+        textures.push(plain('│'));
+    }
+    textures.push(plain('└'));
+    textures.extend((0..width - 2).map(|_| plain('─')));
+    textures.push(plain('┘'));
+
+    (UVec2::new(width as u32, lines.len() as u32 + 2), textures)
+}
+
+fn spawn_dialog_widgets(cmd: &mut Commands) {
+    cmd.spawn((
+        DialogOverlay,
+        CharMeshTransform::new(Transform2D::default()),
+        UIComponent::new(Vec3::new(0.0, 0.0, OVERLAY_Z)),
+    ));
+    cmd.spawn((
+        DialogBox,
+        CharMeshTransform::new(Transform2D::default()),
+        UIComponent::new(Vec3::new(0.0, 0.0, DIALOG_Z)),
+    ));
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn sys_update_dialog(
+    mut cmd: Commands,
+    mut requests: EventReader<OpenDialog>,
+    mut closed: EventWriter<DialogClosed>,
+    mut queue: ResMut<DialogQueue>,
+    mut focus: ResMut<InputFocus>,
+    camera: Res<TerminalCamera2D>,
+    widgets: Query<Entity, Or<(With<DialogOverlay>, With<DialogBox>)>>,
+    mut overlay: Query<
+        (&mut CharMesh, &mut Transform2D),
+        (With<DialogOverlay>, Without<DialogBox>),
+    >,
+    mut dialog_box: Query<
+        (&mut CharMesh, &mut Transform2D, &mut UIComponent),
+        (With<DialogBox>, Without<DialogOverlay>),
+    >,
+) {
+    queue.pending.extend(requests.iter().cloned());
+
+    if let Some(response) = queue.active.as_ref().and_then(|a| a.response) {
+        let active = queue.active.take().unwrap();
+        closed.send(DialogClosed {
+            tag: active.request.tag,
+            response,
+        });
+        for entity in widgets.iter() {
+            cmd.entity(entity).despawn();
+        }
+        *focus = InputFocus::World;
+    }
+
+    let Some(active) = queue.active.as_ref() else {
+        if let Some(request) = queue.pending.pop_front() {
+            queue.active = Some(ActiveDialog {
+                request,
+                selected: 0,
+                response: None,
+            });
+            *focus = InputFocus::Dialog;
+            // Drawn on the next update once the widgets exist.
+            spawn_dialog_widgets(&mut cmd);
+        }
+        return;
+    };
+
+    if let Ok((mut mesh, mut transform)) = overlay.get_single_mut() {
+        if transform.scale != *camera.dim() {
+            transform.scale = *camera.dim();
+            mesh.texture_vec = vec![
+                CharTexture::new('░', OVERLAY_RGB);
+                (transform.scale.x * transform.scale.y) as usize
+            ];
+        }
+    }
+    if let Ok((mut mesh, mut transform, mut ui_component)) = dialog_box.get_single_mut() {
+        let (scale, textures) = layout_dialog(active);
+        let offset = (camera.dim().as_ivec2() - scale.as_ivec2()).max(IVec2::ZERO) / 2;
+        if mesh.texture_vec != textures {
+            mesh.texture_vec = textures;
+        }
+        if transform.scale != scale {
+            transform.scale = scale;
+        }
+        if ui_component.local_pos.xy() != offset.as_vec2() {
+            ui_component.local_pos = offset.as_vec2().extend(DIALOG_Z);
+            // Touch the transform so the UI layer repositions us.
+            transform.set_changed();
+        }
+    }
+}
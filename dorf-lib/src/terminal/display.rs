@@ -34,7 +34,7 @@ impl TerminalDisplayBuffer {
         let width = self.width as usize;
         #[cfg(debug_assertions)]
         {
-            return self.texture_vec.get_mut(x + y * width).unwrap();
+            self.texture_vec.get_mut(x + y * width).unwrap()
         }
         #[cfg(not(debug_assertions))]
         return unsafe { self.texture_vec.get_mut(x + y * width).unwrap_unchecked() };
@@ -92,12 +92,11 @@ impl std::ops::Deref for PhysicalDisplayBuffer {
 impl DisplayBuffer {
     fn init_from_screen() -> Self {
         let (width, height) = get_term_size();
-        let buf = DisplayBuffer {
+        DisplayBuffer {
             texture_vec: vec![default(); width as usize * height as usize],
             width,
             height,
-        };
-        buf
+        }
     }
 }
 
@@ -141,11 +140,18 @@ fn rgb_convert(ours: RGB) -> Color {
 fn write_texture(stdout: &mut StdoutLock, texture: &CharTexture) {
     let mut binding = [0; 4];
     let c = texture.c.encode_utf8(&mut binding);
+    if texture.rgb.is_none() && texture.bg.is_none() {
+        stdout.write_all(c.as_bytes());
+        return;
+    }
+    let mut styled = c.stylize();
     if let Some(rgb) = texture.rgb {
-        stdout.queue(style::PrintStyledContent(c.with(rgb_convert(rgb))));
-    } else {
-        stdout.write(c.as_bytes());
+        styled = styled.with(rgb_convert(rgb));
+    }
+    if let Some(bg) = texture.bg {
+        styled = styled.on(rgb_convert(bg));
     }
+    stdout.queue(style::PrintStyledContent(styled));
 }
 
 /// Handler for [`TerminalResize`] events, updates buffer sizes to match the new
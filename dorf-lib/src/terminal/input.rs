@@ -18,11 +18,28 @@ impl Plugin for TerminalInputPlugin {
     fn build(&self, app: &mut App) {
         app.add_event::<KeyboardInput>()
             .add_event::<TerminalResize>()
+            .init_resource::<InputFocus>()
             .add_system(handle_input_buffer)
             .add_startup_system(init);
     }
 }
 
+/// What keyboard input is currently directed at. Handlers interacting with
+/// the world should drop input while focus is elsewhere, e.g. on a dialog.
+///
+/// Widgets which take focus should only change it from
+/// [`CoreSet::PostUpdate`], so the key press which moved focus isn't seen by
+/// another handler later in the same frame.
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum InputFocus {
+    #[default]
+    World,
+    Dialog,
+    Minimap,
+    WorldMap,
+    MapDesigner,
+}
+
 #[derive(Default)]
 struct TerminalState {
     handle: Option<JoinHandle<()>>,
@@ -107,7 +124,7 @@ fn terminal_keycode_to_bevy(in_code: &CrosstermKeyCode) -> Option<KeyCode> {
         CrosstermKeyCode::BackTab => panic!(),
         CrosstermKeyCode::Delete => KeyCode::Delete,
         CrosstermKeyCode::Insert => KeyCode::Insert,
-        CrosstermKeyCode::F(u8) => todo!(),
+        CrosstermKeyCode::F(n) => function_key_to_bevy(*n)?,
         CrosstermKeyCode::Char(c) => charcode_to_bevy_key_code(*c),
         CrosstermKeyCode::Null => todo!(),
         CrosstermKeyCode::Esc => KeyCode::Escape,
@@ -123,6 +140,24 @@ fn terminal_keycode_to_bevy(in_code: &CrosstermKeyCode) -> Option<KeyCode> {
     })
 }
 
+fn function_key_to_bevy(n: u8) -> Option<KeyCode> {
+    Some(match n {
+        1 => KeyCode::F1,
+        2 => KeyCode::F2,
+        3 => KeyCode::F3,
+        4 => KeyCode::F4,
+        5 => KeyCode::F5,
+        6 => KeyCode::F6,
+        7 => KeyCode::F7,
+        8 => KeyCode::F8,
+        9 => KeyCode::F9,
+        10 => KeyCode::F10,
+        11 => KeyCode::F11,
+        12 => KeyCode::F12,
+        _ => return None,
+    })
+}
+
 fn charcode_to_bevy_key_code(c: char) -> KeyCode {
     match c {
         '1' => KeyCode::Key1,
@@ -135,6 +170,12 @@ fn charcode_to_bevy_key_code(c: char) -> KeyCode {
         '8' => KeyCode::Key8,
         '9' => KeyCode::Key9,
         '0' => KeyCode::Key0,
+        ' ' => KeyCode::Space,
+        '+' => KeyCode::Plus,
+        '=' => KeyCode::Equals,
+        '-' => KeyCode::Minus,
+        ',' | '<' => KeyCode::Comma,
+        '.' | '>' => KeyCode::Period,
         'A' => KeyCode::A,
         'B' => KeyCode::B,
         'C' => KeyCode::C,
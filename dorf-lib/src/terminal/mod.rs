@@ -1,6 +1,7 @@
 pub mod camera;
 pub mod char_mesh;
 pub mod char_texture;
+pub mod dialog;
 pub mod display;
 pub mod input;
 pub mod render;
@@ -9,6 +10,7 @@ use crate::prelude::*;
 pub use camera::*;
 pub use char_mesh::*;
 pub use char_texture::*;
+pub use dialog::*;
 pub use input::*;
 pub use render::*;
 
@@ -23,6 +25,7 @@ impl Plugin for TerminalPlugin {
         app.add_plugin(self::input::TerminalInputPlugin::default())
             .add_plugin(self::display::TerminalDisplayPlugin::default())
             .add_plugin(self::render::TerminalRenderPlugin::default())
-            .add_plugin(self::camera::TerminalCamera2dPlugin::default());
+            .add_plugin(self::camera::TerminalCamera2dPlugin::default())
+            .add_plugin(self::dialog::TerminalDialogPlugin::default());
     }
 }
@@ -22,6 +22,10 @@ impl Plugin for TerminalRenderPlugin {
     }
 }
 
+/// Entities with this component aren't rendered.
+#[derive(Component, Debug, Default)]
+pub struct Hidden;
+
 /// Local cache for the rendering function. Rather than needing to allocate a
 /// new Vec, each time keep one static.
 #[derive(Default, Debug)]
@@ -63,17 +67,22 @@ impl PartialEq for Renderables {
     }
 }
 
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
 fn render(
     mut local: Local<RenderCache>,
     changed: Query<(&CharTexture, &Transform2D), Or<(Changed<Transform2D>, Changed<CharTexture>)>>,
-    all_textures: Query<(&CharTexture, &Transform2D)>,
+    all_textures: Query<(&CharTexture, &Transform2D), Without<Hidden>>,
     changed_mesh: Query<(&CharMesh, &Transform2D), Or<(Changed<Transform2D>, Changed<CharMesh>)>>,
-    all_mesh: Query<(&CharMesh, &Transform2D)>,
+    all_mesh: Query<(&CharMesh, &Transform2D), Without<Hidden>>,
+    hidden: Query<(), Added<Hidden>>,
+    mut shown: RemovedComponents<Hidden>,
     camera: Res<TerminalCamera2D>,
     mut display_buf: ResMut<TerminalDisplayBuffer>,
 ) {
     if changed_mesh.is_empty()
         && changed.is_empty()
+        && hidden.is_empty()
+        && shown.iter().count() == 0
         && !display_buf.is_changed()
         && !camera.is_changed()
     {
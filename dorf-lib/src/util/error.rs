@@ -9,3 +9,14 @@ pub enum LightError {
     #[error("Terminated loop early")]
     TerminateEarly,
 }
+
+/// A grid whose values don't cover its area, e.g. one read from a corrupt
+/// file.
+#[derive(Debug, Error, PartialEq)]
+pub enum GridError {
+    #[error("grid rect {0:?} has its corners swapped")]
+    InvertedRect(crate::util::Rect2D),
+
+    #[error("grid has {found} values, expected {expected} to cover it")]
+    WrongLength { expected: usize, found: usize },
+}
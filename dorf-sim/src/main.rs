@@ -1,5 +1,120 @@
 //use dorf_lib;
+use std::{fmt::Display, str::FromStr};
+
+use dorf_lib::{
+    prelude::{IVec2, UVec2},
+    AppSettings,
+};
+
+const USAGE: &str =
+    "usage: dorf-sim [--seed <u64>] [--map-size <W>x<H>] [--map-origin <X>,<Y>] [--data-dir <DIR>] [--map-image <PNG> | --map-file <RON> | --load <SAVE>]";
+
+/// Split `--flag=value` into its parts, otherwise take the value from the next argument.
+fn flag_value(arg: &str, args: &mut impl Iterator<Item = String>) -> (String, Option<String>) {
+    match arg.split_once('=') {
+        Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+        None => (arg.to_string(), args.next()),
+    }
+}
+
+fn parse_value<T>(flag: &str, value: Option<String>) -> Result<T, String>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    let value = value.ok_or_else(|| format!("{} requires a value", flag))?;
+    value
+        .parse()
+        .map_err(|e| format!("invalid {} {:?}: {}", flag, value, e))
+}
+
+/// Parse a pair of values separated by `sep`, e.g. `256x256`.
+fn parse_pair<T>(flag: &str, value: Option<String>, sep: char) -> Result<(T, T), String>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    let value: String = parse_value(flag, value)?;
+    let (l, r) = value.split_once(sep).ok_or_else(|| {
+        format!(
+            "invalid {} {:?}: expected two values split by '{}'",
+            flag, value, sep
+        )
+    })?;
+    Ok((
+        parse_value(flag, Some(l.to_string()))?,
+        parse_value(flag, Some(r.to_string()))?,
+    ))
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<AppSettings, String> {
+    let mut settings = AppSettings::default();
+    while let Some(arg) = args.next() {
+        let (flag, value) = flag_value(&arg, &mut args);
+        match flag.as_str() {
+            "--seed" => settings.seed = Some(parse_value(&flag, value)?),
+            "--map-size" => {
+                let (x, y) = parse_pair(&flag, value, 'x')?;
+                if x == 0 || y == 0 {
+                    return Err(format!("invalid {} {}x{}: can't be empty", flag, x, y));
+                }
+                settings.map.size = UVec2::new(x, y);
+            }
+            "--map-origin" => {
+                let (x, y) = parse_pair(&flag, value, ',')?;
+                settings.map.origin = IVec2::new(x, y);
+            }
+            "--data-dir" => settings.data_dir = Some(parse_value(&flag, value)?),
+            "--map-image" => settings.map_image = Some(parse_value(&flag, value)?),
+            "--map-file" => settings.map_file = Some(parse_value(&flag, value)?),
+            "--load" => settings.load = Some(parse_value(&flag, value)?),
+            _ => return Err(format!("unknown argument {:?}", arg)),
+        }
+    }
+    if settings.map_image.is_some() && settings.map_file.is_some() {
+        return Err("--map-image and --map-file can't be used together".to_string());
+    }
+    // The save has its own map, which would be spawned over the other.
+    if settings.load.is_some() && (settings.map_image.is_some() || settings.map_file.is_some()) {
+        return Err("--load can't be used with --map-image or --map-file".to_string());
+    }
+    Ok(settings)
+}
 
 fn main() {
-    dorf_lib::app_main();
+    let settings = match parse_args(std::env::args().skip(1)) {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
+    if let Err(e) = dorf_lib::app_main(settings) {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<AppSettings, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn map_size() {
+        let settings = parse(&["--map-size", "64x32"]).unwrap();
+        assert_eq!(settings.map.size, UVec2::new(64, 32));
+        assert!(parse(&["--map-size=0x32"]).is_err());
+        assert!(parse(&["--map-size=64x0"]).is_err());
+    }
+
+    #[test]
+    fn load_excludes_map() {
+        assert!(parse(&["--load", "save.ron"]).is_ok());
+        assert!(parse(&["--load", "save.ron", "--map-file", "map.ron"]).is_err());
+        assert!(parse(&["--map-image=map.png", "--load=save.ron"]).is_err());
+    }
 }